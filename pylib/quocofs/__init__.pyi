@@ -3,6 +3,7 @@ from abc import ABC, abstractmethod
 
 import typing
 from .hashes import *
+from .names import *
 
 # TODO(vinhowe): This should be auto-generated by PyO3 in the future.
 # Currently there doesn't seem to be support for stub files with type hints: https://github.com/PyO3/pyo3/issues/510
@@ -17,30 +18,129 @@ UUID_LENGTH = 16
 
 class Session:
     def __new__(
-        cls, path: str, key: bytes, remote: typing.Optional[RemoteAccessorConfig]
+        cls,
+        path: str,
+        key: bytes,
+        remotes: typing.Optional[Remotes] = None,
+        dedupe: bool = False,
+        chunked: bool = False,
     ):
+        """Open a session on the vault at ``path``.
+
+        :param path: A directory, or a URL like ``"file:///srv/vault"``, ``"mem://"`` or
+            ``"s3://bucket/vault?region=eu-west-1"`` with options as query parameters
+
+        :param remotes: A single remote, which is named ``"default"``, a dict of remotes by name,
+            or a list of ``(name, remote)`` tuples
+
+        :param dedupe: Make objects with identical contents share storage in the local vault,
+            like the ``dedupe=1`` URL parameter
+
+        :param chunked: Store objects in the local vault as chunks, so modifying part of a large
+            object only writes the chunks that changed, like the ``chunked=1`` URL parameter
+        """
+        pass
+    @staticmethod
+    def in_memory(key: bytes, remotes: typing.Optional[Remotes] = None) -> Session:
+        """Open a session whose local vault only exists in memory and is discarded on exit."""
+        pass
+    @staticmethod
+    def local_only(
+        path: str, key: bytes, dedupe: bool = False, chunked: bool = False
+    ) -> Session:
+        """Open a session on the vault at ``path`` that never syncs with remotes. Remote
+        operations raise ``RemoteNotFound``, and pushing or pulling all remotes does nothing."""
+        pass
+    @staticmethod
+    def remote_only(remote: RemoteAccessorConfig, key: bytes) -> Session:
+        """Open a session that reads and writes ``remote`` directly, without a local vault."""
         pass
     def object(self, id: bytes) -> bytes:
         pass
+    def object_range(self, id: bytes, offset: int, length: int) -> bytes:
+        """Read up to ``length`` bytes of an object starting at ``offset``.
+
+        Nothing past the end of the range is read, but everything before ``offset`` still is, so
+        this is only faster than ``object`` for ranges near the start of an object.
+        """
+        pass
     def create_object(self, data: bytes) -> bytes:
         pass
     def modify_object(self, id: bytes, data: bytes) -> None:
         pass
+    def create_object_from_file(self, file: typing.BinaryIO) -> bytes:
+        """Create an object from a binary file-like object, such as a socket or a subprocess's
+        stdout, reading it in chunks instead of all at once."""
+        pass
+    def modify_object_from_file(self, id: bytes, file: typing.BinaryIO) -> None:
+        pass
     def delete_object(self, id: bytes) -> None:
         pass
     def object_id_with_name(self, name: str) -> typing.Optional[bytes]:
         pass
     def set_object_name(self, id: bytes, name: str) -> None:
         pass
+    def rename_object(self, id: bytes, name: str) -> None:
+        """Rename an object, raising ``NameTaken`` if another object already has the name."""
+        pass
+    def copy_object(self, id: bytes) -> bytes:
+        """Copy an object's data to a new, unnamed object and return the new object's ID."""
+        pass
+    def apply_batch(
+        self, operations: typing.List[BatchOperation], parallelism: typing.Optional[int] = None
+    ) -> typing.List[bytes]:
+        """Apply several changes at once, saving names and hashes only at the end. If any change
+        fails, none of them take effect.
+
+        :return: IDs of created objects, in order
+        """
+        pass
     def flush(self) -> None:
         pass
     def object_temp_file(self, id: bytes, ext: str) -> str:
         pass
     def clear_temp_files(self) -> None:
         pass
-    def push_remote(self) -> None:
+    def push_remote(self, name: typing.Optional[str] = None) -> None:
+        """Push to the remote called ``name``. Without a name, pushes to the only remote if there's
+        just one, and otherwise to the one called ``"default"``."""
+        pass
+    def pull_remote(self, name: typing.Optional[str] = None) -> None:
+        """Pull from the remote called ``name``, which defaults as for ``push_remote``."""
+        pass
+    def push_all(self) -> None:
+        """Push to every remote, raising ``RemoteSyncsFailed`` afterwards if any push failed."""
+        pass
+    def pull_all(self) -> None:
+        """Pull from every remote in name order, raising ``RemoteSyncsFailed`` afterwards if any
+        pull failed."""
         pass
-    def pull_remote(self) -> None:
+    def remote_names(self) -> typing.List[str]:
+        pass
+    def remote_status(self, name: str) -> RemoteStatus:
+        """Compare a remote with the local vault.
+
+        Timestamps are seconds since the epoch, or ``None`` if the remote hasn't been pushed to or
+        pulled from during this session.
+        """
+        pass
+    def export_metadata_json(self) -> str:
+        pass
+    def import_metadata_json(self, json: str) -> None:
+        pass
+    def import_directory(self, path: str) -> typing.Tuple[int, int, int, typing.List[str]]:
+        """Create or update an object for every file under ``path``, named by relative path.
+        Files whose paths aren't valid UTF-8 or are too long to be names are skipped.
+
+        :return: Number of objects created, modified and left unchanged, and paths of skipped files
+        """
+        pass
+    def export_directory(self, path: str) -> typing.Tuple[int, int, typing.List[str]]:
+        """Write every named object to its relative path under ``path``. Symlinks are never
+        followed, and objects whose names can't be written as paths under ``path`` are skipped.
+
+        :return: Number of files written and left unchanged, and the names of skipped objects
+        """
         pass
     def __enter__(self):
         pass
@@ -53,12 +153,122 @@ class RemoteAccessorConfig(ABC):
     def create(self) -> RemoteAccessorConfig:
         pass
 
+class FilesystemAccessorConfig(RemoteAccessorConfig):
+    def __new__(cls, path: str):
+        """Configure another local directory, like an external drive or network mount, as a remote."""
+        pass
+    def create(self) -> FilesystemAccessorConfig:
+        pass
+
+class MemoryAccessorConfig(RemoteAccessorConfig):
+    def __new__(cls):
+        """Configure a remote that only exists in memory for the lifetime of the session."""
+        pass
+    def create(self) -> MemoryAccessorConfig:
+        pass
+
 class GoogleStorageAccessorConfig(RemoteAccessorConfig):
-    def __new__(cls, bucket: str, config_path: str):
+    def __new__(
+        cls,
+        bucket: str,
+        config_path: typing.Optional[str] = None,
+        prefix: str = "",
+        credentials_json: typing.Optional[str] = None,
+        token_provider: typing.Optional[typing.Callable[[], str]] = None,
+        endpoint: typing.Optional[str] = None,
+    ):
+        """Configure a Google Cloud Storage bucket as a remote.
+
+        Give at most one of ``config_path``, ``credentials_json`` and ``token_provider``. Without
+        any, requests are sent unauthenticated, which only works against emulators.
+
+        :param config_path: Path to a service account key file
+        :param prefix: Key prefix the vault is stored under, so a bucket can hold several vaults
+        :param credentials_json: Contents of a service account key file
+        :param token_provider: Called for an OAuth 2.0 access token before every request
+        :param endpoint: URL of an emulator like fake-gcs-server. Google is used when omitted.
+        """
         pass
     def create(self) -> GoogleStorageAccessorConfig:
         pass
 
+class S3AccessorConfig(RemoteAccessorConfig):
+    def __new__(
+        cls,
+        bucket: str,
+        region: str,
+        access_key_id: str,
+        secret_access_key: str,
+        endpoint: typing.Optional[str] = None,
+        prefix: str = "",
+        session_token: typing.Optional[str] = None,
+    ):
+        """Configure an S3 bucket as a remote.
+
+        :param endpoint: URL of an S3-compatible server like MinIO. AWS is used when omitted.
+        :param prefix: Key prefix the vault is stored under, so a bucket can hold several vaults
+        """
+        pass
+    def create(self) -> S3AccessorConfig:
+        pass
+
+class SftpAccessorConfig(RemoteAccessorConfig):
+    def __new__(
+        cls,
+        host: str,
+        username: str,
+        known_hosts: str,
+        path: str,
+        port: int = 22,
+        private_key: typing.Optional[str] = None,
+        passphrase: typing.Optional[str] = None,
+    ):
+        """Configure a directory on an SSH server as a remote.
+
+        :param known_hosts: OpenSSH known hosts file that must already contain the server's key
+        :param path: Directory on the server holding the vault
+        :param private_key: Private key file to authenticate with. An SSH agent is used when omitted.
+        """
+        pass
+    def create(self) -> SftpAccessorConfig:
+        pass
+
+class WebDavAccessorConfig(RemoteAccessorConfig):
+    def __new__(cls, url: str, username: str, password: str):
+        """Configure a WebDAV collection, like a Nextcloud or ownCloud folder, as a remote.
+
+        :param url: URL of the collection holding the vault
+        :param password: Password, or an app password for Nextcloud and ownCloud
+        """
+        pass
+    def create(self) -> WebDavAccessorConfig:
+        pass
+
+# A remote can also be given as a URL with a registered scheme, like "file:///mnt/backup"
+Remote = typing.Union[RemoteAccessorConfig, str]
+Remotes = typing.Union[
+    Remote,
+    typing.Dict[str, Remote],
+    typing.List[typing.Tuple[str, Remote]],
+]
+
+BatchOperation = typing.Union[
+    typing.Tuple[typing.Literal["create"], bytes, typing.Optional[str]],
+    typing.Tuple[typing.Literal["modify"], bytes, bytes],
+    typing.Tuple[typing.Literal["rename"], bytes, str],
+    typing.Tuple[typing.Literal["delete"], bytes],
+]
+
+# Returned as a plain dict
+class RemoteStatus(typing.TypedDict):
+    name: str
+    local_only: int
+    remote_only: int
+    modified: int
+    last_pushed: typing.Optional[float]
+    last_pulled: typing.Optional[float]
+    last_error: typing.Optional[str]
+
 class IoError(Exception):
     pass
 
@@ -77,9 +287,15 @@ class KeyGenerationError(Exception):
 class NameTooLong(Exception):
     pass
 
+class NameTaken(Exception):
+    pass
+
 class InvalidMagicBytes(Exception):
     pass
 
+class UnsupportedFormatVersion(Exception):
+    pass
+
 class EncryptionInputTooLong(Exception):
     pass
 
@@ -92,6 +308,9 @@ class SessionDisposed(Exception):
 class SessionPathLocked(Exception):
     pass
 
+class SessionNotFound(Exception):
+    pass
+
 class TempFileDeletesFailed(Exception):
     pass
 
@@ -101,9 +320,45 @@ class TempFileDeleteFailed(Exception):
 class NoRemotes(Exception):
     pass
 
+class RemoteNotFound(Exception):
+    pass
+
+class RemoteSyncsFailed(Exception):
+    pass
+
 class GoogleStorageError(Exception):
     pass
 
+class S3Error(Exception):
+    pass
+
+class WebDavError(Exception):
+    pass
+
+class InvalidUrl(Exception):
+    pass
+
+class UnsupportedScheme(Exception):
+    pass
+
+class HttpError(Exception):
+    pass
+
+class HostKeyRejected(Exception):
+    pass
+
+class SshError(Exception):
+    pass
+
+class JsonError(Exception):
+    pass
+
+class InvalidPath(Exception):
+    pass
+
+class InvalidArchive(Exception):
+    pass
+
 def dumps(obj: bytes, key: bytes) -> bytes:
     """Return compressed, encrypted quoco data from ``obj`` as a ``bytes`` object.
 
@@ -148,27 +403,48 @@ __all__ = [
     "UUID_LENGTH",
     "Session",
     "RemoteAccessorConfig",
+    "FilesystemAccessorConfig",
+    "MemoryAccessorConfig",
     "GoogleStorageAccessorConfig",
+    "S3AccessorConfig",
+    "SftpAccessorConfig",
+    "WebDavAccessorConfig",
     "IoError",
     "EncryptionError",
     "DecryptionError",
     "EmptyInput",
     "KeyGenerationError",
     "NameTooLong",
+    "NameTaken",
     "InvalidMagicBytes",
+    "UnsupportedFormatVersion",
     "EncryptionInputTooLong",
     "UndeterminedError",
     "SessionDisposed",
     "SessionPathLocked",
+    "SessionNotFound",
     "TempFileDeleteFailed",
     "TempFileDeletesFailed",
     "NoRemotes",
+    "RemoteNotFound",
+    "RemoteSyncsFailed",
     "GoogleStorageError",
+    "S3Error",
+    "WebDavError",
+    "InvalidUrl",
+    "UnsupportedScheme",
+    "HttpError",
+    "HostKeyRejected",
+    "SshError",
+    "JsonError",
+    "InvalidPath",
+    "InvalidArchive",
     "dumps",
     "loads",
     "key",
     "sha256",
     "hashes",
+    "names",
 ]
 
 __loader__ = None
@@ -0,0 +1,8 @@
+def loads(obj: bytes, key: bytes) -> dict[bytes, str]:
+    """Return names data from quocoed data ``obj`` as a ``dict`` object.
+
+    :param obj: Compressed/encrypted data
+    :param key: Encryption key of length KEY_LENGTH
+    :return: Names dictionary
+    """
+    pass
@@ -3,16 +3,23 @@ use pyo3::exceptions;
 use pyo3::prelude::*;
 use pyo3::types::{PyBytes, PyDict, PyType};
 use quocofs::error::QuocoError;
-use quocofs::formats::{Hashes, ReferenceFormat};
+use quocofs::formats::{Hashes, Names, ReferenceFormat};
 use quocofs::object::{
-    Finish, Key, ObjectId, ObjectSource, QuocoReader, QuocoWriter, RemoteSourceConfig,
-    CHUNK_LENGTH, HASH_LENGTH, KEY_LENGTH, MAX_DATA_LENGTH, MAX_NAME_LENGTH, SALT_LENGTH,
-    UUID_LENGTH,
+    Batch, Finish, GoogleCredentials, Key, ObjectId, ObjectSource, QuocoReader, QuocoWriter,
+    RemoteSourceConfig, S3Credentials, SshCredentials, WebDavCredentials, CHUNK_LENGTH,
+    HASH_LENGTH, KEY_LENGTH, MAX_DATA_LENGTH, MAX_NAME_LENGTH, SALT_LENGTH, UUID_LENGTH,
+};
+use quocofs::session::{
+    close_session, get_session, new_local_session_with_options, new_memory_session,
+    new_remote_session, new_session_with_options, RemoteStatus, SessionHandle, VaultOptions,
+    VaultSession,
 };
-use quocofs::session::{close_session, get_session, new_session};
 use quocofs::*;
 use std::io;
 use std::io::{BufReader, Cursor, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 create_exception!(module, IoError, exceptions::PyException);
 create_exception!(module, EncryptionError, exceptions::PyException);
@@ -20,15 +27,30 @@ create_exception!(module, DecryptionError, exceptions::PyException);
 create_exception!(module, EmptyInput, exceptions::PyException);
 create_exception!(module, KeyGenerationError, exceptions::PyException);
 create_exception!(module, NameTooLong, exceptions::PyException);
+create_exception!(module, NameTaken, exceptions::PyException);
 create_exception!(module, InvalidMagicBytes, exceptions::PyException);
+create_exception!(module, UnsupportedFormatVersion, exceptions::PyException);
 create_exception!(module, EncryptionInputTooLong, exceptions::PyException);
 create_exception!(module, UndeterminedError, exceptions::PyException);
 create_exception!(module, SessionDisposed, exceptions::PyException);
 create_exception!(module, SessionPathLocked, exceptions::PyException);
+create_exception!(module, SessionNotFound, exceptions::PyException);
 create_exception!(module, NoRemotes, exceptions::PyException);
+create_exception!(module, RemoteNotFound, exceptions::PyException);
+create_exception!(module, RemoteSyncsFailed, exceptions::PyException);
 create_exception!(module, TempFileDeleteFailed, exceptions::PyException);
 create_exception!(module, TempFileDeletesFailed, exceptions::PyException);
 create_exception!(module, GoogleStorageError, exceptions::PyException);
+create_exception!(module, S3Error, exceptions::PyException);
+create_exception!(module, WebDavError, exceptions::PyException);
+create_exception!(module, InvalidUrl, exceptions::PyException);
+create_exception!(module, UnsupportedScheme, exceptions::PyException);
+create_exception!(module, HttpError, exceptions::PyException);
+create_exception!(module, HostKeyRejected, exceptions::PyException);
+create_exception!(module, SshError, exceptions::PyException);
+create_exception!(module, JsonError, exceptions::PyException);
+create_exception!(module, InvalidPath, exceptions::PyException);
+create_exception!(module, InvalidArchive, exceptions::PyException);
 
 struct PyQuocoError(QuocoError);
 
@@ -41,19 +63,36 @@ impl From<PyQuocoError> for PyErr {
             QuocoError::EmptyInput => EmptyInput::new_err(err.0.to_string()),
             QuocoError::KeyGenerationError => KeyGenerationError::new_err(err.0.to_string()),
             QuocoError::NameTooLong(_) => NameTooLong::new_err(err.0.to_string()),
+            QuocoError::NameTaken(_) => NameTaken::new_err(err.0.to_string()),
             QuocoError::InvalidMagicBytes(_) => InvalidMagicBytes::new_err(err.0.to_string()),
+            QuocoError::UnsupportedFormatVersion(_, _) => {
+                UnsupportedFormatVersion::new_err(err.0.to_string())
+            }
             QuocoError::EncryptionInputTooLong(_) => {
                 EncryptionInputTooLong::new_err(err.0.to_string())
             }
             QuocoError::UndeterminedError => UndeterminedError::new_err(err.0.to_string()),
             QuocoError::SessionDisposed => SessionDisposed::new_err(err.0.to_string()),
             QuocoError::SessionPathLocked(_) => SessionPathLocked::new_err(err.0.to_string()),
+            QuocoError::SessionNotFound(_) => SessionNotFound::new_err(err.0.to_string()),
             QuocoError::NoRemotes => NoRemotes::new_err(err.0.to_string()),
+            QuocoError::RemoteNotFound(_) => RemoteNotFound::new_err(err.0.to_string()),
+            QuocoError::RemoteSyncsFailed(_) => RemoteSyncsFailed::new_err(err.0.to_string()),
             QuocoError::TempFileDeleteFailed(_) => TempFileDeleteFailed::new_err(err.0.to_string()),
             QuocoError::TempFileDeletesFailed(_) => {
                 TempFileDeletesFailed::new_err(err.0.to_string())
             }
             QuocoError::GoogleStorageError(_) => GoogleStorageError::new_err(err.0.to_string()),
+            QuocoError::S3Error(_) => S3Error::new_err(err.0.to_string()),
+            QuocoError::WebDavError(_) => WebDavError::new_err(err.0.to_string()),
+            QuocoError::InvalidUrl(_) => InvalidUrl::new_err(err.0.to_string()),
+            QuocoError::UnsupportedScheme(_) => UnsupportedScheme::new_err(err.0.to_string()),
+            QuocoError::HttpError(_) => HttpError::new_err(err.0.to_string()),
+            QuocoError::HostKeyRejected(_) => HostKeyRejected::new_err(err.0.to_string()),
+            QuocoError::SshError(_) => SshError::new_err(err.0.to_string()),
+            QuocoError::JsonError(_) => JsonError::new_err(err.0.to_string()),
+            QuocoError::InvalidPath(_) => InvalidPath::new_err(err.0.to_string()),
+            QuocoError::InvalidArchive(_) => InvalidArchive::new_err(err.0.to_string()),
         }
     }
 }
@@ -68,180 +107,695 @@ trait PyRemoteAccessConfigProvider {
     fn create(self) -> RemoteSourceConfig;
 }
 
+#[pyclass]
+#[derive(Clone)]
+struct FilesystemAccessorConfig {
+    path: String,
+}
+
+#[pymethods]
+impl FilesystemAccessorConfig {
+    #[new]
+    fn new(path: String) -> Self {
+        FilesystemAccessorConfig { path }
+    }
+}
+
+impl PyRemoteAccessConfigProvider for FilesystemAccessorConfig {
+    fn create(self) -> RemoteSourceConfig {
+        RemoteSourceConfig::Filesystem {
+            path: self.path.into(),
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct MemoryAccessorConfig {}
+
+#[pymethods]
+impl MemoryAccessorConfig {
+    #[new]
+    fn new() -> Self {
+        MemoryAccessorConfig {}
+    }
+}
+
+impl PyRemoteAccessConfigProvider for MemoryAccessorConfig {
+    fn create(self) -> RemoteSourceConfig {
+        RemoteSourceConfig::Memory
+    }
+}
+
 #[pyclass]
 #[derive(Clone)]
 struct GoogleStorageAccessorConfig {
     bucket: String,
-    config_path: String,
+    credentials: GoogleCredentials,
+    prefix: String,
+    endpoint: Option<String>,
 }
 
 #[pymethods]
 impl GoogleStorageAccessorConfig {
     #[new]
-    fn new(bucket: String, config_path: String) -> Self {
-        GoogleStorageAccessorConfig {
+    #[args(
+        config_path = "None",
+        prefix = "\"\"",
+        credentials_json = "None",
+        token_provider = "None",
+        endpoint = "None"
+    )]
+    fn new(
+        bucket: String,
+        config_path: Option<String>,
+        prefix: &str,
+        credentials_json: Option<String>,
+        token_provider: Option<PyObject>,
+        endpoint: Option<String>,
+    ) -> PyResult<Self> {
+        let credentials = match (config_path, credentials_json, token_provider) {
+            (Some(path), None, None) => GoogleCredentials::ServiceAccountFile(path.into()),
+            (None, Some(json), None) => GoogleCredentials::ServiceAccountJson(json),
+            (None, None, Some(provider)) => GoogleCredentials::TokenProvider(Arc::new(move || {
+                Python::with_gil(|py| provider.call0(py)?.extract::<String>(py)).map_err(|err| {
+                    QuocoError::GoogleStorageError(format!("Token provider failed: {}", err))
+                })
+            })),
+            (None, None, None) => GoogleCredentials::Anonymous,
+            _ => {
+                return Err(exceptions::PyValueError::new_err(
+                    "Only one of config_path, credentials_json and token_provider can be given",
+                ))
+            }
+        };
+
+        Ok(GoogleStorageAccessorConfig {
             bucket,
-            config_path,
-        }
+            credentials,
+            prefix: prefix.into(),
+            endpoint,
+        })
     }
 }
 
 impl PyRemoteAccessConfigProvider for GoogleStorageAccessorConfig {
     fn create(self) -> RemoteSourceConfig {
         RemoteSourceConfig::GoogleStorage {
+            endpoint: self.endpoint,
+            bucket: self.bucket,
+            prefix: self.prefix,
+            credentials: self.credentials,
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct S3AccessorConfig {
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    endpoint: Option<String>,
+    prefix: String,
+    session_token: Option<String>,
+}
+
+#[pymethods]
+impl S3AccessorConfig {
+    #[new]
+    #[args(endpoint = "None", prefix = "\"\"", session_token = "None")]
+    fn new(
+        bucket: String,
+        region: String,
+        access_key_id: String,
+        secret_access_key: String,
+        endpoint: Option<String>,
+        prefix: &str,
+        session_token: Option<String>,
+    ) -> Self {
+        S3AccessorConfig {
+            bucket,
+            region,
+            access_key_id,
+            secret_access_key,
+            endpoint,
+            prefix: prefix.into(),
+            session_token,
+        }
+    }
+}
+
+impl PyRemoteAccessConfigProvider for S3AccessorConfig {
+    fn create(self) -> RemoteSourceConfig {
+        RemoteSourceConfig::S3 {
+            endpoint: self.endpoint,
+            region: self.region,
             bucket: self.bucket,
-            config_path: self.config_path.into(),
+            prefix: self.prefix,
+            credentials: S3Credentials {
+                access_key_id: self.access_key_id,
+                secret_access_key: self.secret_access_key,
+                session_token: self.session_token,
+            },
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct SftpAccessorConfig {
+    host: String,
+    username: String,
+    known_hosts: String,
+    path: String,
+    port: u16,
+    private_key: Option<String>,
+    passphrase: Option<String>,
+}
+
+#[pymethods]
+impl SftpAccessorConfig {
+    #[new]
+    #[args(port = "22", private_key = "None", passphrase = "None")]
+    fn new(
+        host: String,
+        username: String,
+        known_hosts: String,
+        path: String,
+        port: u16,
+        private_key: Option<String>,
+        passphrase: Option<String>,
+    ) -> Self {
+        SftpAccessorConfig {
+            host,
+            username,
+            known_hosts,
+            path,
+            port,
+            private_key,
+            passphrase,
+        }
+    }
+}
+
+impl PyRemoteAccessConfigProvider for SftpAccessorConfig {
+    fn create(self) -> RemoteSourceConfig {
+        RemoteSourceConfig::Sftp {
+            host: self.host,
+            port: self.port,
+            known_hosts: self.known_hosts.into(),
+            credentials: SshCredentials {
+                username: self.username,
+                private_key: self.private_key.map(|path| path.into()),
+                passphrase: self.passphrase,
+            },
+            path: self.path.into(),
+        }
+    }
+}
+
+#[pyclass]
+#[derive(Clone)]
+struct WebDavAccessorConfig {
+    url: String,
+    username: String,
+    password: String,
+}
+
+#[pymethods]
+impl WebDavAccessorConfig {
+    #[new]
+    fn new(url: String, username: String, password: String) -> Self {
+        WebDavAccessorConfig {
+            url,
+            username,
+            password,
+        }
+    }
+}
+
+impl PyRemoteAccessConfigProvider for WebDavAccessorConfig {
+    fn create(self) -> RemoteSourceConfig {
+        RemoteSourceConfig::WebDav {
+            url: self.url,
+            credentials: WebDavCredentials {
+                username: self.username,
+                password: self.password,
+            },
+        }
+    }
+}
+
+fn extract_remote_config(config: &PyAny) -> PyResult<RemoteSourceConfig> {
+    if let Ok(url) = config.extract::<String>() {
+        return Ok(RemoteSourceConfig::Url(url));
+    }
+    if let Ok(config) = config.extract::<FilesystemAccessorConfig>() {
+        return Ok(config.create());
+    }
+    if let Ok(config) = config.extract::<MemoryAccessorConfig>() {
+        return Ok(config.create());
+    }
+    if let Ok(config) = config.extract::<GoogleStorageAccessorConfig>() {
+        return Ok(config.create());
+    }
+    if let Ok(config) = config.extract::<S3AccessorConfig>() {
+        return Ok(config.create());
+    }
+    if let Ok(config) = config.extract::<SftpAccessorConfig>() {
+        return Ok(config.create());
+    }
+    if let Ok(config) = config.extract::<WebDavAccessorConfig>() {
+        return Ok(config.create());
+    }
+    Err(exceptions::PyTypeError::new_err(
+        "remote must be a URL or a remote accessor config",
+    ))
+}
+
+/// What a single remote config is named.
+const DEFAULT_REMOTE_NAME: &str = "default";
+
+/// Remotes can be given as a single config, which is named "default", as a dict of configs by
+/// name, or as a list of (name, config) tuples.
+fn extract_remote_configs(remotes: Option<&PyAny>) -> PyResult<Vec<(String, RemoteSourceConfig)>> {
+    let remotes = match remotes {
+        Some(remotes) if !remotes.is_none() => remotes,
+        _ => return Ok(Vec::new()),
+    };
+    if let Ok(remotes) = remotes.downcast::<PyDict>() {
+        return remotes
+            .iter()
+            .map(|(name, config)| Ok((name.extract()?, extract_remote_config(config)?)))
+            .collect();
+    }
+    if let Ok(remotes) = remotes.extract::<Vec<(String, &PyAny)>>() {
+        return remotes
+            .into_iter()
+            .map(|(name, config)| Ok((name, extract_remote_config(config)?)))
+            .collect();
+    }
+    Ok(vec![(
+        DEFAULT_REMOTE_NAME.into(),
+        extract_remote_config(remotes)?,
+    )])
+}
+
+/// Builds a batch from `("create", data, name)`, `("modify", id, data)`, `("rename", id, name)`
+/// and `("delete", id)` tuples.
+fn extract_batch(operations: Vec<&PyAny>) -> PyResult<Batch> {
+    let mut batch = Batch::new();
+    for operation in operations {
+        let kind: &str = operation.get_item(0)?.extract()?;
+        match kind {
+            "create" => {
+                let (_, data, name): (&str, Vec<u8>, Option<&str>) = operation.extract()?;
+                batch.create(Cursor::new(data), name);
+            }
+            "modify" => {
+                let (_, id, data): (&str, ObjectId, Vec<u8>) = operation.extract()?;
+                batch.modify(&id, Cursor::new(data));
+            }
+            "rename" => {
+                let (_, id, name): (&str, ObjectId, &str) = operation.extract()?;
+                batch.rename(&id, name);
+            }
+            "delete" => {
+                let (_, id): (&str, ObjectId) = operation.extract()?;
+                batch.delete(&id);
+            }
+            _ => {
+                return Err(exceptions::PyValueError::new_err(format!(
+                    "Unknown batch operation {}",
+                    kind
+                )))
+            }
+        }
+    }
+    Ok(batch)
+}
+
+/// Copies everything from a binary file-like object into `writer`, a chunk at a time.
+fn copy_from_file(file: &PyAny, writer: &mut dyn io::Write) -> PyResult<()> {
+    loop {
+        let chunk = file.call_method1("read", (CHUNK_LENGTH,))?;
+        let chunk: &[u8] = chunk.downcast::<PyBytes>()?.as_bytes();
+        if chunk.is_empty() {
+            return Ok(());
         }
+        writer.write_all(chunk)?;
     }
 }
 
+fn system_time_to_timestamp(time: Option<SystemTime>) -> Option<f64> {
+    time.map(|t| t.duration_since(UNIX_EPOCH).unwrap().as_secs_f64())
+}
+
+fn remote_status_dict(py: Python, status: RemoteStatus) -> PyResult<&PyDict> {
+    let dict = PyDict::new(py);
+    dict.set_item("name", status.name)?;
+    dict.set_item("local_only", status.local_only)?;
+    dict.set_item("remote_only", status.remote_only)?;
+    dict.set_item("modified", status.modified)?;
+    dict.set_item("last_pushed", system_time_to_timestamp(status.last_pushed))?;
+    dict.set_item("last_pulled", system_time_to_timestamp(status.last_pulled))?;
+    dict.set_item("last_error", status.last_error)?;
+    Ok(dict)
+}
+
 #[pyclass(name = "Session")]
 struct PySession {
     id: UuidBytes,
 }
 
+impl PySession {
+    fn session(&self) -> PyResult<SessionHandle> {
+        Ok(get_session(&self.id).map_err(PyQuocoError)?)
+    }
+
+    /// Runs `f` on the session with the GIL released, so other Python threads keep running while
+    /// it waits on I/O or on the session's lock.
+    fn with_session<T, F>(&self, py: Python, f: F) -> PyResult<T>
+    where
+        T: Send,
+        F: FnOnce(&SessionHandle) -> Result<T, QuocoError> + Send,
+    {
+        let session = self.session()?;
+        Ok(py.allow_threads(|| f(&session)).map_err(PyQuocoError)?)
+    }
+
+    /// The remote to push to or pull from when none is named: the only remote if there's just
+    /// one, and otherwise the one a single remote config is registered as.
+    fn remote_name(&self, name: Option<&str>) -> PyResult<String> {
+        if let Some(name) = name {
+            return Ok(name.into());
+        }
+        let mut names = self.session()?.read().unwrap().remote_names();
+        Ok(match names.len() {
+            1 => names.remove(0),
+            _ => DEFAULT_REMOTE_NAME.into(),
+        })
+    }
+}
+
 #[pymethods]
 impl PySession {
     #[new]
-    fn new(path: &str, key: Key, remote: Option<&PyAny>) -> PyResult<Self> {
+    #[args(remotes = "None", dedupe = "false", chunked = "false")]
+    fn new(
+        path: &str,
+        key: Key,
+        remotes: Option<&PyAny>,
+        dedupe: bool,
+        chunked: bool,
+    ) -> PyResult<Self> {
+        let options = VaultOptions { dedupe, chunked };
         Ok(PySession {
-            id: new_session(
-                path,
-                &key,
-                remote
-                    .map(|c| {
-                        // TODO: Remove this once we add more remote providers
-                        #[allow(clippy::match_single_binding)]
-                        c.extract().map(|c| match c {
-                            GoogleStorageAccessorConfig { .. } => c.create(),
-                        } as RemoteSourceConfig)
-                    })
-                    .transpose()?,
-            )
-            .map_err(PyQuocoError)?,
+            id: new_session_with_options(path, &key, extract_remote_configs(remotes)?, &options)
+                .map_err(PyQuocoError)?,
+        })
+    }
+
+    /// Opens a session whose local vault only exists in memory.
+    #[staticmethod]
+    fn in_memory(key: Key, remotes: Option<&PyAny>) -> PyResult<Self> {
+        Ok(PySession {
+            id: new_memory_session(&key, extract_remote_configs(remotes)?).map_err(PyQuocoError)?,
+        })
+    }
+
+    /// Opens a session on a local vault that never syncs with remotes.
+    #[staticmethod]
+    #[args(dedupe = "false", chunked = "false")]
+    fn local_only(path: &str, key: Key, dedupe: bool, chunked: bool) -> PyResult<Self> {
+        let options = VaultOptions { dedupe, chunked };
+        Ok(PySession {
+            id: new_local_session_with_options(path, &key, &options).map_err(PyQuocoError)?,
+        })
+    }
+
+    /// Opens a session that works directly on a remote, without a local vault.
+    #[staticmethod]
+    fn remote_only(remote: &PyAny, key: Key) -> PyResult<Self> {
+        Ok(PySession {
+            id: new_remote_session(extract_remote_config(remote)?, &key).map_err(PyQuocoError)?,
         })
     }
 
-    fn __enter__(&mut self) -> PyResult<()> {
-        self.pull_remote()
+    fn __enter__(&mut self, py: Python) -> PyResult<()> {
+        self.pull_all(py)
     }
 
     fn __exit__(
         &mut self,
+        py: Python,
         _ty: Option<&PyType>,
         _value: Option<&PyAny>,
         _traceback: Option<&PyAny>,
     ) -> PyResult<bool> {
-        self.clear_temp_files()?;
-        self.flush()?;
-        self.push_remote()?;
+        self.clear_temp_files(py)?;
+        self.flush(py)?;
+        self.push_all(py)?;
         Ok(close_session(&self.id))
     }
 
     fn object<'p>(&self, py: Python<'p>, id: ObjectId) -> PyResult<&'p PyBytes> {
-        let mut object_data = Vec::new();
-        let mut object_reader = get_session(&self.id)
-            .borrow_mut()
-            .local
-            .object(&id)
-            .map_err(PyQuocoError)?;
-        object_reader.read_to_end(&mut object_data)?;
+        let object_data = self.with_session(py, |session| {
+            let mut object_data = Vec::new();
+            session
+                .read()
+                .unwrap()
+                .source()
+                .object(&id)?
+                .read_to_end(&mut object_data)?;
+            Ok(object_data)
+        })?;
+
+        Ok(PyBytes::new(py, &object_data))
+    }
+
+    fn object_range<'p>(
+        &self,
+        py: Python<'p>,
+        id: ObjectId,
+        offset: u64,
+        length: u64,
+    ) -> PyResult<&'p PyBytes> {
+        let object_data = self.with_session(py, |session| {
+            let mut object_data = Vec::new();
+            session
+                .read()
+                .unwrap()
+                .source()
+                .object_range(&id, offset, length)?
+                .read_to_end(&mut object_data)?;
+            Ok(object_data)
+        })?;
 
         Ok(PyBytes::new(py, &object_data))
     }
 
     fn create_object<'p>(&self, py: Python<'p>, data: Vec<u8>) -> PyResult<&'p PyBytes> {
-        let object_id = get_session(&self.id)
-            .borrow_mut()
-            .local
-            .create_object(&mut (Box::new(Cursor::new(data)) as Box<dyn ReadSeek>))
-            .map_err(PyQuocoError)?;
+        let object_id = self.with_session(py, |session| {
+            session
+                .write()
+                .unwrap()
+                .source_mut()
+                .create_object(&mut (Box::new(Cursor::new(data)) as Box<dyn ReadSeek>))
+        })?;
 
         Ok(PyBytes::new(py, &object_id))
     }
 
-    fn modify_object(&self, id: ObjectId, data: Vec<u8>) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .local
-            .modify_object(&id, &mut (Box::new(Cursor::new(data)) as Box<dyn ReadSeek>))
-            .map_err(PyQuocoError)?;
+    fn modify_object(&self, py: Python, id: ObjectId, data: Vec<u8>) -> PyResult<()> {
+        self.with_session(py, |session| {
+            session
+                .write()
+                .unwrap()
+                .source_mut()
+                .modify_object(&id, &mut (Box::new(Cursor::new(data)) as Box<dyn ReadSeek>))
+        })
+    }
 
-        Ok(())
+    /// Holds the GIL throughout, since the file is read through Python as the object is written.
+    fn create_object_from_file<'p>(&self, py: Python<'p>, file: &PyAny) -> PyResult<&'p PyBytes> {
+        let session = self.session()?;
+        let mut session = session.write().unwrap();
+        let mut writer = session.source_mut().begin_object().map_err(PyQuocoError)?;
+        copy_from_file(file, &mut writer)?;
+        let object_id = writer.commit().map_err(PyQuocoError)?;
+
+        Ok(PyBytes::new(py, &object_id))
     }
 
-    fn delete_object(&self, id: ObjectId) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .local
-            .delete_object(&id)
+    /// Holds the GIL throughout, like [`create_object_from_file`](Self::create_object_from_file).
+    fn modify_object_from_file(&self, id: ObjectId, file: &PyAny) -> PyResult<()> {
+        let session = self.session()?;
+        let mut session = session.write().unwrap();
+        let mut writer = session
+            .source_mut()
+            .begin_modify_object(&id)
             .map_err(PyQuocoError)?;
+        copy_from_file(file, &mut writer)?;
+        writer.commit().map_err(PyQuocoError)?;
 
         Ok(())
     }
 
+    fn delete_object(&self, py: Python, id: ObjectId) -> PyResult<()> {
+        self.with_session(py, |session| {
+            session.write().unwrap().source_mut().delete_object(&id)
+        })
+    }
+
     fn object_id_with_name(&self, name: &str) -> PyResult<Option<ObjectId>> {
-        Ok(get_session(&self.id)
-            .borrow()
-            .local
+        Ok(self
+            .session()?
+            .read()
+            .unwrap()
+            .source()
             .object_id_with_name(name)
             .map(|o| o.copied())
             .map_err(PyQuocoError)?)
     }
 
-    fn set_object_name(&self, id: ObjectId, name: &str) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .local
-            .set_object_name(&id, name)
-            .map_err(PyQuocoError)?;
+    fn set_object_name(&self, py: Python, id: ObjectId, name: &str) -> PyResult<()> {
+        self.with_session(py, |session| {
+            session
+                .write()
+                .unwrap()
+                .source_mut()
+                .set_object_name(&id, name)
+        })
+    }
 
-        Ok(())
+    fn rename_object(&self, py: Python, id: ObjectId, name: &str) -> PyResult<()> {
+        self.with_session(py, |session| {
+            session
+                .write()
+                .unwrap()
+                .source_mut()
+                .rename_object(&id, name)
+        })
     }
 
-    fn flush(&self) -> PyResult<()> {
-        Ok(get_session(&self.id)
-            .borrow_mut()
-            .local
-            .flush()
-            .map_err(PyQuocoError)?)
+    fn copy_object<'p>(&self, py: Python<'p>, id: ObjectId) -> PyResult<&'p PyBytes> {
+        let object_id = self.with_session(py, |session| {
+            session.write().unwrap().source_mut().copy_object(&id)
+        })?;
+
+        Ok(PyBytes::new(py, &object_id))
     }
 
-    fn object_temp_file(&self, id: ObjectId, ext: &str) -> PyResult<String> {
-        let path = get_session(&self.id)
-            .borrow_mut()
-            .object_temp_file(&id, ext)
-            .map(|path| path.to_str().unwrap().to_string())
-            .map_err(PyQuocoError)?;
+    #[args(parallelism = "None")]
+    fn apply_batch<'p>(
+        &self,
+        py: Python<'p>,
+        operations: Vec<&PyAny>,
+        parallelism: Option<usize>,
+    ) -> PyResult<Vec<&'p PyBytes>> {
+        let mut batch = extract_batch(operations)?;
+        if let Some(parallelism) = parallelism {
+            batch.set_parallelism(parallelism);
+        }
+        let object_ids = self.with_session(py, |session| {
+            session.write().unwrap().source_mut().apply_batch(batch)
+        })?;
 
-        Ok(path)
+        Ok(object_ids
+            .iter()
+            .map(|object_id| PyBytes::new(py, object_id))
+            .collect())
     }
 
-    fn clear_temp_files(&self) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .clear_temp_files()
-            .map_err(PyQuocoError)?;
+    fn flush(&self, py: Python) -> PyResult<()> {
+        self.with_session(py, |session| session.write().unwrap().source_mut().flush())
+    }
 
-        Ok(())
+    fn object_temp_file(&self, py: Python, id: ObjectId, ext: &str) -> PyResult<String> {
+        let path = self.with_session(py, |session| {
+            session.write().unwrap().object_temp_file(&id, ext)
+        })?;
+
+        Ok(path.to_str().unwrap().to_string())
     }
 
-    fn push_remote(&self) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .push_remote()
-            .map_err(PyQuocoError)?;
+    fn clear_temp_files(&self, py: Python) -> PyResult<()> {
+        self.with_session(py, |session| session.write().unwrap().clear_temp_files())
+    }
 
-        Ok(())
+    fn export_metadata_json(&self, py: Python) -> PyResult<String> {
+        let json = self.with_session(py, |session| {
+            let mut json = Vec::new();
+            session.read().unwrap().export_metadata_json(&mut json)?;
+            Ok(json)
+        })?;
+
+        Ok(String::from_utf8(json).unwrap())
     }
 
-    fn pull_remote(&self) -> PyResult<()> {
-        get_session(&self.id)
-            .borrow_mut()
-            .pull_remote()
-            .map_err(PyQuocoError)?;
+    fn import_metadata_json(&self, py: Python, json: &str) -> PyResult<()> {
+        self.with_session(py, |session| {
+            session
+                .write()
+                .unwrap()
+                .import_metadata_json(&mut json.as_bytes())
+        })
+    }
 
-        Ok(())
+    fn import_directory(
+        &self,
+        py: Python,
+        path: &str,
+    ) -> PyResult<(usize, usize, usize, Vec<String>)> {
+        let import = self.with_session(py, |session| {
+            session.write().unwrap().import_directory(Path::new(path))
+        })?;
+
+        let skipped = import.skipped.into_iter().map(|(path, _)| path).collect();
+        Ok((import.created, import.modified, import.unchanged, skipped))
+    }
+
+    fn export_directory(&self, py: Python, path: &str) -> PyResult<(usize, usize, Vec<String>)> {
+        let export = self.with_session(py, |session| {
+            session.write().unwrap().export_directory(Path::new(path))
+        })?;
+
+        let skipped = export.skipped.into_iter().map(|(name, _)| name).collect();
+        Ok((export.written, export.unchanged, skipped))
+    }
+
+    #[args(name = "None")]
+    fn push_remote(&self, py: Python, name: Option<&str>) -> PyResult<()> {
+        let name = self.remote_name(name)?;
+        self.with_session(py, |session| session.write().unwrap().push_remote(&name))
+    }
+
+    #[args(name = "None")]
+    fn pull_remote(&self, py: Python, name: Option<&str>) -> PyResult<()> {
+        let name = self.remote_name(name)?;
+        self.with_session(py, |session| session.write().unwrap().pull_remote(&name))
+    }
+
+    fn push_all(&self, py: Python) -> PyResult<()> {
+        self.with_session(py, |session| session.write().unwrap().push_all())
+    }
+
+    fn pull_all(&self, py: Python) -> PyResult<()> {
+        self.with_session(py, |session| session.write().unwrap().pull_all())
+    }
+
+    fn remote_names(&self) -> PyResult<Vec<String>> {
+        Ok(self.session()?.read().unwrap().remote_names())
+    }
+
+    fn remote_status<'p>(&self, py: Python<'p>, name: &str) -> PyResult<&'p PyDict> {
+        let status =
+            self.with_session(py, |session| session.read().unwrap().remote_status(name))?;
+
+        remote_status_dict(py, status)
     }
 }
 
@@ -266,6 +820,27 @@ fn init_hashes_module(_py: Python, _m: &PyModule) -> PyResult<()> {
     Ok(())
 }
 
+#[pymodule]
+fn init_names_module(_py: Python, _m: &PyModule) -> PyResult<()> {
+    #[pyfn(_m)]
+    #[pyo3(name = "loads")]
+    fn loads(py: Python, data: Vec<u8>, key: Key) -> PyResult<&PyDict> {
+        let mut names = Names::default();
+        let py_names = PyDict::new(py);
+        names
+            .load(&mut BufReader::new(QuocoReader::new(
+                Cursor::new(data),
+                &key,
+            )))
+            .map_err(PyQuocoError)?;
+        names
+            .iter()
+            .try_for_each(|(id, name)| py_names.set_item(PyBytes::new(py, id), name))?;
+        Ok(py_names)
+    }
+    Ok(())
+}
+
 #[pymodule]
 fn quocofs(_py: Python, _m: &PyModule) -> PyResult<()> {
     // Constants
@@ -284,7 +859,12 @@ fn quocofs(_py: Python, _m: &PyModule) -> PyResult<()> {
     _m.add("EmptyInput", _py.get_type::<EmptyInput>())?;
     _m.add("KeyGenerationError", _py.get_type::<KeyGenerationError>())?;
     _m.add("NameTooLong", _py.get_type::<NameTooLong>())?;
+    _m.add("NameTaken", _py.get_type::<NameTaken>())?;
     _m.add("InvalidMagicBytes", _py.get_type::<InvalidMagicBytes>())?;
+    _m.add(
+        "UnsupportedFormatVersion",
+        _py.get_type::<UnsupportedFormatVersion>(),
+    )?;
     _m.add(
         "EncryptionInputTooLong",
         _py.get_type::<EncryptionInputTooLong>(),
@@ -292,6 +872,7 @@ fn quocofs(_py: Python, _m: &PyModule) -> PyResult<()> {
     _m.add("UndeterminedError", _py.get_type::<UndeterminedError>())?;
     _m.add("SessionDisposed", _py.get_type::<SessionDisposed>())?;
     _m.add("SessionPathLocked", _py.get_type::<SessionPathLocked>())?;
+    _m.add("SessionNotFound", _py.get_type::<SessionNotFound>())?;
     _m.add(
         "TempFileDeleteFailed",
         _py.get_type::<TempFileDeleteFailed>(),
@@ -301,10 +882,27 @@ fn quocofs(_py: Python, _m: &PyModule) -> PyResult<()> {
         _py.get_type::<TempFileDeletesFailed>(),
     )?;
     _m.add("NoRemotes", _py.get_type::<NoRemotes>())?;
+    _m.add("RemoteNotFound", _py.get_type::<RemoteNotFound>())?;
+    _m.add("RemoteSyncsFailed", _py.get_type::<RemoteSyncsFailed>())?;
     _m.add("GoogleStorageError", _py.get_type::<GoogleStorageError>())?;
+    _m.add("S3Error", _py.get_type::<S3Error>())?;
+    _m.add("WebDavError", _py.get_type::<WebDavError>())?;
+    _m.add("InvalidUrl", _py.get_type::<InvalidUrl>())?;
+    _m.add("UnsupportedScheme", _py.get_type::<UnsupportedScheme>())?;
+    _m.add("HttpError", _py.get_type::<HttpError>())?;
+    _m.add("HostKeyRejected", _py.get_type::<HostKeyRejected>())?;
+    _m.add("SshError", _py.get_type::<SshError>())?;
+    _m.add("JsonError", _py.get_type::<JsonError>())?;
+    _m.add("InvalidPath", _py.get_type::<InvalidPath>())?;
+    _m.add("InvalidArchive", _py.get_type::<InvalidArchive>())?;
 
     // Classes
+    _m.add_class::<FilesystemAccessorConfig>()?;
+    _m.add_class::<MemoryAccessorConfig>()?;
     _m.add_class::<GoogleStorageAccessorConfig>()?;
+    _m.add_class::<S3AccessorConfig>()?;
+    _m.add_class::<SftpAccessorConfig>()?;
+    _m.add_class::<WebDavAccessorConfig>()?;
     _m.add_class::<PySession>()?;
 
     // Submodules
@@ -312,6 +910,10 @@ fn quocofs(_py: Python, _m: &PyModule) -> PyResult<()> {
     init_hashes_module(_py, hashes_module)?;
     _m.add_submodule(hashes_module)?;
 
+    let names_module = PyModule::new(_py, "names")?;
+    init_names_module(_py, names_module)?;
+    _m.add_submodule(names_module)?;
+
     #[pyfn(_m)]
     #[pyo3(name = "dumps")]
     fn dumps(py: Python, data: Vec<u8>, key: Key) -> PyResult<&PyBytes> {
@@ -0,0 +1,385 @@
+//! Single-file archives of a whole vault, for handing a vault to someone else or storing it
+//! offline. Everything but the framing is encrypted under a key chosen at export time, which
+//! doesn't have to be the key of the vault being exported.
+//!
+//! Layout (all integers little-endian):
+//!
+//! ```text
+//! header   "qarc" | version: u16
+//! entries  for each object: id: [u8; 16] | encrypted object data | length: u64
+//! index    length: u64 | encrypted index
+//! footer   index offset: u64 | SHA-256 of everything before the footer | "qarc"
+//! ```
+//!
+//! Object data is streamed into the archive as it's encrypted, so each entry's length follows its
+//! data instead of preceding it. The decrypted index holds the entry count followed by
+//! `id | offset | length` for every entry, then the vault's `names` and `hashes` reference
+//! formats, each prefixed with its length.
+
+use crate::error::QuocoError;
+use crate::formats::{Hashes, Names, ReferenceFormat};
+use crate::object::{
+    Batch, Finish, Key, ObjectId, ObjectSource, QuocoReader, QuocoWriter, HASH_LENGTH, UUID_LENGTH,
+};
+use crate::util::Sha256;
+use crate::Result;
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::io;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+
+pub const ARCHIVE_MAGIC_BYTES: &[u8] = b"qarc";
+pub const ARCHIVE_VERSION: u16 = 1;
+const FOOTER_LENGTH: usize = size_of::<u64>() + HASH_LENGTH + 4;
+const INDEX_ENTRY_LENGTH: usize = UUID_LENGTH + size_of::<u64>() * 2;
+
+/// What to do when an archived object's name is already used by a different object in the
+/// destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameConflict {
+    /// Leave the existing object alone and don't import the archived one.
+    Skip,
+    /// Import the archived object under the name and delete the existing object.
+    Overwrite,
+    /// Import the archived object alongside the existing one under a name like `name (1)`.
+    Rename,
+}
+
+#[derive(Debug, Default)]
+pub struct ArchiveImport {
+    /// IDs of imported objects in the destination source, which are always new.
+    pub imported: Vec<ObjectId>,
+    /// IDs (as stored in the archive) of objects that weren't imported because of a name conflict.
+    pub skipped: Vec<ObjectId>,
+}
+
+struct IndexEntry {
+    id: ObjectId,
+    offset: u64,
+    length: u64,
+}
+
+/// Keeps a running hash and position of everything written through it.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    position: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written])?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes every object in `source`, along with its names and hashes, to `writer` as an archive
+/// encrypted with `key`.
+pub fn export_archive<W: Write>(source: &mut dyn ObjectSource, writer: W, key: &Key) -> Result<()> {
+    let mut writer = HashingWriter {
+        inner: writer,
+        hasher: Sha256::new()?,
+        position: 0,
+    };
+    writer.write_all(ARCHIVE_MAGIC_BYTES)?;
+    writer.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+
+    let ids: Vec<ObjectId> = source.hashes().get_ids().copied().collect();
+    let mut entries = Vec::with_capacity(ids.len());
+    for id in ids.iter() {
+        writer.write_all(id)?;
+        let offset = writer.position;
+        let mut object_writer = QuocoWriter::new(&mut writer, key);
+        io::copy(&mut source.object(id)?, &mut object_writer)?;
+        object_writer.finish()?;
+
+        let length = writer.position - offset;
+        writer.write_all(&length.to_le_bytes())?;
+        entries.push(IndexEntry {
+            id: *id,
+            offset,
+            length,
+        });
+    }
+
+    let index_offset = writer.position;
+    let index = encrypt_index(&entries, source.names(), source.hashes(), key)?;
+    writer.write_all(&(index.len() as u64).to_le_bytes())?;
+    writer.write_all(&index)?;
+
+    let HashingWriter {
+        mut inner, hasher, ..
+    } = writer;
+    inner.write_all(&index_offset.to_le_bytes())?;
+    inner.write_all(&hasher.finish()?)?;
+    inner.write_all(ARCHIVE_MAGIC_BYTES)?;
+    inner.flush()?;
+
+    Ok(())
+}
+
+/// Imports every object in an archive encrypted with `key` into `destination` under new IDs,
+/// then flushes `destination`. The archive's checksum is verified before anything is written.
+///
+/// Objects are first imported without names and checked against their archived hashes. Names are
+/// then given out, and overwritten objects deleted, in a single [`Batch`] once every object has
+/// been imported, so an import that fails partway through leaves `destination`'s names and
+/// objects as they were.
+pub fn import_archive<R: Read + Seek>(
+    reader: &mut R,
+    key: &Key,
+    destination: &mut dyn ObjectSource,
+    on_conflict: NameConflict,
+) -> Result<ArchiveImport> {
+    let index_offset = verify_archive(reader)?;
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut magic_bytes = [0u8; 4];
+    reader.read_exact(&mut magic_bytes)?;
+    if magic_bytes != ARCHIVE_MAGIC_BYTES {
+        return Err(QuocoError::InvalidArchive("Invalid magic bytes"));
+    }
+    let mut version = [0u8; 2];
+    reader.read_exact(&mut version)?;
+    if u16::from_le_bytes(version) > ARCHIVE_VERSION {
+        return Err(QuocoError::InvalidArchive("Unsupported archive version"));
+    }
+
+    reader.seek(SeekFrom::Start(index_offset))?;
+    let index_data = read_entry(reader)?;
+    let (entries, names, hashes) = decrypt_index(index_data, key)?;
+
+    let mut import = ArchiveImport::default();
+    let mut staged = Vec::new();
+    for entry in entries.iter() {
+        let conflicting_id = match names.get_name(&entry.id) {
+            Some(name) => destination.object_id_with_name(name)?.copied(),
+            None => None,
+        };
+        if conflicting_id.is_some() && on_conflict == NameConflict::Skip {
+            import.skipped.push(entry.id);
+            continue;
+        }
+
+        match stage_object(reader, key, destination, entry, &hashes) {
+            Ok(id) => staged.push((id, entry.id, conflicting_id)),
+            Err(err) => {
+                discard_staged(destination, staged.iter().map(|(id, _, _)| id));
+                return Err(err);
+            }
+        }
+    }
+
+    // Names kept or given out by the import, so renamed objects never collide with each other
+    let mut taken: HashSet<String> = destination
+        .names()
+        .iter()
+        .map(|(_, name)| name.clone())
+        .collect();
+    taken.extend(
+        staged
+            .iter()
+            .filter_map(|(_, archived_id, _)| names.get_name(archived_id).cloned()),
+    );
+
+    let mut batch = Batch::new();
+    for (id, archived_id, conflicting_id) in staged.iter() {
+        let name = match (names.get_name(archived_id), conflicting_id) {
+            (Some(name), Some(existing_id)) if on_conflict == NameConflict::Overwrite => {
+                batch.delete(existing_id);
+                name.clone()
+            }
+            (Some(name), Some(_)) => {
+                let name = free_name(&taken, name);
+                taken.insert(name.clone());
+                name
+            }
+            (Some(name), None) => name.clone(),
+            (None, _) => continue,
+        };
+        batch.rename(id, &name);
+    }
+    if let Err(err) = destination.apply_batch(batch) {
+        discard_staged(destination, staged.iter().map(|(id, _, _)| id));
+        return Err(err);
+    }
+    import.imported = staged.into_iter().map(|(id, _, _)| id).collect();
+
+    destination.flush()?;
+
+    Ok(import)
+}
+
+/// Streams an archived object into a new, unnamed object in `destination` and checks it against
+/// its archived hash, returning the new object's ID.
+fn stage_object<R: Read + Seek>(
+    reader: &mut R,
+    key: &Key,
+    destination: &mut dyn ObjectSource,
+    entry: &IndexEntry,
+    hashes: &Hashes,
+) -> Result<ObjectId> {
+    reader.seek(SeekFrom::Start(entry.offset))?;
+    let mut writer = destination.begin_object()?;
+    io::copy(
+        &mut QuocoReader::new(reader.take(entry.length), key),
+        &mut writer,
+    )?;
+    let id = writer.commit()?;
+
+    // Object data is authenticated by decryption, so a mismatch here means the exported vault's
+    //  hashes were stale
+    if hashes.get_hash(&entry.id) != destination.object_hash(&id)? {
+        discard_staged(destination, [id].iter());
+        return Err(QuocoError::InvalidArchive(
+            "Object doesn't match its archived hash",
+        ));
+    }
+    Ok(id)
+}
+
+/// Deletes objects staged by an import that won't finish. Failures only leave unnamed objects
+/// behind, so they're ignored in favor of the error that stopped the import.
+fn discard_staged<'a, I: Iterator<Item = &'a ObjectId>>(
+    destination: &mut dyn ObjectSource,
+    staged: I,
+) {
+    for id in staged {
+        let _ = destination.delete_object(id);
+    }
+}
+
+/// Checks the footer and checksum, returning the offset of the index.
+fn verify_archive<R: Read + Seek>(reader: &mut R) -> Result<u64> {
+    let archive_length = reader.seek(SeekFrom::End(0))?;
+    if archive_length < (FOOTER_LENGTH + ARCHIVE_MAGIC_BYTES.len() + 2) as u64 {
+        return Err(QuocoError::InvalidArchive("Archive is truncated"));
+    }
+
+    let footer_offset = archive_length - FOOTER_LENGTH as u64;
+    reader.seek(SeekFrom::Start(footer_offset))?;
+    let mut footer = [0u8; FOOTER_LENGTH];
+    reader.read_exact(&mut footer)?;
+    if &footer[FOOTER_LENGTH - ARCHIVE_MAGIC_BYTES.len()..] != ARCHIVE_MAGIC_BYTES {
+        return Err(QuocoError::InvalidArchive("Invalid magic bytes"));
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha256::new()?;
+    let mut body = BufReader::new(reader.take(footer_offset));
+    loop {
+        let buf = body.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        hasher.update(buf)?;
+        let length = buf.len();
+        body.consume(length);
+    }
+
+    if hasher.finish()?[..] != footer[size_of::<u64>()..size_of::<u64>() + HASH_LENGTH] {
+        return Err(QuocoError::InvalidArchive(
+            "Checksum doesn't match archive contents",
+        ));
+    }
+
+    Ok(u64::from_le_bytes(footer[..size_of::<u64>()].try_into()?))
+}
+
+fn read_entry<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let mut length = [0u8; size_of::<u64>()];
+    reader.read_exact(&mut length)?;
+    let mut data = Vec::new();
+    reader
+        .take(u64::from_le_bytes(length))
+        .read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn encrypt_index(
+    entries: &[IndexEntry],
+    names: &Names,
+    hashes: &Hashes,
+    key: &Key,
+) -> Result<Vec<u8>> {
+    let mut writer = QuocoWriter::new(Vec::new(), key);
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for entry in entries {
+        writer.write_all(&entry.id)?;
+        writer.write_all(&entry.offset.to_le_bytes())?;
+        writer.write_all(&entry.length.to_le_bytes())?;
+    }
+
+    // Only include metadata for archived objects
+    let mut archived_names = Names::new();
+    let mut archived_hashes = Hashes::new();
+    for entry in entries {
+        if let Some(name) = names.get_name(&entry.id) {
+            archived_names.insert(&entry.id, name);
+        }
+        if let Some(hash) = hashes.get_hash(&entry.id) {
+            archived_hashes.insert(&entry.id, hash);
+        }
+    }
+
+    for format_data in [
+        save_format(&archived_names)?,
+        save_format(&archived_hashes)?,
+    ]
+    .iter()
+    {
+        writer.write_all(&(format_data.len() as u64).to_le_bytes())?;
+        writer.write_all(format_data)?;
+    }
+
+    Ok(writer.finish()?)
+}
+
+fn decrypt_index(data: Vec<u8>, key: &Key) -> Result<(Vec<IndexEntry>, Names, Hashes)> {
+    let mut index = Vec::new();
+    QuocoReader::new(Cursor::new(data), key).read_to_end(&mut index)?;
+    let mut reader = Cursor::new(index);
+
+    let mut count = [0u8; size_of::<u64>()];
+    reader.read_exact(&mut count)?;
+    let count = u64::from_le_bytes(count) as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut entry = [0u8; INDEX_ENTRY_LENGTH];
+    for _ in 0..count {
+        reader.read_exact(&mut entry)?;
+        entries.push(IndexEntry {
+            id: entry[..UUID_LENGTH].try_into()?,
+            offset: u64::from_le_bytes(entry[UUID_LENGTH..UUID_LENGTH + 8].try_into()?),
+            length: u64::from_le_bytes(entry[UUID_LENGTH + 8..].try_into()?),
+        });
+    }
+
+    let mut names = Names::new();
+    names.load(&mut Cursor::new(read_entry(&mut reader)?))?;
+    let mut hashes = Hashes::new();
+    hashes.load(&mut Cursor::new(read_entry(&mut reader)?))?;
+
+    Ok((entries, names, hashes))
+}
+
+fn save_format<F: ReferenceFormat>(format: &F) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    format.save(&mut data)?;
+    Ok(data)
+}
+
+/// Finds the first name of the form `name (n)` that isn't `taken`.
+fn free_name(taken: &HashSet<String>, name: &str) -> String {
+    (1..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| !taken.contains(candidate))
+        .unwrap()
+}
@@ -0,0 +1,196 @@
+//! Bulk copying between plain directory trees and object sources. Objects are named by their
+//! path relative to the root directory, always using `/` as the separator.
+
+use crate::error::QuocoError;
+use crate::object::{ObjectSource, MAX_NAME_LENGTH};
+use crate::util::sha256;
+use crate::{ReadSeek, Result};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Default)]
+pub struct DirectoryImport {
+    pub created: usize,
+    pub modified: usize,
+    /// Files skipped because the object with the same name already has the same SHA-256.
+    pub unchanged: usize,
+    /// Paths of files that weren't imported, relative to the root, with why: paths that aren't
+    /// valid UTF-8 or are too long to be object names.
+    pub skipped: Vec<(String, QuocoError)>,
+}
+
+#[derive(Debug, Default)]
+pub struct DirectoryExport {
+    pub written: usize,
+    /// Objects skipped because a file with the same contents already exists at their path.
+    pub unchanged: usize,
+    /// Names of objects that weren't written, with why: names that can't be used as relative
+    /// paths, that collide with another object's directory, or whose path already holds
+    /// something other than a regular file, like a symlink.
+    pub skipped: Vec<(String, QuocoError)>,
+}
+
+/// Creates or updates an object for every file under `root`, then flushes `source`. Files whose
+/// paths can't be used as object names are skipped and listed in [`DirectoryImport::skipped`].
+pub fn import_directory(source: &mut dyn ObjectSource, root: &Path) -> Result<DirectoryImport> {
+    let mut paths = Vec::new();
+    collect_files(root, &mut paths)?;
+    paths.sort();
+
+    let mut import = DirectoryImport::default();
+    for path in paths {
+        let name = match object_name(root, &path) {
+            Ok(name) => name,
+            Err(err @ QuocoError::InvalidPath(_)) | Err(err @ QuocoError::NameTooLong(_)) => {
+                let relative = path.strip_prefix(root).unwrap().to_string_lossy().into();
+                import.skipped.push((relative, err));
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let mut file = File::open(&path)?;
+
+        match source.object_id_with_name(&name)?.copied() {
+            Some(id) => {
+                if source.object_hash(&id)? == Some(&sha256(&mut file)?) {
+                    import.unchanged += 1;
+                    continue;
+                }
+                file.seek(SeekFrom::Start(0))?;
+                source.modify_object(&id, &mut (Box::new(file) as Box<dyn ReadSeek>))?;
+                import.modified += 1;
+            }
+            None => {
+                let id = source.create_object(&mut (Box::new(file) as Box<dyn ReadSeek>))?;
+                source.set_object_name(&id, &name)?;
+                import.created += 1;
+            }
+        }
+    }
+
+    source.flush()?;
+
+    Ok(import)
+}
+
+/// Writes every named object in `source` to its path under `root`, creating directories as
+/// needed. Unnamed objects are skipped, as are objects that can't be written without leaving
+/// `root` or replacing something other than a file, which are listed in
+/// [`DirectoryExport::skipped`].
+pub fn export_directory(source: &mut dyn ObjectSource, root: &Path) -> Result<DirectoryExport> {
+    let mut objects: Vec<_> = source
+        .names()
+        .iter()
+        .map(|(id, name)| (*id, name.clone()))
+        .collect();
+    objects.sort_by(|a, b| a.1.cmp(&b.1));
+
+    let mut export = DirectoryExport::default();
+    for (id, name) in objects {
+        let path = match object_path(root, &name) {
+            Ok(path) => path,
+            Err(err) => {
+                export.skipped.push((name, err));
+                continue;
+            }
+        };
+        let exists = match existing_file(root, &path) {
+            Ok(exists) => exists,
+            Err(err @ QuocoError::InvalidPath(_)) => {
+                export.skipped.push((name, err));
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+
+        if exists && source.object_hash(&id)? == Some(&sha256(&mut File::open(&path)?)?) {
+            export.unchanged += 1;
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut source.object(&id)?, &mut File::create(&path)?)?;
+        export.written += 1;
+    }
+
+    Ok(export)
+}
+
+fn collect_files(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        // Symlinks are skipped so imports can't escape the root or loop forever
+        if file_type.is_dir() {
+            collect_files(&entry.path(), paths)?;
+        } else if file_type.is_file() {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+fn object_name(root: &Path, path: &Path) -> Result<String> {
+    let name = path
+        .strip_prefix(root)
+        .unwrap()
+        .components()
+        .map(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .ok_or_else(|| QuocoError::InvalidPath(path.to_string_lossy().into()))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join("/");
+
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(QuocoError::NameTooLong(name.len()));
+    }
+
+    Ok(name)
+}
+
+/// Whether a regular file is already at `path` under `root`. Fails with
+/// [`QuocoError::InvalidPath`] if something other than a directory is in the way of its parent
+/// directories, or something other than a regular file is at `path`. Symlinks are never
+/// followed, so nothing outside `root` can be read or overwritten through them.
+fn existing_file(root: &Path, path: &Path) -> Result<bool> {
+    let mut current = root.to_path_buf();
+    for component in path.strip_prefix(root).unwrap().components() {
+        current.push(component);
+        let file_type = match fs::symlink_metadata(&current) {
+            Ok(metadata) => metadata.file_type(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        let usable = if current == path {
+            file_type.is_file()
+        } else {
+            file_type.is_dir()
+        };
+        if !usable {
+            return Err(QuocoError::InvalidPath(current.to_string_lossy().into()));
+        }
+    }
+    Ok(true)
+}
+
+/// Converts an object name back to a path under `root`, refusing names that would escape it.
+fn object_path(root: &Path, name: &str) -> Result<PathBuf> {
+    let relative = Path::new(name);
+    if name.is_empty()
+        || relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return Err(QuocoError::InvalidPath(name.into()));
+    }
+
+    Ok(root.join(relative))
+}
@@ -23,17 +23,49 @@ pub enum QuocoError {
     DecryptionError(EncryptionErrorType),
     EmptyInput,
     InvalidMagicBytes(&'static ReferenceFormatSpecification),
+    /// A reference format file has a version newer than this build supports, or no migration
+    /// path exists from its version to the current one.
+    UnsupportedFormatVersion(&'static ReferenceFormatSpecification, u16),
     EncryptionInputTooLong(usize),
     NameTooLong(usize),
+    /// Another object already has the name an object is being given.
+    NameTaken(String),
     KeyGenerationError,
     SessionPathLocked(String),
     SessionDisposed,
+    /// No open session has the given ID, which may have been closed already.
+    SessionNotFound(String),
     UndeterminedError,
+    /// A path can't be used as an object name or an object name can't be used as a relative path.
+    InvalidPath(String),
+    /// An archive is malformed, corrupted, or from a newer version of quocofs.
+    InvalidArchive(&'static str),
     /// No remote object sources were found
     NoRemotes,
+    /// A session has no remote with the given name.
+    RemoteNotFound(String),
+    /// Syncing with one or more remotes failed, given as (remote name, error) pairs.
+    RemoteSyncsFailed(Vec<(String, QuocoError)>),
     TempFileDeleteFailed(String),
     TempFileDeletesFailed(Vec<(String, QuocoError)>),
-    GoogleStorageError(cloud_storage::Error),
+    /// Google Cloud Storage credentials are invalid or a request was rejected.
+    GoogleStorageError(String),
+    /// An S3 endpoint is invalid or rejected a request.
+    S3Error(String),
+    /// A URL can't be parsed or doesn't address an object source.
+    InvalidUrl(String),
+    /// No source factory is registered for a URL scheme.
+    UnsupportedScheme(String),
+    /// A WebDAV URL is invalid or the server rejected a request.
+    WebDavError(String),
+    /// A request to a remote couldn't be sent or its response couldn't be read.
+    HttpError(reqwest::Error),
+    /// An SSH server's host key isn't in the known hosts file or doesn't match the one there.
+    HostKeyRejected(String),
+    /// Any error from an SSH connection or SFTP operation.
+    SshError(ssh2::Error),
+    /// Reference formats couldn't be read from or written to JSON.
+    JsonError(serde_json::Error),
     /// Any otherwise unhandled `std::io::Error`.
     IoError(std::io::Error),
 }
@@ -45,16 +77,31 @@ impl std::error::Error for QuocoError {
             | QuocoError::DecryptionError(_)
             | QuocoError::EmptyInput
             | QuocoError::InvalidMagicBytes(_)
+            | QuocoError::UnsupportedFormatVersion(_, _)
             | QuocoError::EncryptionInputTooLong(_)
             | QuocoError::NameTooLong(_)
+            | QuocoError::NameTaken(_)
             | QuocoError::KeyGenerationError
             | QuocoError::SessionPathLocked(_)
             | QuocoError::SessionDisposed
+            | QuocoError::SessionNotFound(_)
             | QuocoError::NoRemotes
+            | QuocoError::RemoteNotFound(_)
+            | QuocoError::RemoteSyncsFailed(_)
+            | QuocoError::InvalidArchive(_)
+            | QuocoError::InvalidPath(_)
             | QuocoError::TempFileDeleteFailed(_)
             | QuocoError::TempFileDeletesFailed(_)
+            | QuocoError::GoogleStorageError(_)
+            | QuocoError::S3Error(_)
+            | QuocoError::WebDavError(_)
+            | QuocoError::InvalidUrl(_)
+            | QuocoError::UnsupportedScheme(_)
+            | QuocoError::HostKeyRejected(_)
             | QuocoError::UndeterminedError => None,
-            QuocoError::GoogleStorageError(ref err) => err.source(),
+            QuocoError::HttpError(ref err) => err.source(),
+            QuocoError::SshError(ref err) => err.source(),
+            QuocoError::JsonError(ref err) => err.source(),
             QuocoError::IoError(ref err) => err.source(),
         }
     }
@@ -77,9 +124,21 @@ impl From<std::io::Error> for QuocoError {
     }
 }
 
-impl From<cloud_storage::Error> for QuocoError {
-    fn from(err: cloud_storage::Error) -> Self {
-        QuocoError::GoogleStorageError(err)
+impl From<reqwest::Error> for QuocoError {
+    fn from(err: reqwest::Error) -> Self {
+        QuocoError::HttpError(err)
+    }
+}
+
+impl From<ssh2::Error> for QuocoError {
+    fn from(err: ssh2::Error) -> Self {
+        QuocoError::SshError(err)
+    }
+}
+
+impl From<serde_json::Error> for QuocoError {
+    fn from(err: serde_json::Error) -> Self {
+        QuocoError::JsonError(err)
     }
 }
 
@@ -119,6 +178,9 @@ impl std::fmt::Display for QuocoError {
                     length, MAX_NAME_LENGTH
                 )
             }
+            QuocoError::NameTaken(name) => {
+                write!(f, "Another object is already named {}", name)
+            }
             QuocoError::KeyGenerationError => {
                 write!(f, "Key generation failed")
             }
@@ -128,12 +190,42 @@ impl std::fmt::Display for QuocoError {
             QuocoError::SessionDisposed => {
                 write!(f, "Attempted to use session after clearing lock")
             }
+            QuocoError::SessionNotFound(id) => {
+                write!(f, "No open session has ID {}", id)
+            }
             QuocoError::InvalidMagicBytes(data_type) => {
                 write!(f, "Invalid magic bytes for {} data", data_type)
             }
+            QuocoError::UnsupportedFormatVersion(data_type, version) => {
+                write!(
+                    f,
+                    "Unsupported version {} for {} data (current version is {})",
+                    version, data_type, data_type.version
+                )
+            }
             QuocoError::NoRemotes => {
                 write!(f, "No remotes configured")
             }
+            QuocoError::RemoteNotFound(name) => {
+                write!(f, "No remote named {}", name)
+            }
+            QuocoError::RemoteSyncsFailed(errors) => {
+                write!(f, "Failed to sync remotes:")?;
+                for (name, error) in errors {
+                    write!(f, "\n\t{}: {}", name, error)?;
+                }
+                Ok(())
+            }
+            QuocoError::InvalidPath(path) => {
+                write!(
+                    f,
+                    "{} can't be mapped between an object name and a path",
+                    path
+                )
+            }
+            QuocoError::InvalidArchive(msg) => {
+                write!(f, "Invalid archive: {}", msg)
+            }
             QuocoError::UndeterminedError => {
                 write!(f, "Undetermined error")
             }
@@ -148,7 +240,19 @@ impl std::fmt::Display for QuocoError {
                 }
                 Ok(())
             }
-            QuocoError::GoogleStorageError(ref err) => err.fmt(f),
+            QuocoError::GoogleStorageError(msg) => write!(f, "Google Cloud Storage error: {}", msg),
+            QuocoError::S3Error(msg) => write!(f, "S3 error: {}", msg),
+            QuocoError::WebDavError(msg) => write!(f, "WebDAV error: {}", msg),
+            QuocoError::InvalidUrl(msg) => write!(f, "Invalid URL: {}", msg),
+            QuocoError::UnsupportedScheme(scheme) => {
+                write!(f, "No object source registered for URL scheme {}", scheme)
+            }
+            QuocoError::HttpError(ref err) => err.fmt(f),
+            QuocoError::HostKeyRejected(host) => {
+                write!(f, "Host key for {} is unknown or has changed", host)
+            }
+            QuocoError::SshError(ref err) => err.fmt(f),
+            QuocoError::JsonError(ref err) => write!(f, "Invalid metadata JSON: {}", err),
             QuocoError::IoError(ref err) => err.fmt(f),
         }
     }
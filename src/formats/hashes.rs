@@ -13,7 +13,11 @@ type HashesDataType = HashMap<ObjectId, ObjectHash>;
 
 const ENTRY_LENGTH: usize = UUID_LENGTH + HASH_LENGTH;
 
+#[derive(Clone)]
 pub struct Hashes {
+    /// When an object's hash was last added, changed or removed, or its name changed (which sources
+    /// record with [`set_last_updated`](Self::set_last_updated)). Saved as is, so a timestamp
+    /// brought in with `set_last_updated` survives until the next change.
     last_updated: SystemTime,
     data: HashesDataType,
 }
@@ -27,11 +31,20 @@ impl Hashes {
     }
 
     pub fn insert(&mut self, id: &ObjectId, hash: &ObjectHash) -> Option<ObjectHash> {
-        self.data.insert(*id, *hash)
+        let previous = self.data.insert(*id, *hash);
+        // Re-inserting the same hash changes nothing, so it shouldn't look like a change to sync
+        if previous.as_ref() != Some(hash) {
+            self.last_updated = SystemTime::now();
+        }
+        previous
     }
 
     pub fn remove(&mut self, id: &ObjectId) -> Option<ObjectHash> {
-        self.data.remove(id)
+        let removed = self.data.remove(id);
+        if removed.is_some() {
+            self.last_updated = SystemTime::now();
+        }
+        removed
     }
 
     pub fn get_hash(&self, id: &ObjectId) -> Option<&ObjectHash> {
@@ -46,6 +59,10 @@ impl Hashes {
         &self.last_updated
     }
 
+    pub fn set_last_updated(&mut self, last_updated: SystemTime) {
+        self.last_updated = last_updated;
+    }
+
     pub fn get_ids(&self) -> hash_map::Keys<'_, ObjectId, ObjectHash> {
         self.data.keys()
     }
@@ -60,9 +77,7 @@ impl ReferenceFormat for Hashes {
         &HASHES
     }
 
-    fn load<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
-        Self::check_magic_bytes(reader)?;
-
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
         let mut timestamp = [0u8; size_of::<u64>()];
         reader.read_exact(&mut timestamp)?;
         let timestamp = u64::from_le_bytes(timestamp);
@@ -93,15 +108,15 @@ impl ReferenceFormat for Hashes {
         Ok(())
     }
 
-    fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(Self::specification().magic_bytes)?;
-        let now: u64 = SystemTime::now()
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let last_updated: u64 = self
+            .last_updated
             .duration_since(UNIX_EPOCH)
             .unwrap()
             .as_millis()
             .try_into()
             .unwrap();
-        writer.write_all(&now.to_le_bytes())?;
+        writer.write_all(&last_updated.to_le_bytes())?;
         for (id, hash) in self.data.iter() {
             assert_eq!(id.len(), UUID_LENGTH);
             assert_eq!(hash.len(), HASH_LENGTH);
@@ -0,0 +1,99 @@
+//! Plaintext JSON representation of a vault's reference formats, meant for debugging and for
+//! moving metadata between tools. The schema is:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "last_updated": 1617235200000,
+//!   "names": { "<object id as hex>": "<name>" },
+//!   "hashes": { "<object id as hex>": "<SHA-256 of plaintext as hex>" }
+//! }
+//! ```
+//!
+//! `last_updated` is in milliseconds since the Unix epoch, matching the `hashes` format. Objects
+//! without a name are only listed in `hashes`.
+
+use crate::error::QuocoError;
+use crate::formats::{Hashes, Names};
+use crate::object::{ObjectHash, ObjectId};
+use crate::util::bytes_to_hex_str;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Version of the JSON schema written by [`export_json`].
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct ReferenceFormatsJson {
+    version: u32,
+    last_updated: u64,
+    // BTreeMap keeps exports stable between runs, which makes them diffable
+    names: BTreeMap<String, String>,
+    hashes: BTreeMap<String, String>,
+}
+
+pub fn export_json<W: Write>(names: &Names, hashes: &Hashes, writer: W) -> Result<()> {
+    let json = ReferenceFormatsJson {
+        version: JSON_VERSION,
+        last_updated: hashes
+            .get_last_updated()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            .try_into()
+            .unwrap(),
+        names: names
+            .iter()
+            .map(|(id, name)| (bytes_to_hex_str(id), name.clone()))
+            .collect(),
+        hashes: hashes
+            .iter()
+            .map(|(id, hash)| (bytes_to_hex_str(id), bytes_to_hex_str(hash)))
+            .collect(),
+    };
+
+    serde_json::to_writer_pretty(writer, &json)?;
+    Ok(())
+}
+
+pub fn import_json<R: Read>(reader: R) -> Result<(Names, Hashes)> {
+    let json: ReferenceFormatsJson = serde_json::from_reader(reader)?;
+
+    if json.version > JSON_VERSION {
+        return Err(invalid_json(format!(
+            "unsupported version {} (current version is {})",
+            json.version, JSON_VERSION
+        )));
+    }
+
+    let mut names = Names::new();
+    for (id, name) in json.names.iter() {
+        names.insert(&decode_hex::<ObjectId>(id)?, name);
+    }
+
+    let mut hashes = Hashes::new();
+    for (id, hash) in json.hashes.iter() {
+        hashes.insert(
+            &decode_hex::<ObjectId>(id)?,
+            &decode_hex::<ObjectHash>(hash)?,
+        );
+    }
+    hashes.set_last_updated(UNIX_EPOCH + Duration::from_millis(json.last_updated));
+
+    Ok((names, hashes))
+}
+
+fn decode_hex<T: for<'a> TryFrom<&'a [u8]>>(hex_str: &str) -> Result<T> {
+    let bytes = hex::decode(hex_str)
+        .map_err(|_| invalid_json(format!("\"{}\" is not a valid hex string", hex_str)))?;
+    T::try_from(bytes.as_slice())
+        .map_err(|_| invalid_json(format!("\"{}\" has the wrong length", hex_str)))
+}
+
+pub(crate) fn invalid_json(msg: String) -> QuocoError {
+    QuocoError::JsonError(serde::de::Error::custom(msg))
+}
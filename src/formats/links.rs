@@ -0,0 +1,166 @@
+use crate::formats::{Hashes, ReferenceFormat, ReferenceFormatSpecification, LINKS};
+use crate::object::{ObjectHash, ObjectId, UUID_LENGTH};
+use crate::Result;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+type LinksDataType = HashMap<ObjectId, ObjectId>;
+
+const ENTRY_LENGTH: usize = UUID_LENGTH * 2;
+
+/// Maps objects to the blob holding their encrypted data when that blob isn't stored under the
+/// object's own ID, which happens when deduplicated objects share a blob. Objects without an
+/// entry are stored under their own ID.
+///
+/// A blob's reference count is the number of objects in [`Hashes`] that resolve to it. Counts,
+/// and a blob for each hash, are only kept in memory: they're rebuilt by [`index`](Self::index)
+/// and kept up to date by [`link`](Self::link) and [`unlink`](Self::unlink), which change the
+/// hashes along with the links.
+#[derive(Clone)]
+pub struct Links {
+    data: LinksDataType,
+    blobs: HashMap<ObjectId, BlobRefs>,
+    /// A blob holding data with each hash. When several do, only one is kept.
+    blobs_by_hash: HashMap<ObjectHash, ObjectId>,
+}
+
+#[derive(Clone)]
+struct BlobRefs {
+    hash: ObjectHash,
+    count: usize,
+}
+
+impl Links {
+    pub fn new() -> Self {
+        Links {
+            data: HashMap::new(),
+            blobs: HashMap::new(),
+            blobs_by_hash: HashMap::new(),
+        }
+    }
+
+    pub fn blob_id(&self, id: &ObjectId) -> ObjectId {
+        *self.data.get(id).unwrap_or(id)
+    }
+
+    /// Rebuilds reference counts from `hashes`, dropping links of objects that aren't in it.
+    /// Needed whenever links or hashes are loaded or replaced rather than changed through
+    /// [`link`](Self::link) and [`unlink`](Self::unlink).
+    pub fn index(&mut self, hashes: &Hashes) {
+        self.data.retain(|id, _| hashes.get_hash(id).is_some());
+        self.blobs.clear();
+        self.blobs_by_hash.clear();
+        for (id, hash) in hashes.iter() {
+            self.add_ref(&self.blob_id(id), hash);
+        }
+    }
+
+    pub fn ref_count(&self, blob_id: &ObjectId) -> usize {
+        self.blobs.get(blob_id).map_or(0, |refs| refs.count)
+    }
+
+    /// A blob that holds data with `hash`, if any object uses one.
+    pub fn blob_with_hash(&self, hash: &ObjectHash) -> Option<ObjectId> {
+        self.blobs_by_hash.get(hash).copied()
+    }
+
+    /// Sets `id`'s hash in `hashes` and points it at `blob_id`, returning the blob it used
+    /// before, if it had one.
+    pub fn link(
+        &mut self,
+        hashes: &mut Hashes,
+        id: &ObjectId,
+        hash: &ObjectHash,
+        blob_id: &ObjectId,
+    ) -> Option<ObjectId> {
+        let previous_blob_id = self.unlink(hashes, id);
+
+        hashes.insert(id, hash);
+        if id == blob_id {
+            self.data.remove(id);
+        } else {
+            self.data.insert(*id, *blob_id);
+        }
+        self.add_ref(blob_id, hash);
+
+        previous_blob_id
+    }
+
+    /// Removes `id` from `hashes` and from the links, returning the blob it used, if it had one.
+    pub fn unlink(&mut self, hashes: &mut Hashes, id: &ObjectId) -> Option<ObjectId> {
+        let blob_id = self.blob_id(id);
+        self.data.remove(id);
+        hashes.remove(id)?;
+
+        if let Entry::Occupied(mut refs) = self.blobs.entry(blob_id) {
+            refs.get_mut().count -= 1;
+            if refs.get().count == 0 {
+                let hash = refs.remove().hash;
+                if self.blobs_by_hash.get(&hash) == Some(&blob_id) {
+                    self.blobs_by_hash.remove(&hash);
+                }
+            }
+        }
+        Some(blob_id)
+    }
+
+    fn add_ref(&mut self, blob_id: &ObjectId, hash: &ObjectHash) {
+        self.blobs
+            .entry(*blob_id)
+            .or_insert(BlobRefs {
+                hash: *hash,
+                count: 0,
+            })
+            .count += 1;
+        self.blobs_by_hash.entry(*hash).or_insert(*blob_id);
+    }
+}
+
+impl ReferenceFormat for Links {
+    fn specification() -> &'static ReferenceFormatSpecification {
+        &LINKS
+    }
+
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
+        let mut entry_buf = Vec::with_capacity(ENTRY_LENGTH);
+        loop {
+            entry_buf.clear();
+
+            let entry_bytes_read = reader
+                .take(ENTRY_LENGTH as u64)
+                .read_to_end(&mut entry_buf)?;
+
+            if entry_bytes_read == 0 {
+                break;
+            }
+
+            if entry_bytes_read < ENTRY_LENGTH {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+
+            self.data.insert(
+                entry_buf[..UUID_LENGTH].try_into()?,
+                entry_buf[UUID_LENGTH..].try_into()?,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for (id, blob_id) in self.data.iter() {
+            writer.write_all(id)?;
+            writer.write_all(blob_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Links {
+    fn default() -> Self {
+        Links::new()
+    }
+}
@@ -0,0 +1,130 @@
+use crate::formats::{ReferenceFormat, ReferenceFormatSpecification, MANIFESTS};
+use crate::object::{ObjectHash, ObjectId, HASH_LENGTH, UUID_LENGTH};
+use crate::Result;
+use std::collections::{hash_map, HashMap};
+use std::convert::TryInto;
+use std::io::{BufRead, Read, Write};
+use std::mem::size_of;
+
+type ManifestsDataType = HashMap<ObjectId, Manifest>;
+
+/// The chunks making up a chunked object, in order, along with the SHA-256 of the whole object.
+#[derive(Clone)]
+pub struct Manifest {
+    pub hash: ObjectHash,
+    pub chunks: Vec<ObjectId>,
+}
+
+/// Manifests by object ID. How many times each chunk is used is only kept in memory, and is kept
+/// up to date as manifests are inserted and removed.
+pub struct Manifests {
+    data: ManifestsDataType,
+    chunk_refs: HashMap<ObjectId, usize>,
+}
+
+impl Manifests {
+    pub fn new() -> Self {
+        Manifests {
+            data: HashMap::new(),
+            chunk_refs: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, id: &ObjectId, manifest: Manifest) -> Option<Manifest> {
+        self.add_refs(&manifest);
+        let previous = self.data.insert(*id, manifest);
+        if let Some(previous) = &previous {
+            self.remove_refs(previous);
+        }
+        previous
+    }
+
+    pub fn remove(&mut self, id: &ObjectId) -> Option<Manifest> {
+        let manifest = self.data.remove(id)?;
+        self.remove_refs(&manifest);
+        Some(manifest)
+    }
+
+    pub fn get(&self, id: &ObjectId) -> Option<&Manifest> {
+        self.data.get(id)
+    }
+
+    pub fn contains_chunk(&self, chunk_id: &ObjectId) -> bool {
+        self.chunk_refs.contains_key(chunk_id)
+    }
+
+    pub fn iter(&self) -> hash_map::Iter<'_, ObjectId, Manifest> {
+        self.data.iter()
+    }
+
+    fn add_refs(&mut self, manifest: &Manifest) {
+        for chunk_id in manifest.chunks.iter() {
+            *self.chunk_refs.entry(*chunk_id).or_insert(0) += 1;
+        }
+    }
+
+    fn remove_refs(&mut self, manifest: &Manifest) {
+        for chunk_id in manifest.chunks.iter() {
+            if let hash_map::Entry::Occupied(mut refs) = self.chunk_refs.entry(*chunk_id) {
+                *refs.get_mut() -= 1;
+                if *refs.get() == 0 {
+                    refs.remove();
+                }
+            }
+        }
+    }
+}
+
+impl ReferenceFormat for Manifests {
+    fn specification() -> &'static ReferenceFormatSpecification {
+        &MANIFESTS
+    }
+
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
+        let mut header = [0u8; UUID_LENGTH + HASH_LENGTH + size_of::<u32>()];
+        loop {
+            // An empty buffer here means we're at the end of the file
+            if reader.fill_buf()?.is_empty() {
+                break;
+            }
+            reader.read_exact(&mut header)?;
+
+            let chunk_count =
+                u32::from_le_bytes(header[UUID_LENGTH + HASH_LENGTH..].try_into()?) as usize;
+            let mut chunks = Vec::with_capacity(chunk_count);
+            let mut chunk_id = [0u8; UUID_LENGTH];
+            for _ in 0..chunk_count {
+                reader.read_exact(&mut chunk_id)?;
+                chunks.push(chunk_id);
+            }
+
+            self.insert(
+                &header[..UUID_LENGTH].try_into()?,
+                Manifest {
+                    hash: header[UUID_LENGTH..UUID_LENGTH + HASH_LENGTH].try_into()?,
+                    chunks,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for (id, manifest) in self.data.iter() {
+            writer.write_all(id)?;
+            writer.write_all(&manifest.hash)?;
+            writer.write_all(&(manifest.chunks.len() as u32).to_le_bytes())?;
+            for chunk_id in manifest.chunks.iter() {
+                writer.write_all(chunk_id)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Manifests {
+    fn default() -> Self {
+        Manifests::new()
+    }
+}
@@ -0,0 +1,56 @@
+use crate::error::QuocoError;
+use crate::formats::{ReferenceFormatSpecification, HASHES, NAMES};
+use crate::Result;
+use std::io;
+use std::io::{Read, Write};
+
+/// A single upgrade step for the body (everything after the header) of a reference format file.
+pub struct Migration {
+    pub format: &'static ReferenceFormatSpecification,
+    /// Version this step upgrades from. Every step upgrades by exactly one version.
+    pub from_version: u16,
+    pub upgrade: fn(&mut dyn Read, &mut dyn Write) -> Result<()>,
+}
+
+/// Every known upgrade step. Adding a version to a format means bumping its
+/// [`ReferenceFormatSpecification::version`] and adding a step from the previous version here.
+pub const MIGRATIONS: &[Migration] = &[
+    // Version 1 only added the version number to the header
+    Migration {
+        format: &NAMES,
+        from_version: 0,
+        upgrade: copy_body,
+    },
+    Migration {
+        format: &HASHES,
+        from_version: 0,
+        upgrade: copy_body,
+    },
+];
+
+fn copy_body(reader: &mut dyn Read, writer: &mut dyn Write) -> Result<()> {
+    io::copy(reader, writer)?;
+    Ok(())
+}
+
+/// Upgrades a format body from `version` to the current version of `specification` by applying
+/// each step in [`MIGRATIONS`] in turn.
+pub fn migrate(
+    specification: &'static ReferenceFormatSpecification,
+    version: u16,
+    body: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let mut body = body;
+    for from_version in version..specification.version {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|m| m.format.name == specification.name && m.from_version == from_version)
+            .ok_or(QuocoError::UnsupportedFormatVersion(specification, version))?;
+
+        let mut upgraded = Vec::with_capacity(body.len());
+        (migration.upgrade)(&mut body.as_slice(), &mut upgraded)?;
+        body = upgraded;
+    }
+
+    Ok(body)
+}
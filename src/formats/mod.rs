@@ -1,17 +1,35 @@
 mod hashes;
+mod json;
+mod links;
+mod manifests;
+mod migration;
 mod names;
+mod vault_config;
 
 pub use crate::formats::hashes::Hashes;
+pub(crate) use crate::formats::json::invalid_json;
+pub use crate::formats::json::{export_json, import_json, JSON_VERSION};
+pub use crate::formats::links::Links;
+pub use crate::formats::manifests::{Manifest, Manifests};
+pub use crate::formats::migration::{migrate, Migration, MIGRATIONS};
 pub use crate::formats::names::Names;
+pub use crate::formats::vault_config::{ObjectLayout, VaultConfig};
 
 use crate::error::QuocoError;
 use crate::Result;
-use std::io::{BufRead, Read, Write};
+use std::io::{BufRead, Cursor, Read, Write};
 
 #[derive(Debug)]
 pub struct ReferenceFormatSpecification {
+    /// Written at the start of every file, followed by the format version as a little-endian
+    /// `u16`.
     pub magic_bytes: &'static [u8],
+    /// Magic bytes used before formats were versioned. Files starting with these are treated as
+    /// version 0. Formats added after versioning have none.
+    pub unversioned_magic_bytes: Option<&'static [u8]>,
     pub name: &'static str,
+    /// Version of the layout that [`ReferenceFormat::save`] writes.
+    pub version: u16,
 }
 
 impl std::fmt::Display for ReferenceFormatSpecification {
@@ -21,34 +39,96 @@ impl std::fmt::Display for ReferenceFormatSpecification {
 }
 
 pub const NAMES: ReferenceFormatSpecification = ReferenceFormatSpecification {
-    magic_bytes: b"pern",
+    magic_bytes: b"perN",
+    unversioned_magic_bytes: Some(b"pern"),
     name: "names",
+    version: 1,
 };
 
 pub const HASHES: ReferenceFormatSpecification = ReferenceFormatSpecification {
-    magic_bytes: b"perh",
+    magic_bytes: b"perH",
+    unversioned_magic_bytes: Some(b"perh"),
     name: "hashes",
+    version: 1,
+};
+
+pub const LINKS: ReferenceFormatSpecification = ReferenceFormatSpecification {
+    magic_bytes: b"perL",
+    unversioned_magic_bytes: None,
+    name: "links",
+    version: 1,
+};
+
+pub const MANIFESTS: ReferenceFormatSpecification = ReferenceFormatSpecification {
+    magic_bytes: b"perM",
+    unversioned_magic_bytes: None,
+    name: "manifests",
+    version: 1,
+};
+
+pub const VAULT_CONFIG: ReferenceFormatSpecification = ReferenceFormatSpecification {
+    magic_bytes: b"perC",
+    unversioned_magic_bytes: None,
+    name: "config",
+    version: 1,
 };
 
 pub trait ReferenceFormat {
     // TODO: Is there a cleaner way to do this? I want to force every format to provide a name and
-    //  magic bytes field (as used in the default implementation of check_magic_bytes) as part of
+    //  magic bytes field (as used in the default implementation of read_header) as part of
     //  the ReferenceFormat contract--and maybe I'm just stuck in OOP land--but I can't think of
     //  any solution that feels less icky than this.
     fn specification() -> &'static ReferenceFormatSpecification;
 
-    fn load<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()>;
-    fn save<W: Write>(&self, writer: &mut W) -> Result<()>;
+    /// Reads everything after the header, which is always in the current version's layout.
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()>;
+    /// Writes everything after the header in the current version's layout.
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()>;
+
+    /// Loads a file written by any supported version of this format, upgrading older layouts in
+    /// memory. The upgrade is only persisted the next time the format is saved.
+    fn load<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
+        let specification = Self::specification();
+        let version = Self::read_header(reader)?;
+
+        if version == specification.version {
+            return self.load_body(reader);
+        }
+
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        self.load_body(&mut Cursor::new(migrate(specification, version, body)?))
+    }
 
-    fn check_magic_bytes<R: Read>(reader: &mut R) -> Result<()> {
+    fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let specification = Self::specification();
+        writer.write_all(specification.magic_bytes)?;
+        writer.write_all(&specification.version.to_le_bytes())?;
+        self.save_body(writer)
+    }
+
+    /// Checks magic bytes and returns the version of the file being read.
+    fn read_header<R: Read>(reader: &mut R) -> Result<u16> {
         let format_info = Self::specification();
         let mut magic_bytes = vec![0; format_info.magic_bytes.len()];
         reader.read_exact(&mut magic_bytes)?;
 
+        if Some(magic_bytes.as_slice()) == format_info.unversioned_magic_bytes {
+            return Ok(0);
+        }
+
         if magic_bytes.ne(format_info.magic_bytes) {
             return Err(QuocoError::InvalidMagicBytes(format_info));
         }
 
-        Ok(())
+        let mut version = [0u8; 2];
+        reader.read_exact(&mut version)?;
+        let version = u16::from_le_bytes(version);
+
+        if version > format_info.version {
+            return Err(QuocoError::UnsupportedFormatVersion(format_info, version));
+        }
+
+        Ok(version)
     }
 }
@@ -9,6 +9,7 @@ use std::ops::Index;
 
 type NamesDataType = HashMap<ObjectId, String>;
 
+#[derive(Clone)]
 pub struct Names {
     data: NamesDataType,
 }
@@ -58,8 +59,7 @@ impl ReferenceFormat for Names {
         &NAMES
     }
 
-    fn load<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
-        Self::check_magic_bytes(reader)?;
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
         let mut uuid = Vec::with_capacity(UUID_LENGTH);
 
         loop {
@@ -84,8 +84,7 @@ impl ReferenceFormat for Names {
         Ok(())
     }
 
-    fn save<W: Write>(&self, writer: &mut W) -> Result<()> {
-        writer.write_all(Self::specification().magic_bytes)?;
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()> {
         for name in self.data.iter() {
             writer.write_all(name.0)?;
             // Strip name of non-ASCII characters
@@ -0,0 +1,73 @@
+use crate::formats::{ReferenceFormat, ReferenceFormatSpecification, VAULT_CONFIG};
+use crate::Result;
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+/// How a [`FsObjectSource`](crate::object::FsObjectSource) arranges object files in its
+/// directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObjectLayout {
+    /// Every object file directly in the vault directory, as vaults were always laid out before
+    /// layouts could be chosen.
+    Flat,
+    /// Object files two directories deep, named after the first two pairs of hex digits of their
+    /// IDs, like `ab/cd/abcd...`, which keeps directories small in large vaults.
+    Sharded,
+}
+
+impl ObjectLayout {
+    /// The layout a vault could have been in before switching to this one.
+    pub fn other(self) -> Self {
+        match self {
+            ObjectLayout::Flat => ObjectLayout::Sharded,
+            ObjectLayout::Sharded => ObjectLayout::Flat,
+        }
+    }
+}
+
+/// Settings stored alongside a vault's reference formats. Vaults without a config file use the
+/// defaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultConfig {
+    pub layout: ObjectLayout,
+}
+
+impl VaultConfig {
+    pub fn new() -> Self {
+        VaultConfig {
+            layout: ObjectLayout::Flat,
+        }
+    }
+}
+
+impl ReferenceFormat for VaultConfig {
+    fn specification() -> &'static ReferenceFormatSpecification {
+        &VAULT_CONFIG
+    }
+
+    fn load_body<R: BufRead + Read>(&mut self, reader: &mut R) -> Result<()> {
+        let mut layout = [0u8; 1];
+        reader.read_exact(&mut layout)?;
+        self.layout = match layout[0] {
+            0 => ObjectLayout::Flat,
+            1 => ObjectLayout::Sharded,
+            _ => return Err(io::Error::from(io::ErrorKind::InvalidData).into()),
+        };
+        Ok(())
+    }
+
+    fn save_body<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let layout: u8 = match self.layout {
+            ObjectLayout::Flat => 0,
+            ObjectLayout::Sharded => 1,
+        };
+        writer.write_all(&[layout])?;
+        Ok(())
+    }
+}
+
+impl Default for VaultConfig {
+    fn default() -> Self {
+        VaultConfig::new()
+    }
+}
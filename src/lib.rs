@@ -6,6 +6,8 @@ use std::fs::File;
 use std::io::{Cursor, Read, Seek};
 use std::result;
 
+pub mod archive;
+pub mod directory;
 pub mod error;
 pub mod formats;
 pub mod object;
@@ -0,0 +1,191 @@
+use crate::object::{Finish, Key, QuocoReader, QuocoWriter, CHUNK_LENGTH};
+use std::future::Future;
+use std::io;
+use std::io::Write;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use tokio::task::{spawn_blocking, JoinHandle};
+use tokio_util::io::SyncIoBridge;
+
+/// How much unencrypted data can wait between async code and the blocking task on the other end
+/// of a pipe before writes have to wait for reads.
+const PIPE_LENGTH: usize = CHUNK_LENGTH * 16;
+
+/// Reads whatever a task on tokio's blocking thread pool writes. Compression, encryption and
+/// synchronous sources all run on such tasks, so none of them hold up the executor.
+pub(crate) struct BlockingReader {
+    pipe: DuplexStream,
+    task: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl BlockingReader {
+    /// Runs `write` on the blocking thread pool, handing it the writing end of the pipe this reads
+    /// from. Must be called from within a tokio runtime.
+    pub(crate) fn spawn<F>(write: F) -> Self
+    where
+        F: FnOnce(&mut dyn Write) -> io::Result<()> + Send + 'static,
+    {
+        let (pipe, other_end) = tokio::io::duplex(PIPE_LENGTH);
+        let mut writer = SyncIoBridge::new(other_end);
+        let task = spawn_blocking(move || {
+            write(&mut writer)?;
+            writer.shutdown()
+        });
+        BlockingReader {
+            pipe,
+            task: Some(task),
+        }
+    }
+}
+
+impl AsyncRead for BlockingReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.pipe).poll_read(cx, buf))?;
+        if buf.filled().len() > filled || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        // The pipe only ends once the task stops writing, which it also does when it fails, so
+        // its result decides whether this is really the end of the data
+        match self.task.as_mut() {
+            Some(task) => {
+                let result = ready!(Pin::new(task).poll(cx));
+                self.task = None;
+                Poll::Ready(result.map_err(io::Error::from).and_then(|result| result))
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+/// Async counterpart of [`QuocoReader`], decrypting and decompressing data from an [`AsyncRead`].
+pub struct AsyncQuocoReader {
+    inner: BlockingReader,
+}
+
+impl AsyncQuocoReader {
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn new<R: AsyncRead + Send + Unpin + 'static>(reader: R, key: &Key) -> Self {
+        let reader = SyncIoBridge::new(reader);
+        let key = *key;
+        AsyncQuocoReader {
+            inner: BlockingReader::spawn(move |writer| {
+                io::copy(&mut QuocoReader::new(reader, &key), writer).map(drop)
+            }),
+        }
+    }
+}
+
+impl AsyncRead for AsyncQuocoReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+/// Async counterpart of [`QuocoWriter`], compressing and encrypting data into an [`AsyncWrite`].
+///
+/// Shutting the writer down finishes encryption and waits for everything to reach the inner
+/// writer, which [`finish`](Self::finish) then hands back. Flushing only waits for data to be
+/// accepted, since compression holds on to data until it has enough of it.
+pub struct AsyncQuocoWriter<W> {
+    pipe: DuplexStream,
+    task: Option<JoinHandle<io::Result<W>>>,
+    inner: Option<W>,
+}
+
+impl<W: AsyncWrite + Send + Unpin + 'static> AsyncQuocoWriter<W> {
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime.
+    pub fn new(writer: W, key: &Key) -> Self {
+        let (pipe, other_end) = tokio::io::duplex(PIPE_LENGTH);
+        let mut reader = SyncIoBridge::new(other_end);
+        let writer = SyncIoBridge::new(writer);
+        let key = *key;
+        let task = spawn_blocking(move || {
+            let mut writer = QuocoWriter::new(writer, &key);
+            let copied = io::copy(&mut reader, &mut writer);
+            // Encryption has to be finished even if copying failed, since it insists on it
+            let writer = writer.finish();
+            copied?;
+            let mut writer = writer?;
+            writer.flush()?;
+            Ok(writer.into_inner())
+        });
+        AsyncQuocoWriter {
+            pipe,
+            task: Some(task),
+            inner: None,
+        }
+    }
+
+    /// Finishes encrypting and returns the inner writer, like [`Finish::finish`] does for
+    /// [`QuocoWriter`].
+    pub async fn finish(mut self) -> io::Result<W> {
+        tokio::io::AsyncWriteExt::shutdown(&mut self).await?;
+        self.inner
+            .take()
+            .ok_or_else(|| io::Error::other("writer was already finished"))
+    }
+
+    /// Waits for the task to stop, which it only does early if it failed.
+    fn poll_task(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.task.as_mut() {
+            Some(task) => {
+                let result = ready!(Pin::new(task).poll(cx));
+                self.task = None;
+                let inner = result.map_err(io::Error::from).and_then(|result| result)?;
+                self.inner = Some(inner);
+                Poll::Ready(Ok(()))
+            }
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    /// Swaps a failed write into the pipe for the error that made the task stop reading from it.
+    fn task_error(&mut self, cx: &mut Context<'_>, err: io::Error) -> io::Error {
+        match self.poll_task(cx) {
+            Poll::Ready(Err(task_err)) => task_err,
+            _ => err,
+        }
+    }
+}
+
+impl<W: AsyncWrite + Send + Unpin + 'static> AsyncWrite for AsyncQuocoWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match ready!(Pin::new(&mut self.pipe).poll_write(cx, buf)) {
+            Ok(written) => Poll::Ready(Ok(written)),
+            Err(err) => Poll::Ready(Err(self.task_error(cx, err))),
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match ready!(Pin::new(&mut self.pipe).poll_flush(cx)) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(err) => Poll::Ready(Err(self.task_error(cx, err))),
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Err(err) = ready!(Pin::new(&mut self.pipe).poll_shutdown(cx)) {
+            return Poll::Ready(Err(self.task_error(cx, err)));
+        }
+        self.poll_task(cx)
+    }
+}
@@ -0,0 +1,208 @@
+use crate::object::async_quoco::BlockingReader;
+use crate::object::{Batch, BoxedObjectSource, ObjectHash, ObjectId, ObjectInfo, ObjectSource};
+use crate::Result;
+use async_trait::async_trait;
+use std::io;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncRead;
+use tokio::sync::oneshot;
+use tokio::task::spawn_blocking;
+use tokio_util::io::SyncIoBridge;
+
+pub type AsyncObjectReader = Box<dyn AsyncRead + Send + Unpin>;
+
+/// Async counterpart of [`ObjectSource`] for services running on tokio.
+///
+/// Calls may run while other tasks hold on to the source, so metadata comes back owned instead of
+/// borrowed from the source.
+#[async_trait]
+pub trait AsyncObjectSource: Send + Sync {
+    async fn object(&mut self, id: &ObjectId) -> Result<AsyncObjectReader>;
+    async fn object_exists(&self, id: &ObjectId) -> Result<bool>;
+    async fn delete_object(&mut self, id: &ObjectId) -> Result<()>;
+    /// Unlike [`ObjectSource::create_object`], data is only read once, so it doesn't need to be
+    /// seekable.
+    async fn create_object(&mut self, reader: AsyncObjectReader) -> Result<ObjectId>;
+    async fn modify_object(&mut self, id: &ObjectId, reader: AsyncObjectReader) -> Result<()>;
+    async fn object_hash(&self, id: &ObjectId) -> Result<Option<ObjectHash>>;
+    async fn object_name(&self, id: &ObjectId) -> Result<Option<String>>;
+    async fn object_id_with_name(&self, name: &str) -> Result<Option<ObjectId>>;
+    async fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()>;
+    async fn remove_object_name(&mut self, id: &ObjectId) -> Result<()>;
+    async fn rename_object(&mut self, id: &ObjectId, name: &str) -> Result<()>;
+    async fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId>;
+    async fn object_range(
+        &mut self,
+        id: &ObjectId,
+        offset: u64,
+        len: u64,
+    ) -> Result<AsyncObjectReader>;
+    async fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>>;
+    async fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>>;
+    async fn list(&self) -> Result<Vec<ObjectInfo>>;
+    async fn flush(&mut self) -> Result<()>;
+}
+
+/// Runs any [`ObjectSource`] on tokio's blocking thread pool, so the existing sources can be used
+/// from async code without blocking the executor. Object data streams through in both directions
+/// rather than being read into memory first.
+pub struct TokioObjectSource {
+    source: Arc<Mutex<BoxedObjectSource>>,
+}
+
+impl TokioObjectSource {
+    pub fn new(source: BoxedObjectSource) -> Self {
+        TokioObjectSource {
+            source: Arc::new(Mutex::new(source)),
+        }
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn ObjectSource) -> Result<T> + Send + 'static,
+    {
+        let source = Arc::clone(&self.source);
+        spawn_blocking(move || f(&mut **source.lock().unwrap()))
+            .await
+            .map_err(io::Error::from)?
+    }
+
+    /// Opens a reader with `open` and streams it back once it's open. The source is only locked
+    /// while opening, so other calls can go ahead while the data is read.
+    async fn open<F>(&self, open: F) -> Result<AsyncObjectReader>
+    where
+        F: FnOnce(&mut dyn ObjectSource) -> Result<Box<dyn Read>> + Send + 'static,
+    {
+        let source = Arc::clone(&self.source);
+        let (opened_sender, opened) = oneshot::channel();
+        let reader = BlockingReader::spawn(move |writer| {
+            let reader = open(&mut **source.lock().unwrap());
+            drop(source);
+            let mut reader = match reader {
+                Ok(reader) => {
+                    let _ = opened_sender.send(Ok(()));
+                    reader
+                }
+                Err(err) => {
+                    let _ = opened_sender.send(Err(err));
+                    return Ok(());
+                }
+            };
+            io::copy(&mut reader, writer).map(drop)
+        });
+        opened.await.map_err(io::Error::other)??;
+
+        Ok(Box::new(reader))
+    }
+}
+
+#[async_trait]
+impl AsyncObjectSource for TokioObjectSource {
+    async fn object(&mut self, id: &ObjectId) -> Result<AsyncObjectReader> {
+        let id = *id;
+        self.open(move |source| source.object(&id)).await
+    }
+
+    async fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        let id = *id;
+        self.run(move |source| source.object_exists(&id)).await
+    }
+
+    async fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        let id = *id;
+        self.run(move |source| source.delete_object(&id)).await
+    }
+
+    async fn create_object(&mut self, reader: AsyncObjectReader) -> Result<ObjectId> {
+        let mut reader = SyncIoBridge::new(reader);
+        self.run(move |source| {
+            let mut writer = source.begin_object()?;
+            io::copy(&mut reader, &mut writer)?;
+            writer.commit()
+        })
+        .await
+    }
+
+    async fn modify_object(&mut self, id: &ObjectId, reader: AsyncObjectReader) -> Result<()> {
+        let id = *id;
+        let mut reader = SyncIoBridge::new(reader);
+        self.run(move |source| {
+            let mut writer = source.begin_modify_object(&id)?;
+            io::copy(&mut reader, &mut writer)?;
+            writer.commit().map(drop)
+        })
+        .await
+    }
+
+    async fn object_hash(&self, id: &ObjectId) -> Result<Option<ObjectHash>> {
+        let id = *id;
+        self.run(move |source| Ok(source.object_hash(&id)?.copied()))
+            .await
+    }
+
+    async fn object_name(&self, id: &ObjectId) -> Result<Option<String>> {
+        let id = *id;
+        self.run(move |source| Ok(source.object_name(&id)?.cloned()))
+            .await
+    }
+
+    async fn object_id_with_name(&self, name: &str) -> Result<Option<ObjectId>> {
+        let name = name.to_string();
+        self.run(move |source| Ok(source.object_id_with_name(&name)?.copied()))
+            .await
+    }
+
+    async fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        let id = *id;
+        let name = name.to_string();
+        self.run(move |source| source.set_object_name(&id, &name))
+            .await
+    }
+
+    async fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        let id = *id;
+        self.run(move |source| source.remove_object_name(&id)).await
+    }
+
+    async fn rename_object(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        let id = *id;
+        let name = name.to_string();
+        self.run(move |source| source.rename_object(&id, &name))
+            .await
+    }
+
+    async fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        let id = *id;
+        self.run(move |source| source.copy_object(&id)).await
+    }
+
+    async fn object_range(
+        &mut self,
+        id: &ObjectId,
+        offset: u64,
+        len: u64,
+    ) -> Result<AsyncObjectReader> {
+        let id = *id;
+        self.open(move |source| source.object_range(&id, offset, len))
+            .await
+    }
+
+    async fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>> {
+        self.run(move |source| source.apply_batch(batch)).await
+    }
+
+    async fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>> {
+        let id = *id;
+        self.run(move |source| source.stat(&id)).await
+    }
+
+    async fn list(&self) -> Result<Vec<ObjectInfo>> {
+        self.run(|source| Ok(source.list()?.collect())).await
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.run(|source| source.flush()).await
+    }
+}
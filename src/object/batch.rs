@@ -0,0 +1,437 @@
+use crate::error::QuocoError::NameTaken;
+use crate::object::blob_store::{BlobStore, LinkedBlobs};
+use crate::object::object_writer::HashingQuocoWriter;
+use crate::object::{Key, ObjectHash, ObjectId, ObjectSource};
+use crate::Result;
+use std::collections::HashSet;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use uuid::Uuid;
+
+/// How many objects a batch writes at once unless told otherwise.
+pub const DEFAULT_BATCH_PARALLELISM: usize = 8;
+
+/// One change in a [`Batch`].
+pub enum BatchOperation {
+    Create {
+        data: Box<dyn Read + Send>,
+        name: Option<String>,
+    },
+    Modify {
+        id: ObjectId,
+        data: Box<dyn Read + Send>,
+    },
+    Rename {
+        id: ObjectId,
+        name: String,
+    },
+    Delete {
+        id: ObjectId,
+    },
+}
+
+/// Changes to apply to a source together with [`ObjectSource::apply_batch`], which saves names
+/// and hashes once for the whole batch instead of once per change. Operations apply in the order
+/// they're added, so later ones see the effects of earlier ones.
+pub struct Batch {
+    operations: Vec<BatchOperation>,
+    parallelism: usize,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Batch {
+            operations: Vec::new(),
+            parallelism: DEFAULT_BATCH_PARALLELISM,
+        }
+    }
+
+    /// Adds a new object. Its ID is returned by [`ObjectSource::apply_batch`].
+    pub fn create<R: Read + Send + 'static>(&mut self, data: R, name: Option<&str>) -> &mut Self {
+        self.operations.push(BatchOperation::Create {
+            data: Box::new(data),
+            name: name.map(String::from),
+        });
+        self
+    }
+
+    pub fn modify<R: Read + Send + 'static>(&mut self, id: &ObjectId, data: R) -> &mut Self {
+        self.operations.push(BatchOperation::Modify {
+            id: *id,
+            data: Box::new(data),
+        });
+        self
+    }
+
+    /// Like [`ObjectSource::rename_object`], fails the batch if another object has the name.
+    pub fn rename(&mut self, id: &ObjectId, name: &str) -> &mut Self {
+        self.operations.push(BatchOperation::Rename {
+            id: *id,
+            name: name.into(),
+        });
+        self
+    }
+
+    pub fn delete(&mut self, id: &ObjectId) -> &mut Self {
+        self.operations.push(BatchOperation::Delete { id: *id });
+        self
+    }
+
+    /// Limits how many objects are encrypted and written at once, for sources that write in
+    /// parallel. At least 1.
+    pub fn set_parallelism(&mut self, parallelism: usize) -> &mut Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+
+    pub fn operations(&self) -> &[BatchOperation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Batch::new()
+    }
+}
+
+impl IntoIterator for Batch {
+    type Item = BatchOperation;
+    type IntoIter = std::vec::IntoIter<BatchOperation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::from(io::ErrorKind::NotFound)
+}
+
+/// Applies a batch through a source's ordinary methods and flushes once at the end, for
+/// [`ObjectSource::apply_batch`]'s default.
+///
+/// Deletes are held back until everything else has succeeded so a failed batch never loses data.
+/// If anything fails, names and hashes are put back as they were, but data that was already
+/// modified can't be.
+pub(crate) fn apply_batch_sequentially<S: ObjectSource + ?Sized>(
+    source: &mut S,
+    batch: Batch,
+) -> Result<Vec<ObjectId>> {
+    let names = source.names().clone();
+    let hashes = source.hashes().clone();
+
+    let result = apply_each(source, batch).and_then(|created| {
+        source.flush()?;
+        Ok(created)
+    });
+    if result.is_err() {
+        source.replace_reference_formats(names, hashes)?;
+    }
+    result
+}
+
+fn apply_each<S: ObjectSource + ?Sized>(source: &mut S, batch: Batch) -> Result<Vec<ObjectId>> {
+    let mut created = Vec::new();
+    let mut deleted = HashSet::new();
+    let check_exists = |source: &S, deleted: &HashSet<ObjectId>, id: &ObjectId| {
+        let known = source.hashes().get_hash(id).is_some() || source.names().get_name(id).is_some();
+        if !known || deleted.contains(id) {
+            return Err(not_found());
+        }
+        Ok(())
+    };
+
+    for operation in batch {
+        match operation {
+            BatchOperation::Create { mut data, name } => {
+                if let Some(name) = &name {
+                    if source.object_id_with_name(name)?.is_some() {
+                        return Err(NameTaken(name.clone()));
+                    }
+                }
+                let mut writer = source.begin_object()?;
+                io::copy(&mut data, &mut writer)?;
+                let id = writer.commit()?;
+                if let Some(name) = &name {
+                    source.set_object_name(&id, name)?;
+                }
+                created.push(id);
+            }
+            BatchOperation::Modify { id, mut data } => {
+                check_exists(source, &deleted, &id)?;
+                let mut writer = source.begin_modify_object(&id)?;
+                io::copy(&mut data, &mut writer)?;
+                writer.commit()?;
+            }
+            BatchOperation::Rename { id, name } => {
+                check_exists(source, &deleted, &id)?;
+                source.rename_object(&id, &name)?;
+            }
+            BatchOperation::Delete { id } => {
+                check_exists(source, &deleted, &id)?;
+                // Frees the name for later operations
+                source.remove_object_name(&id)?;
+                deleted.insert(id);
+            }
+        }
+    }
+
+    for id in deleted.iter() {
+        source.delete_object(id)?;
+    }
+    Ok(created)
+}
+
+/// A batch whose data is written, with the names, hashes and links it results in, waiting to be
+/// committed.
+///
+/// Nothing the source currently uses is touched until the source's metadata is
+/// [swapped](Self::swap) with the staged metadata and saved. Until then, or if saving fails and
+/// the metadata is swapped back, [`discard`](Self::discard) leaves the source as it was.
+pub(crate) struct StagedBatch {
+    blobs: LinkedBlobs,
+    created: Vec<ObjectId>,
+    /// Blobs this batch wrote
+    written: Vec<ObjectId>,
+    /// Blobs that may no longer be used once the batch is committed
+    released: Vec<ObjectId>,
+}
+
+impl StagedBatch {
+    pub(crate) fn stage<B: BlobStore>(
+        store: &B,
+        blobs: &LinkedBlobs,
+        key: &Key,
+        batch: Batch,
+    ) -> Result<Self> {
+        let parallelism = batch.parallelism();
+        let mut staged = StagedBatch {
+            blobs: blobs.clone(),
+            created: Vec::new(),
+            written: Vec::new(),
+            released: Vec::new(),
+        };
+
+        // Data is written to fresh blobs first, in parallel and before anything is checked, so
+        // no blob the source uses is ever overwritten. Creates get blobs under their own ID.
+        let mut writes = Vec::new();
+        let mut operations = Vec::new();
+        for operation in batch {
+            let (operation, data) = match operation {
+                BatchOperation::Create { data, name } => {
+                    let id = *Uuid::new_v4().as_bytes();
+                    (StagedOperation::Create { id, name }, Some((id, data)))
+                }
+                BatchOperation::Modify { id, data } => (
+                    StagedOperation::Modify { id },
+                    Some((*Uuid::new_v4().as_bytes(), data)),
+                ),
+                BatchOperation::Rename { id, name } => (StagedOperation::Rename { id, name }, None),
+                BatchOperation::Delete { id } => (StagedOperation::Delete { id }, None),
+            };
+            if let Some(write) = data {
+                writes.push(write);
+            }
+            operations.push(operation);
+        }
+
+        let written = Mutex::new(Vec::new());
+        let blob_hashes = parallel_map(writes, parallelism, |(blob_id, mut data)| {
+            let mut writer = HashingQuocoWriter::new(store.stage_blob()?, key)?;
+            io::copy(&mut data, &mut writer)?;
+            let (staged_blob, hash) = writer.finish()?;
+            store.put_blob(&blob_id, staged_blob)?;
+            written.lock().unwrap().push(blob_id);
+            Ok((blob_id, hash))
+        });
+        staged.written = written.into_inner().unwrap();
+        let blob_hashes = match blob_hashes {
+            Ok(blob_hashes) => blob_hashes,
+            Err(err) => {
+                staged.discard(store);
+                return Err(err);
+            }
+        };
+
+        let mut blob_hashes = blob_hashes.into_iter();
+        for operation in operations {
+            let result = match operation {
+                StagedOperation::Create { id, name } => {
+                    let (blob_id, hash) = blob_hashes.next().unwrap();
+                    staged.create(&id, name.as_deref(), &blob_id, &hash)
+                }
+                StagedOperation::Modify { id } => {
+                    let (blob_id, hash) = blob_hashes.next().unwrap();
+                    staged.modify(&id, &blob_id, &hash)
+                }
+                StagedOperation::Rename { id, name } => staged.rename(&id, &name),
+                StagedOperation::Delete { id } => staged.delete(&id),
+            };
+            if let Err(err) = result {
+                staged.discard(store);
+                return Err(err);
+            }
+        }
+
+        Ok(staged)
+    }
+
+    fn check_exists(&self, id: &ObjectId) -> Result<()> {
+        if self.blobs.hashes.get_hash(id).is_none() && self.blobs.names.get_name(id).is_none() {
+            return Err(not_found().into());
+        }
+        Ok(())
+    }
+
+    fn check_name_free(&self, id: &ObjectId, name: &str) -> Result<()> {
+        match self.blobs.names.get_id(name) {
+            Some(other_id) if other_id != id => Err(NameTaken(name.into())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Points `id` at the blob written for it, or at an identical blob it can share, in which
+    /// case the written blob is released again.
+    fn link(&mut self, id: &ObjectId, blob_id: &ObjectId, hash: &ObjectHash) {
+        let blob_id = match self.blobs.shared_blob(hash) {
+            Some(shared_blob_id) => {
+                self.released.push(*blob_id);
+                shared_blob_id
+            }
+            None => *blob_id,
+        };
+        if let Some(previous_blob_id) = self.blobs.link(id, hash, &blob_id) {
+            self.released.push(previous_blob_id);
+        }
+    }
+
+    fn create(
+        &mut self,
+        id: &ObjectId,
+        name: Option<&str>,
+        blob_id: &ObjectId,
+        hash: &ObjectHash,
+    ) -> Result<()> {
+        if let Some(name) = name {
+            self.check_name_free(id, name)?;
+            self.blobs.set_name(id, name);
+        }
+        self.link(id, blob_id, hash);
+        self.created.push(*id);
+        Ok(())
+    }
+
+    fn modify(&mut self, id: &ObjectId, blob_id: &ObjectId, hash: &ObjectHash) -> Result<()> {
+        if self.blobs.hashes.get_hash(id).is_none() {
+            return Err(not_found().into());
+        }
+        self.link(id, blob_id, hash);
+        Ok(())
+    }
+
+    fn rename(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.check_exists(id)?;
+        self.check_name_free(id, name)?;
+        self.blobs.set_name(id, name);
+        Ok(())
+    }
+
+    fn delete(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_exists(id)?;
+        let blob_id = self.blobs.unlink(id);
+        self.released.push(blob_id);
+        Ok(())
+    }
+
+    /// Exchanges the staged metadata with a source's, so swapping twice puts it back.
+    pub(crate) fn swap(&mut self, blobs: &mut LinkedBlobs) {
+        mem::swap(&mut self.blobs, blobs);
+    }
+
+    /// Deletes every blob the batch wrote, for batches that won't be committed. Failures leave
+    /// orphans behind, which [`ObjectSource::list`] can find.
+    pub(crate) fn discard<B: BlobStore>(self, store: &B) {
+        for blob_id in self.written.iter() {
+            let _ = store.delete_blob(blob_id);
+        }
+    }
+
+    /// Releases blobs that nothing uses anymore once the batch is committed, returning the IDs
+    /// of created objects in the order they were added.
+    pub(crate) fn finish(self, blobs: &mut LinkedBlobs) -> Vec<ObjectId> {
+        for blob_id in self.released.iter() {
+            blobs.release(blob_id);
+        }
+        self.created
+    }
+}
+
+/// A batch operation with its data taken out to be written.
+enum StagedOperation {
+    Create { id: ObjectId, name: Option<String> },
+    Modify { id: ObjectId },
+    Rename { id: ObjectId, name: String },
+    Delete { id: ObjectId },
+}
+
+/// Maps `items` on up to `parallelism` threads, keeping their order. Stops taking new items after
+/// the first error, which is returned.
+fn parallel_map<T, U, F>(items: Vec<T>, parallelism: usize, f: F) -> Result<Vec<U>>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> Result<U> + Sync,
+{
+    let count = items.len();
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let results = Mutex::new((0..count).map(|_| None).collect::<Vec<Option<U>>>());
+    let error = Mutex::new(None);
+    let failed = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.min(count) {
+            scope.spawn(|| {
+                while !failed.load(Ordering::SeqCst) {
+                    let (index, item) = match queue.lock().unwrap().next() {
+                        Some(next) => next,
+                        None => return,
+                    };
+                    match f(item) {
+                        Ok(result) => results.lock().unwrap()[index] = Some(result),
+                        Err(err) => {
+                            failed.store(true, Ordering::SeqCst);
+                            error.lock().unwrap().get_or_insert(err);
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    match error.into_inner().unwrap() {
+        Some(err) => Err(err),
+        None => Ok(results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(Option::unwrap)
+            .collect()),
+    }
+}
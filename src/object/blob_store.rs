@@ -0,0 +1,331 @@
+use crate::formats::{Hashes, Links, Names};
+use crate::object::batch::StagedBatch;
+use crate::object::object_writer::HashingQuocoWriter;
+use crate::object::{Batch, Key, ObjectHash, ObjectId, ObjectWriter};
+use crate::Result;
+use std::collections::HashSet;
+use std::io;
+use std::io::{Read, Write};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+use uuid::Uuid;
+
+/// Where a source that keeps [`Links`] stores encrypted blobs. Stores only read, put and delete
+/// whole blobs; which blob each object uses is up to [`LinkedBlobs`].
+pub(crate) trait BlobStore: Sync {
+    fn read_blob(&self, blob_id: &ObjectId) -> Result<Box<dyn Read>>;
+
+    /// Somewhere to write a blob's encrypted data before it's known which blob it goes in. By
+    /// default a temporary file, so objects never have to fit in memory before they're uploaded.
+    fn stage_blob(&self) -> Result<NamedTempFile> {
+        Ok(NamedTempFile::new()?)
+    }
+
+    /// Stores staged data as `blob_id`.
+    fn put_blob(&self, blob_id: &ObjectId, staged: NamedTempFile) -> Result<()>;
+
+    fn delete_blob(&self, blob_id: &ObjectId) -> Result<()>;
+
+    /// Duplicates a blob's encrypted data as `to`. Stores that can copy without reading the blob
+    /// back should.
+    fn copy_blob(&self, from: &ObjectId, to: &ObjectId) -> Result<()> {
+        let mut staged = self.stage_blob()?;
+        io::copy(&mut self.read_blob(from)?, &mut staged)?;
+        self.put_blob(to, staged)
+    }
+}
+
+/// The names, hashes and links of a source that stores objects as blobs in a [`BlobStore`], and
+/// what every such source does with them: streaming objects into blobs, sharing blobs between
+/// identical objects, and deleting blobs once nothing uses them.
+///
+/// Blobs are never overwritten, and ones nothing uses anymore are only deleted by
+/// [`delete_released`](Self::delete_released) once the reference formats are saved, so the
+/// saved formats never point at missing or changed data.
+#[derive(Clone, Default)]
+pub(crate) struct LinkedBlobs {
+    pub(crate) names: Names,
+    pub(crate) hashes: Hashes,
+    pub(crate) links: Links,
+    /// When enabled, objects with identical contents share a single blob. Objects that already
+    /// share blobs keep sharing them after this is disabled.
+    pub(crate) dedupe: bool,
+    /// Blobs nothing used when they were released, which the saved formats may still use
+    released: HashSet<ObjectId>,
+}
+
+impl LinkedBlobs {
+    pub(crate) fn new(names: Names, hashes: Hashes, mut links: Links) -> Self {
+        links.index(&hashes);
+        LinkedBlobs {
+            names,
+            hashes,
+            links,
+            dedupe: false,
+            released: HashSet::new(),
+        }
+    }
+
+    /// Swaps in names and hashes from elsewhere, as described by
+    /// [`ObjectSource::replace_reference_formats`]. Blobs that only objects left out of `hashes`
+    /// used are released.
+    ///
+    /// [`ObjectSource::replace_reference_formats`]: crate::object::ObjectSource::replace_reference_formats
+    pub(crate) fn replace(&mut self, names: Names, hashes: Hashes) {
+        let previous_blob_ids: HashSet<ObjectId> =
+            self.hashes.get_ids().map(|id| self.blob_id(id)).collect();
+
+        self.names = names;
+        self.hashes = hashes;
+        self.links.index(&self.hashes);
+
+        for blob_id in previous_blob_ids {
+            self.release(&blob_id);
+        }
+    }
+
+    /// Names an object. Name changes count as updates to the source, like changes to hashes.
+    pub(crate) fn set_name(&mut self, id: &ObjectId, name: &str) {
+        if self.names.insert(id, name).as_deref() != Some(name) {
+            self.hashes.set_last_updated(SystemTime::now());
+        }
+    }
+
+    pub(crate) fn remove_name(&mut self, id: &ObjectId) {
+        if self.names.remove(id).is_some() {
+            self.hashes.set_last_updated(SystemTime::now());
+        }
+    }
+
+    pub(crate) fn blob_id(&self, id: &ObjectId) -> ObjectId {
+        self.links.blob_id(id)
+    }
+
+    /// Whether a blob is only still stored because it's waiting to be deleted on flush.
+    pub(crate) fn is_released(&self, blob_id: &ObjectId) -> bool {
+        self.released.contains(blob_id)
+    }
+
+    /// Starts streaming data into a staged blob for `id`, or for a new object when `id` is
+    /// `None`. Nothing changes until the writer is committed.
+    pub(crate) fn begin_write<'a, B: BlobStore>(
+        &'a mut self,
+        store: &'a B,
+        key: &Key,
+        id: Option<ObjectId>,
+    ) -> Result<Box<dyn ObjectWriter + 'a>> {
+        let writer = HashingQuocoWriter::new(store.stage_blob()?, key)?;
+        Ok(Box::new(LinkedObjectWriter {
+            blobs: self,
+            store,
+            id,
+            writer: Some(writer),
+        }))
+    }
+
+    /// Like [`begin_write`](Self::begin_write), for data that's all available up front.
+    pub(crate) fn write<B: BlobStore>(
+        &mut self,
+        store: &B,
+        key: &Key,
+        id: Option<ObjectId>,
+        reader: &mut dyn Read,
+    ) -> Result<ObjectId> {
+        let mut writer = self.begin_write(store, key, id)?;
+        io::copy(reader, &mut writer)?;
+        writer.commit()
+    }
+
+    /// A blob that already holds data with `hash` to share, if dedupe is on.
+    pub(crate) fn shared_blob(&self, hash: &ObjectHash) -> Option<ObjectId> {
+        if !self.dedupe {
+            return None;
+        }
+        self.links.blob_with_hash(hash)
+    }
+
+    /// Which blob new data for `id` should be written to when it isn't shared: one under the
+    /// object's own ID for new objects, and a fresh one otherwise, so the saved formats keep
+    /// pointing at the old data until they're saved again.
+    fn new_blob_id(&self, id: &ObjectId) -> ObjectId {
+        let unused = self.hashes.get_hash(id).is_none()
+            && self.links.ref_count(id) == 0
+            && !self.released.contains(id);
+        if unused {
+            *id
+        } else {
+            *Uuid::new_v4().as_bytes()
+        }
+    }
+
+    /// Points `id` at `blob_id`, which holds data with `hash`, returning the blob it used before.
+    pub(crate) fn link(
+        &mut self,
+        id: &ObjectId,
+        hash: &ObjectHash,
+        blob_id: &ObjectId,
+    ) -> Option<ObjectId> {
+        self.links.link(&mut self.hashes, id, hash, blob_id)
+    }
+
+    /// Removes `id` entirely, returning the blob it used, which may no longer be needed.
+    pub(crate) fn unlink(&mut self, id: &ObjectId) -> ObjectId {
+        let blob_id = self.links.blob_id(id);
+        self.links.unlink(&mut self.hashes, id);
+        self.names.remove(id);
+        blob_id
+    }
+
+    /// Points `id` at a blob holding data with `hash`, storing `staged` as that blob unless an
+    /// identical one can be shared, then releases the blob `id` used before.
+    fn store<B: BlobStore>(
+        &mut self,
+        store: &B,
+        id: &ObjectId,
+        hash: &ObjectHash,
+        staged: NamedTempFile,
+    ) -> Result<()> {
+        let blob_id = match self.shared_blob(hash) {
+            Some(blob_id) => blob_id,
+            None => {
+                let blob_id = self.new_blob_id(id);
+                store.put_blob(&blob_id, staged)?;
+                blob_id
+            }
+        };
+
+        if let Some(previous_blob_id) = self.link(id, hash, &blob_id) {
+            self.release(&previous_blob_id);
+        }
+        Ok(())
+    }
+
+    /// Duplicates an object under a new ID, sharing its blob if dedupe is on.
+    pub(crate) fn copy<B: BlobStore>(&mut self, store: &B, id: &ObjectId) -> Result<ObjectId> {
+        let hash = *self
+            .hashes
+            .get_hash(id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        let new_id = *Uuid::new_v4().as_bytes();
+        let mut blob_id = self.links.blob_id(id);
+        if !self.dedupe {
+            store.copy_blob(&blob_id, &new_id)?;
+            blob_id = new_id;
+        }
+        self.link(&new_id, &hash, &blob_id);
+
+        Ok(new_id)
+    }
+
+    pub(crate) fn delete<B: BlobStore>(&mut self, store: &B, id: &ObjectId) -> Result<()> {
+        if self.hashes.get_hash(id).is_none() && self.names.get_name(id).is_none() {
+            // Orphans aren't in the saved formats, so they can go right away
+            if self.links.ref_count(id) > 0 || self.released.contains(id) {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            return store.delete_blob(id);
+        }
+        let blob_id = self.unlink(id);
+        self.release(&blob_id);
+        Ok(())
+    }
+
+    /// Queues a blob for deletion once no objects reference it.
+    pub(crate) fn release(&mut self, blob_id: &ObjectId) {
+        if self.links.ref_count(blob_id) == 0 {
+            self.released.insert(*blob_id);
+        }
+    }
+
+    /// Saves the reference formats with `save`, then deletes blobs released since they were last
+    /// saved.
+    pub(crate) fn flush<B: BlobStore, F: FnOnce(&LinkedBlobs) -> Result<()>>(
+        &mut self,
+        store: &B,
+        save: F,
+    ) -> Result<()> {
+        save(self)?;
+        self.delete_released(store)
+    }
+
+    /// Deletes released blobs, which is only safe once the reference formats are saved. Blobs
+    /// that objects use again, like after the formats were replaced, are kept.
+    pub(crate) fn delete_released<B: BlobStore>(&mut self, store: &B) -> Result<()> {
+        let released: Vec<ObjectId> = self.released.iter().copied().collect();
+        for blob_id in released {
+            if self.links.ref_count(&blob_id) == 0 {
+                store.delete_blob(&blob_id)?;
+            }
+            self.released.remove(&blob_id);
+        }
+        Ok(())
+    }
+
+    /// Applies a batch as described by [`ObjectSource::apply_batch`], writing data to fresh blobs
+    /// in parallel. `save` writes the reference formats, and is called again with the old ones
+    /// if saving the new ones fails.
+    ///
+    /// [`ObjectSource::apply_batch`]: crate::object::ObjectSource::apply_batch
+    pub(crate) fn apply_batch<B: BlobStore, F: Fn(&LinkedBlobs) -> Result<()>>(
+        &mut self,
+        store: &B,
+        key: &Key,
+        batch: Batch,
+        save: F,
+    ) -> Result<Vec<ObjectId>> {
+        let mut staged = StagedBatch::stage(store, self, key, batch)?;
+        staged.swap(self);
+        if let Err(err) = save(self) {
+            // Metadata may have been partly saved, so put the old metadata back too
+            staged.swap(self);
+            let _ = save(self);
+            staged.discard(store);
+            return Err(err);
+        }
+
+        let created = staged.finish(self);
+        // The batch is committed either way, so blobs that can't be deleted now are left for
+        // the next flush
+        let _ = self.delete_released(store);
+        Ok(created)
+    }
+}
+
+/// Streams an object into a staged blob, which is stored and linked on commit.
+struct LinkedObjectWriter<'a, B: BlobStore> {
+    blobs: &'a mut LinkedBlobs,
+    store: &'a B,
+    /// Object to modify, or `None` to create a new one
+    id: Option<ObjectId>,
+    /// Only taken on commit
+    writer: Option<HashingQuocoWriter<NamedTempFile>>,
+}
+
+impl<'a, B: BlobStore> Write for LinkedObjectWriter<'a, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.as_mut().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl<'a, B: BlobStore> ObjectWriter for LinkedObjectWriter<'a, B> {
+    fn commit(mut self: Box<Self>) -> Result<ObjectId> {
+        let (staged, hash) = self.writer.take().unwrap().finish()?;
+
+        let id = self.id.unwrap_or_else(|| *Uuid::new_v4().as_bytes());
+        self.blobs.store(self.store, &id, &hash, staged)?;
+        Ok(id)
+    }
+}
+
+impl<'a, B: BlobStore> Drop for LinkedObjectWriter<'a, B> {
+    fn drop(&mut self) {
+        // The staged file deletes itself unless it was stored
+        if let Some(writer) = self.writer.take() {
+            writer.discard();
+        }
+    }
+}
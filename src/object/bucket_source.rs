@@ -0,0 +1,488 @@
+use crate::error::QuocoError;
+use crate::formats::{Hashes, Links, Names, ReferenceFormat};
+use crate::object::blob_store::{BlobStore, LinkedBlobs};
+use crate::object::fs_source::LOCK_FILE_NAME;
+use crate::object::source::read_range;
+use crate::object::{
+    Batch, Finish, Key, ObjectHash, ObjectId, ObjectInfo, ObjectSource, ObjectWriter, QuocoReader,
+    QuocoWriter,
+};
+use crate::util::{bytes_to_hex_str, hex_str_to_object_id};
+use crate::{ReadSeek, Result};
+use std::collections::hash_map::Keys;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::time::SystemTime;
+use std::{cmp, io};
+use tempfile::NamedTempFile;
+
+/// Range reads fetch windows starting at this size, doubling up to the max so short previews
+/// stay cheap without making long ranges take many round trips.
+const MIN_RANGE_WINDOW: u64 = 256 * 1024;
+const MAX_RANGE_WINDOW: u64 = 16 * 1024 * 1024;
+
+/// What a bucket reports about an object stored in it.
+pub struct StoredObject {
+    /// Full name in the bucket
+    pub name: String,
+    pub size: Option<u64>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+impl StoredObject {
+    fn fill_info(&self, mut info: ObjectInfo) -> ObjectInfo {
+        info.size = self.size;
+        info.created = self.created;
+        info.modified = self.modified;
+        info
+    }
+}
+
+/// The calls a [`BucketObjectSource`] makes to an object storage service, like Cloud Storage or
+/// S3. Names are full object names in the bucket.
+pub trait BucketClient: Send + Sync + 'static {
+    /// An object's data, or `None` if there's no object with the name.
+    fn get(&self, name: &str) -> Result<Option<Box<dyn Read + Send>>>;
+
+    /// Up to `len` bytes of an object from `start`, which are only fewer at the end of it.
+    fn get_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>>;
+
+    fn metadata(&self, name: &str) -> Result<Option<StoredObject>>;
+
+    /// Every object whose name starts with `prefix`, following pagination.
+    fn list(&self, prefix: &str) -> Result<Vec<StoredObject>>;
+
+    /// Uploads all of `data`, which is positioned at its start.
+    fn put(&self, name: &str, data: File) -> Result<()>;
+
+    /// Copies an object within the bucket, without downloading it.
+    fn copy(&self, from: &str, to: &str) -> Result<()>;
+
+    fn delete(&self, name: &str) -> Result<()>;
+
+    /// URL of a vault under `prefix`, for errors.
+    fn location(&self, prefix: &str) -> String;
+}
+
+/// The files of a vault under a prefix in a bucket.
+struct BucketStore<C: BucketClient> {
+    client: Arc<C>,
+    /// Empty, or ends with a slash
+    prefix: String,
+}
+
+impl<C: BucketClient> BucketStore<C> {
+    /// Full name in the bucket of a file in the vault.
+    fn object_key(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    fn blob_key(&self, blob_id: &ObjectId) -> String {
+        self.object_key(&bytes_to_hex_str(blob_id))
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        Ok(self.client.metadata(name)?.is_some())
+    }
+
+    fn load_reference_format<F: ReferenceFormat>(&self, mut format: F, key: &Key) -> Result<F> {
+        if let Some(data) = self.client.get(&self.object_key(F::specification().name))? {
+            format.load(&mut BufReader::new(QuocoReader::new(data, key)))?;
+        }
+        Ok(format)
+    }
+
+    fn save_reference_format<F: ReferenceFormat>(&self, format: &F, key: &Key) -> Result<()> {
+        let mut writer = QuocoWriter::new(tempfile::tempfile()?, key);
+        format.save(&mut writer)?;
+        let mut file = writer.finish()?;
+        file.seek(SeekFrom::Start(0))?;
+        self.client
+            .put(&self.object_key(F::specification().name), file)
+    }
+
+    fn save_reference_formats(&self, blobs: &LinkedBlobs, key: &Key) -> Result<()> {
+        self.save_reference_format(&blobs.hashes, key)?;
+        self.save_reference_format(&blobs.names, key)?;
+        self.save_reference_format(&blobs.links, key)?;
+        Ok(())
+    }
+}
+
+impl<C: BucketClient> BlobStore for BucketStore<C> {
+    fn read_blob(&self, blob_id: &ObjectId) -> Result<Box<dyn Read>> {
+        match self.client.get(&self.blob_key(blob_id))? {
+            Some(data) => Ok(data),
+            None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        }
+    }
+
+    fn put_blob(&self, blob_id: &ObjectId, staged: NamedTempFile) -> Result<()> {
+        let mut file = staged.into_file();
+        file.seek(SeekFrom::Start(0))?;
+        self.client.put(&self.blob_key(blob_id), file)
+    }
+
+    fn delete_blob(&self, blob_id: &ObjectId) -> Result<()> {
+        self.client.delete(&self.blob_key(blob_id))
+    }
+
+    fn copy_blob(&self, from: &ObjectId, to: &ObjectId) -> Result<()> {
+        self.client.copy(&self.blob_key(from), &self.blob_key(to))
+    }
+}
+
+/// Reads a stored object through range requests as it's consumed, so decrypting the start of a
+/// large object doesn't download all of it.
+struct RangeReader<C: BucketClient> {
+    client: Arc<C>,
+    name: String,
+    position: u64,
+    window: u64,
+    buffer: Cursor<Vec<u8>>,
+    done: bool,
+}
+
+impl<C: BucketClient> RangeReader<C> {
+    fn new(client: Arc<C>, name: String) -> Self {
+        RangeReader {
+            client,
+            name,
+            position: 0,
+            window: MIN_RANGE_WINDOW,
+            buffer: Cursor::new(Vec::new()),
+            done: false,
+        }
+    }
+}
+
+impl<C: BucketClient> Read for RangeReader<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.buffer.read(buf)?;
+        if read > 0 || self.done || buf.is_empty() {
+            return Ok(read);
+        }
+
+        let data = self
+            .client
+            .get_range(&self.name, self.position, self.window)?;
+        self.done = (data.len() as u64) < self.window;
+        self.position += data.len() as u64;
+        self.window = cmp::min(self.window * 2, MAX_RANGE_WINDOW);
+        self.buffer = Cursor::new(data);
+
+        self.buffer.read(buf)
+    }
+}
+
+/// Stores a vault in an object storage bucket, optionally under a key prefix so several vaults
+/// can share a bucket. See [`GoogleStorageObjectSource`] and [`S3ObjectSource`].
+///
+/// [`GoogleStorageObjectSource`]: crate::object::GoogleStorageObjectSource
+/// [`S3ObjectSource`]: crate::object::S3ObjectSource
+pub struct BucketObjectSource<C: BucketClient> {
+    blobs: LinkedBlobs,
+    store: BucketStore<C>,
+    key: Key,
+    lock: bool,
+}
+
+impl<C: BucketClient> BucketObjectSource<C> {
+    /// Opens the vault under `prefix`, where an empty prefix puts the vault at the root of the
+    /// bucket.
+    pub(crate) fn open_with_client(client: C, prefix: &str, key: &Key) -> Result<Self> {
+        let prefix = if prefix.is_empty() || prefix.ends_with('/') {
+            prefix.to_string()
+        } else {
+            format!("{}/", prefix)
+        };
+
+        let mut source = BucketObjectSource {
+            blobs: LinkedBlobs::default(),
+            store: BucketStore {
+                client: Arc::new(client),
+                prefix,
+            },
+            key: *key,
+            // Not held yet, so a failed open doesn't delete another session's lock on drop
+            lock: false,
+        };
+
+        source.check_no_lock()?;
+        source.touch_lock()?;
+        source.lock = true;
+
+        let store = &source.store;
+        source.blobs = LinkedBlobs::new(
+            store.load_reference_format(Names::new(), key)?,
+            store.load_reference_format(Hashes::new(), key)?,
+            store.load_reference_format(Links::new(), key)?,
+        );
+
+        Ok(source)
+    }
+
+    pub fn unlock(&mut self) -> Result<()> {
+        self.store
+            .client
+            .delete(&self.store.object_key(LOCK_FILE_NAME))?;
+        self.lock = false;
+        Ok(())
+    }
+
+    // TODO: Work out naming/semantic division between this and check_no_lock
+    fn check_lock(&self) -> Result<()> {
+        if !self.lock {
+            return Err(QuocoError::SessionDisposed);
+        }
+        Ok(())
+    }
+
+    /// When enabled, objects with identical contents share a single stored object, so they are
+    /// only uploaded once. Objects that already share storage keep sharing it after this is
+    /// disabled.
+    pub fn set_dedupe(&mut self, enabled: bool) {
+        self.blobs.dedupe = enabled;
+    }
+
+    fn touch_lock(&self) -> Result<()> {
+        self.store.client.put(
+            &self.store.object_key(LOCK_FILE_NAME),
+            tempfile::tempfile()?,
+        )
+    }
+
+    fn check_no_lock(&self) -> Result<()> {
+        if self.store.exists(&self.store.object_key(LOCK_FILE_NAME))? {
+            return Err(QuocoError::SessionPathLocked(
+                self.store.client.location(&self.store.prefix),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<C: BucketClient> ObjectSource for BucketObjectSource<C> {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
+        self.check_lock()?;
+
+        Ok(Box::new(QuocoReader::new(
+            self.store.read_blob(&self.blobs.blob_id(id))?,
+            &self.key,
+        )))
+    }
+
+    fn object_range(&self, id: &ObjectId, offset: u64, len: u64) -> Result<Box<dyn Read>> {
+        self.check_lock()?;
+
+        let name = self.store.blob_key(&self.blobs.blob_id(id));
+        if !self.store.exists(&name)? {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        let reader = RangeReader::new(self.store.client.clone(), name);
+        read_range(Box::new(QuocoReader::new(reader, &self.key)), offset, len)
+    }
+
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.store, &self.key, None)
+    }
+
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.store, &self.key, Some(*id))
+    }
+
+    fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>> {
+        self.check_lock()?;
+
+        let (store, key) = (&self.store, &self.key);
+        self.blobs.apply_batch(store, key, batch, |blobs| {
+            store.save_reference_formats(blobs, key)
+        })
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.copy(&self.store, id)
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        self.check_lock()?;
+
+        let blob_id = self.blobs.blob_id(id);
+        Ok(!self.blobs.is_released(&blob_id)
+            && self.store.exists(&self.store.blob_key(&blob_id))?)
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.delete(&self.store, id)
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.write(&self.store, &self.key, None, reader)
+    }
+
+    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs
+            .write(&self.store, &self.key, Some(*id), reader)
+            .map(|_| ())
+    }
+
+    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.hashes.get_hash(id))
+    }
+
+    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_name(id))
+    }
+
+    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_id(name))
+    }
+
+    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.set_name(id, name);
+
+        Ok(())
+    }
+
+    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.remove_name(id);
+
+        Ok(())
+    }
+
+    fn last_updated(&self) -> &SystemTime {
+        self.blobs.hashes.get_last_updated()
+    }
+
+    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
+        self.blobs.hashes.get_ids()
+    }
+
+    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
+        self.blobs.names.get_ids()
+    }
+
+    fn names(&self) -> &Names {
+        &self.blobs.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.blobs.hashes
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.replace(names, hashes);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.check_lock()?;
+
+        let (store, key) = (&self.store, &self.key);
+        self.blobs
+            .flush(store, |blobs| store.save_reference_formats(blobs, key))
+    }
+
+    fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>> {
+        self.check_lock()?;
+
+        let stored = self
+            .store
+            .client
+            .metadata(&self.store.blob_key(&self.blobs.blob_id(id)))?;
+        let info = match (
+            ObjectInfo::from_metadata(id, &self.blobs.names, &self.blobs.hashes),
+            &stored,
+        ) {
+            (Some(info), _) => info,
+            (None, Some(_)) if !self.blobs.is_released(id) => ObjectInfo::orphan(id),
+            (None, _) => return Ok(None),
+        };
+
+        Ok(Some(match stored {
+            Some(stored) => stored.fill_info(info),
+            None => info,
+        }))
+    }
+
+    fn list(&self) -> Result<Box<dyn Iterator<Item = ObjectInfo> + '_>> {
+        self.check_lock()?;
+
+        // One listing request instead of a metadata request per object
+        let prefix = &self.store.prefix;
+        let stored: HashMap<ObjectId, StoredObject> = self
+            .store
+            .client
+            .list(prefix)?
+            .into_iter()
+            .filter_map(|object| {
+                let id = hex_str_to_object_id(object.name.strip_prefix(prefix.as_str())?)?;
+                Some((id, object))
+            })
+            .collect();
+
+        let names = &self.blobs.names;
+        let hashes = &self.blobs.hashes;
+        let known_ids: HashSet<&ObjectId> = hashes.get_ids().chain(names.get_ids()).collect();
+        let blob_ids: HashSet<ObjectId> =
+            known_ids.iter().map(|id| self.blobs.blob_id(id)).collect();
+
+        let mut infos: Vec<ObjectInfo> = known_ids
+            .into_iter()
+            .map(|id| {
+                let info = ObjectInfo::from_metadata(id, names, hashes).unwrap();
+                match stored.get(&self.blobs.blob_id(id)) {
+                    Some(stored) => stored.fill_info(info),
+                    None => info,
+                }
+            })
+            .collect();
+        infos.extend(
+            stored
+                .iter()
+                .filter(|(id, _)| !blob_ids.contains(*id) && !self.blobs.is_released(id))
+                .map(|(id, stored)| stored.fill_info(ObjectInfo::orphan(id))),
+        );
+
+        Ok(Box::new(infos.into_iter()))
+    }
+}
+
+impl<C: BucketClient> Drop for BucketObjectSource<C> {
+    fn drop(&mut self) {
+        // TODO: Based on BufWriter's Drop impl, I think it might be an anti-pattern to do anything
+        //  that could raise errors in drop. Look into this and refactor accordingly.
+        if self.lock {
+            self.unlock()
+                .expect("Failed to release lock. You may have to release it manually.");
+        }
+    }
+}
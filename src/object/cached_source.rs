@@ -1,11 +1,16 @@
-use crate::object::{BoxedObjectSource, ObjectHash, ObjectId, ObjectSource};
+use crate::formats::{Hashes, Names};
+use crate::object::{
+    Batch, BatchOperation, BoxedObjectSource, ObjectHash, ObjectId, ObjectInfo, ObjectSource,
+    ObjectWriter,
+};
 use crate::{ReadSeek, Result};
+use std::cmp;
 use std::collections::hash_map::Keys;
 use std::collections::{HashMap, VecDeque};
 use std::io;
 use std::io::{Cursor, Read, Seek, SeekFrom};
-use std::ops::Index;
 use std::str;
+use std::sync::{Mutex, MutexGuard};
 use std::time::SystemTime;
 
 /// Max cache size in bytes (2 GiB)
@@ -13,34 +18,25 @@ const MAX_CACHE_SIZE: usize = 1024 * 1024 * 1024 * 2;
 
 pub struct CachedObjectSource {
     inner: BoxedObjectSource,
-    cache: HashMap<ObjectId, Vec<u8>>,
+    /// Behind a lock so objects can be read, and cached, through a shared reference
+    cache: Mutex<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    objects: HashMap<ObjectId, Vec<u8>>,
     insertion_order: VecDeque<ObjectId>,
     /// Total size of all cached objects in bytes
     size: usize,
 }
 
-impl CachedObjectSource {
-    pub fn new(source: BoxedObjectSource) -> Self {
-        CachedObjectSource {
-            inner: source,
-            cache: HashMap::new(),
-            insertion_order: VecDeque::new(),
-            size: 0,
-        }
-    }
-
-    pub fn invalidate(&mut self) {
-        self.cache.clear();
-        self.insertion_order.clear();
-        self.size = 0;
-    }
-
+impl Cache {
     fn remove(&mut self, id: &ObjectId) -> Option<Vec<u8>> {
-        if !self.cache.contains_key(id) {
+        if !self.objects.contains_key(id) {
             return None;
         }
 
-        let entry = match self.cache.remove(id) {
+        let entry = match self.objects.remove(id) {
             Some(entry) => entry,
             None => return None,
         };
@@ -59,76 +55,124 @@ impl CachedObjectSource {
         let existing_data = self.remove(id);
 
         self.size += data.len();
-        self.cache.insert(*id, data);
+        self.objects.insert(*id, data);
         self.insertion_order.push_front(*id);
         self.cull();
 
         existing_data
     }
 
-    fn insert_reader<InR: Read>(
-        &mut self,
-        id: &ObjectId,
-        reader: &mut InR,
-    ) -> io::Result<Option<Vec<u8>>> {
-        let mut data = Vec::new();
-        reader.read_to_end(&mut data)?;
-        Ok(self.insert(id, data))
-    }
-
-    fn load_into_cache(&mut self, id: &ObjectId) -> Result<()> {
-        let mut object_reader = self.inner.object(id)?;
-        self.insert_reader(id, &mut object_reader)?;
-
-        Ok(())
-    }
-
     /// Removes object entries until either the total cache size is under [`MAX_CACHE_SIZE`] or
     /// there is only one entry left.
     fn cull(&mut self) {
-        while self.size > MAX_CACHE_SIZE && self.cache.len() > 1 {
+        while self.size > MAX_CACHE_SIZE && self.objects.len() > 1 {
             self.size -= self
-                .cache
+                .objects
                 .remove(&self.insertion_order.pop_back().unwrap())
                 .unwrap()
                 .len();
         }
     }
+}
 
-    pub fn object_cached(&mut self, id: &ObjectId) -> Result<Cursor<Vec<u8>>> {
-        if !self.cache.contains_key(id) {
-            self.load_into_cache(id)?;
+impl CachedObjectSource {
+    pub fn new(source: BoxedObjectSource) -> Self {
+        CachedObjectSource {
+            inner: source,
+            cache: Mutex::new(Cache::default()),
         }
+    }
 
-        // TODO: See if this irresponsibly fills memory
-        Ok(Cursor::new(self.cache[id].clone()))
+    fn cache(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap()
     }
 
-    pub fn object_cached_boxed(&mut self, id: &ObjectId) -> Result<Box<Cursor<Vec<u8>>>> {
-        let mut object_box = Box::default();
-        std::mem::swap(&mut self.object_cached(id)?, &mut object_box);
-        Ok(object_box)
+    pub fn invalidate(&mut self) {
+        *self.cache() = Cache::default();
     }
-}
 
-impl Index<&ObjectId> for CachedObjectSource {
-    type Output = [u8];
+    fn remove(&mut self, id: &ObjectId) -> Option<Vec<u8>> {
+        self.cache().remove(id)
+    }
+
+    fn insert_reader<InR: Read>(
+        &self,
+        id: &ObjectId,
+        reader: &mut InR,
+    ) -> io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(self.cache().insert(id, data))
+    }
+
+    pub fn object_cached(&self, id: &ObjectId) -> Result<Cursor<Vec<u8>>> {
+        if let Some(data) = self.cache().objects.get(id) {
+            // TODO: See if this irresponsibly fills memory
+            return Ok(Cursor::new(data.clone()));
+        }
+
+        // The cache isn't locked while reading, so other objects can be read meanwhile
+        let mut data = Vec::new();
+        self.inner.object(id)?.read_to_end(&mut data)?;
+        self.cache().insert(id, data.clone());
+        Ok(Cursor::new(data))
+    }
 
-    fn index(&self, index: &ObjectId) -> &Self::Output {
-        &self.cache[index]
+    pub fn object_cached_boxed(&self, id: &ObjectId) -> Result<Box<Cursor<Vec<u8>>>> {
+        Ok(Box::new(self.object_cached(id)?))
     }
 }
 
 impl ObjectSource for CachedObjectSource {
-    fn object(&mut self, id: &ObjectId) -> Result<Box<dyn Read>> {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
         self.object_cached_boxed(id).map(|r| r as Box<dyn Read>)
     }
 
+    fn object_range(&self, id: &ObjectId, offset: u64, len: u64) -> Result<Box<dyn Read>> {
+        // A range doesn't make the whole object worth caching, but a cached one is free to slice
+        if let Some(data) = self.cache().objects.get(id) {
+            let start = cmp::min(offset, data.len() as u64) as usize;
+            let end = cmp::min(offset.saturating_add(len), data.len() as u64) as usize;
+            return Ok(Box::new(Cursor::new(data[start..end].to_vec())));
+        }
+        self.inner.object_range(id, offset, len)
+    }
+
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.inner.begin_object()
+    }
+
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        // Dropped up front since there's no way to hear back once the writer is committed
+        self.remove(id);
+        self.inner.begin_modify_object(id)
+    }
+
+    fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>> {
+        for operation in batch.operations() {
+            match operation {
+                BatchOperation::Modify { id, .. } | BatchOperation::Delete { id } => {
+                    self.remove(id);
+                }
+                BatchOperation::Create { .. } | BatchOperation::Rename { .. } => {}
+            }
+        }
+        self.inner.apply_batch(batch)
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        self.inner.copy_object(id)
+    }
+
+    fn rename_object(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.inner.rename_object(id, name)
+    }
+
     fn object_exists(&self, id: &ObjectId) -> Result<bool> {
         // Checks if key exists in cache first because inner source might have to check the
         //  filesystem. Maybe it would be a good idea to store a cached list of all object IDs,
         //  but I doubt it would provide any noticeable performance boost ever.
-        Ok(self.cache.contains_key(id) || self.inner.object_exists(id)?)
+        Ok(self.cache().objects.contains_key(id) || self.inner.object_exists(id)?)
     }
 
     fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
@@ -185,7 +229,29 @@ impl ObjectSource for CachedObjectSource {
         self.inner.names_ids()
     }
 
+    fn names(&self) -> &Names {
+        self.inner.names()
+    }
+
+    fn hashes(&self) -> &Hashes {
+        self.inner.hashes()
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        // Objects left out of the new formats are gone, so they mustn't be served from the cache
+        self.invalidate();
+        self.inner.replace_reference_formats(names, hashes)
+    }
+
     fn flush(&mut self) -> Result<()> {
         self.inner.flush()
     }
+
+    fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>> {
+        self.inner.stat(id)
+    }
+
+    fn list(&self) -> Result<Box<dyn Iterator<Item = ObjectInfo> + '_>> {
+        self.inner.list()
+    }
 }
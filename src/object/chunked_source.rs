@@ -0,0 +1,343 @@
+use crate::formats::{Hashes, Manifest, Manifests, Names, ReferenceFormat};
+use crate::object::{
+    BoxedObjectSource, Chunker, Key, ObjectHash, ObjectId, ObjectSource, KEY_LENGTH, UUID_LENGTH,
+};
+use crate::util::{bytes_to_hex_str, keyed_hash, sha256};
+use crate::{ReadSeek, Result};
+use std::collections::hash_map::Keys;
+use std::collections::HashSet;
+use std::io;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// ID of the inner object holding [`Manifests`]. Random (v4) UUIDs always have version bits set,
+/// so this can't collide with object IDs.
+const MANIFESTS_ID: ObjectId = [0xff; UUID_LENGTH];
+const CHUNK_KEY_CONTEXT: &[u8] = b"quocofs chunk ids";
+
+/// Stores objects as content-defined chunks in an inner source, so that modifying part of a
+/// large object only writes (and syncs) the chunks that changed. Identical chunks are stored once
+/// across all objects.
+///
+/// Chunks are stored in the inner source as ordinary objects whose IDs are a keyed hash of their
+/// plaintext, so chunk IDs don't reveal anything about their contents without the key. Names are
+/// kept by the inner source under the outer object IDs.
+///
+/// Objects are read a chunk at a time, so reading a large object doesn't hold all of it in memory.
+/// Readers share the inner source, so an object modified or deleted while it's being read may
+/// fail partway through.
+pub struct ChunkedObjectSource {
+    inner: Arc<Mutex<BoxedObjectSource>>,
+    manifests: Manifests,
+    /// Hashes of whole objects, kept separately from the inner source's hashes of chunks
+    hashes: Hashes,
+    /// A copy of the inner source's names, which can't be borrowed through the lock. Names are
+    /// always changed in the inner source first.
+    names: Names,
+    /// The inner source's last update, copied after every change for the same reason
+    last_updated: SystemTime,
+    chunk_key: Key,
+    /// Whether manifests have changed since they were last written
+    manifests_changed: bool,
+    /// Chunks no longer used by any manifest, which are only deleted once the manifests that
+    /// stopped using them are written
+    released_chunks: HashSet<ObjectId>,
+}
+
+impl ChunkedObjectSource {
+    pub fn open(source: BoxedObjectSource, key: &Key) -> Result<Self> {
+        let mut chunk_key = [0u8; KEY_LENGTH];
+        keyed_hash(key, CHUNK_KEY_CONTEXT, &mut chunk_key)?;
+
+        let mut manifests = Manifests::new();
+        if source.object_hash(&MANIFESTS_ID)?.is_some() {
+            manifests.load(&mut BufReader::new(source.object(&MANIFESTS_ID)?))?;
+        }
+
+        let mut hashes = Hashes::new();
+        for (id, manifest) in manifests.iter() {
+            hashes.insert(id, &manifest.hash);
+        }
+
+        Ok(ChunkedObjectSource {
+            names: source.names().clone(),
+            last_updated: *source.last_updated(),
+            inner: Arc::new(Mutex::new(source)),
+            manifests,
+            hashes,
+            chunk_key,
+            manifests_changed: false,
+            released_chunks: HashSet::new(),
+        })
+    }
+
+    fn inner(&self) -> MutexGuard<'_, BoxedObjectSource> {
+        self.inner.lock().unwrap()
+    }
+
+    /// Runs `change` on the inner source, then copies its last update time.
+    fn change_inner<T>(
+        &mut self,
+        change: impl FnOnce(&mut BoxedObjectSource) -> Result<T>,
+    ) -> Result<T> {
+        let mut inner = self.inner.lock().unwrap();
+        let result = change(&mut inner);
+        self.last_updated = *inner.last_updated();
+        result
+    }
+
+    fn chunk_id(&self, chunk: &[u8]) -> Result<ObjectId> {
+        let mut chunk_id = [0u8; UUID_LENGTH];
+        keyed_hash(&self.chunk_key, chunk, &mut chunk_id)?;
+        Ok(chunk_id)
+    }
+
+    fn modify_object_unchecked<R: Read + Seek>(
+        &mut self,
+        id: &ObjectId,
+        reader: &mut R,
+    ) -> Result<()> {
+        let hash = sha256(reader)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut chunks = Vec::new();
+        for chunk in Chunker::new(reader) {
+            let chunk = chunk?;
+            let chunk_id = self.chunk_id(&chunk)?;
+
+            // Chunk IDs are derived from contents, so an existing chunk never needs rewriting
+            if self.inner().object_hash(&chunk_id)?.is_none() {
+                self.change_inner(|inner| {
+                    inner.modify_object(
+                        &chunk_id,
+                        &mut (Box::new(Cursor::new(chunk)) as Box<dyn ReadSeek>),
+                    )
+                })?;
+            }
+            chunks.push(chunk_id);
+        }
+
+        self.hashes.insert(id, &hash);
+        self.manifests_changed = true;
+        if let Some(previous) = self.manifests.insert(id, Manifest { hash, chunks }) {
+            self.release_chunks(previous);
+        }
+
+        Ok(())
+    }
+
+    /// Queues chunks of a removed or replaced manifest that no other manifest uses for deletion
+    /// on the next flush.
+    fn release_chunks(&mut self, manifest: Manifest) {
+        for chunk_id in manifest.chunks {
+            if !self.manifests.contains_chunk(&chunk_id) {
+                self.released_chunks.insert(chunk_id);
+            }
+        }
+    }
+}
+
+/// Reads an object's chunks in order, only opening each one once the previous one is used up.
+struct ChunkReader {
+    inner: Arc<Mutex<BoxedObjectSource>>,
+    chunk_ids: std::vec::IntoIter<ObjectId>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(current) = &mut self.current {
+                let read = current.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+            }
+            let chunk_id = match self.chunk_ids.next() {
+                Some(chunk_id) => chunk_id,
+                None => return Ok(0),
+            };
+            let chunk = self.inner.lock().unwrap().object(&chunk_id);
+            self.current = Some(chunk.map_err(std::io::Error::from)?);
+        }
+    }
+}
+
+impl ObjectSource for ChunkedObjectSource {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
+        let chunk_ids = self
+            .manifests
+            .get(id)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?
+            .chunks
+            .clone();
+
+        Ok(Box::new(ChunkReader {
+            inner: self.inner.clone(),
+            chunk_ids: chunk_ids.into_iter(),
+            current: None,
+        }))
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        Ok(self.manifests.get(id).is_some())
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.hashes.remove(id);
+        self.remove_object_name(id)?;
+        if let Some(manifest) = self.manifests.remove(id) {
+            self.manifests_changed = true;
+            self.release_chunks(manifest);
+        }
+        Ok(())
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        let new_id = {
+            let uuid = Uuid::new_v4();
+            *uuid.as_bytes()
+        };
+        self.modify_object_unchecked(&new_id, reader)?;
+
+        Ok(new_id)
+    }
+
+    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
+        self.modify_object_unchecked(id, reader)
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        // Chunks are shared between manifests, so a copy is just another manifest
+        let manifest = self
+            .manifests
+            .get(id)
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?
+            .clone();
+        let new_id = *Uuid::new_v4().as_bytes();
+        self.hashes.insert(&new_id, &manifest.hash);
+        self.manifests.insert(&new_id, manifest);
+        self.manifests_changed = true;
+
+        Ok(new_id)
+    }
+
+    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
+        Ok(self.hashes.get_hash(id))
+    }
+
+    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
+        Ok(self.names.get_name(id))
+    }
+
+    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
+        Ok(self.names.get_id(name))
+    }
+
+    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.change_inner(|inner| inner.set_object_name(id, name))?;
+        self.names.insert(id, name);
+        Ok(())
+    }
+
+    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        self.change_inner(|inner| inner.remove_object_name(id))?;
+        self.names.remove(id);
+        Ok(())
+    }
+
+    fn last_updated(&self) -> &SystemTime {
+        &self.last_updated
+    }
+
+    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
+        self.hashes.get_ids()
+    }
+
+    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
+        self.names.get_ids()
+    }
+
+    fn names(&self) -> &Names {
+        &self.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.hashes
+    }
+
+    /// Hashes of whole objects are rebuilt from the manifests when the source is opened, so
+    /// imported hashes have to match the manifests. Objects left out of `hashes` are deleted
+    /// along with any chunks nothing else uses.
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        for (id, hash) in hashes.iter() {
+            if self.manifests.get(id).map(|manifest| &manifest.hash) != Some(hash) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "hash of object {} doesn't match its chunks",
+                        bytes_to_hex_str(id)
+                    ),
+                )
+                .into());
+            }
+        }
+
+        // Hashes of chunks belong to the inner source and are kept as they are
+        self.change_inner(|inner| {
+            let inner_hashes = inner.hashes().clone();
+            inner.replace_reference_formats(names.clone(), inner_hashes)
+        })?;
+
+        let left_out: Vec<ObjectId> = self
+            .manifests
+            .iter()
+            .map(|(id, _)| *id)
+            .filter(|id| hashes.get_hash(id).is_none())
+            .collect();
+        for id in left_out {
+            if let Some(manifest) = self.manifests.remove(&id) {
+                self.manifests_changed = true;
+                self.release_chunks(manifest);
+            }
+        }
+        self.names = names;
+        self.hashes = hashes;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.manifests_changed {
+            let mut manifests_data = Vec::new();
+            self.manifests.save(&mut manifests_data)?;
+            self.change_inner(|inner| {
+                inner.modify_object(
+                    &MANIFESTS_ID,
+                    &mut (Box::new(Cursor::new(manifests_data)) as Box<dyn ReadSeek>),
+                )
+            })?;
+            self.manifests_changed = false;
+        }
+        self.change_inner(|inner| inner.flush())?;
+
+        // Only now that no written manifest uses them can the released chunks go. Chunks used
+        // again since they were released are kept.
+        let manifests = &self.manifests;
+        let released: Vec<ObjectId> = self
+            .released_chunks
+            .drain()
+            .filter(|chunk_id| !manifests.contains_chunk(chunk_id))
+            .collect();
+        if released.is_empty() {
+            return Ok(());
+        }
+        self.change_inner(|inner| {
+            for chunk_id in &released {
+                inner.delete_object(chunk_id)?;
+            }
+            inner.flush()
+        })
+    }
+}
@@ -0,0 +1,92 @@
+use std::io;
+use std::io::Read;
+
+pub const MIN_CONTENT_CHUNK_LENGTH: usize = 16 * 1024;
+pub const MAX_CONTENT_CHUNK_LENGTH: usize = 256 * 1024;
+/// Chunk boundaries fall where the low 16 bits of the rolling hash are zero, giving chunks of
+/// 64 KiB on average (plus the minimum length).
+const BOUNDARY_MASK: u64 = (1 << 16) - 1;
+
+const GEAR: [u64; 256] = gear_table();
+
+/// Fills the gear table with splitmix64 output. The values only need to be well mixed, but they
+/// must never change, or every existing chunk boundary would move.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Splits a reader into content-defined chunks using a gear rolling hash, so that an edit only
+/// changes the chunks around it instead of shifting every boundary after it.
+pub struct Chunker<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(reader: R) -> Self {
+        Chunker {
+            inner: reader,
+            buf: Vec::with_capacity(MAX_CONTENT_CHUNK_LENGTH),
+            eof: false,
+        }
+    }
+
+    fn fill_buf(&mut self) -> io::Result<()> {
+        while !self.eof && self.buf.len() < MAX_CONTENT_CHUNK_LENGTH {
+            let remaining = (MAX_CONTENT_CHUNK_LENGTH - self.buf.len()) as u64;
+            if (&mut self.inner)
+                .take(remaining)
+                .read_to_end(&mut self.buf)?
+                == 0
+            {
+                self.eof = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn boundary(&self) -> usize {
+        if self.buf.len() <= MIN_CONTENT_CHUNK_LENGTH {
+            return self.buf.len();
+        }
+
+        let mut hash: u64 = 0;
+        for (i, byte) in self.buf.iter().enumerate().skip(MIN_CONTENT_CHUNK_LENGTH) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & BOUNDARY_MASK == 0 {
+                return i + 1;
+            }
+        }
+
+        self.buf.len()
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(err) = self.fill_buf() {
+            return Some(Err(err));
+        }
+
+        if self.buf.is_empty() {
+            return None;
+        }
+
+        let rest = self.buf.split_off(self.boundary());
+        Some(Ok(std::mem::replace(&mut self.buf, rest)))
+    }
+}
@@ -1,36 +1,133 @@
 use crate::error::QuocoError;
-use crate::formats::{Hashes, Names, ReferenceFormat};
+use crate::formats::{Hashes, Links, Names, ObjectLayout, ReferenceFormat, VaultConfig};
+use crate::object::blob_store::{BlobStore, LinkedBlobs};
 use crate::object::finish::Finish;
-use crate::object::{Key, ObjectHash, ObjectId, ObjectSource, QuocoReader, QuocoWriter};
-use crate::util::{bytes_to_hex_str, sha256};
+use crate::object::source::read_range;
+use crate::object::{
+    Batch, Key, ObjectHash, ObjectId, ObjectInfo, ObjectSource, ObjectWriter, QuocoReader,
+    QuocoWriter,
+};
+use crate::util::{bytes_to_hex_str, hex_str_to_object_id};
 use crate::{ReadSeek, Result};
 use std::collections::hash_map::Keys;
-use std::fs::{File, OpenOptions};
-use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::str;
 use std::time::SystemTime;
 use std::{fs, io};
-use uuid::Uuid;
+use tempfile::NamedTempFile;
 
 pub struct FsObjectSource {
-    names: Names,
-    hashes: Hashes,
-    path: PathBuf,
+    blobs: LinkedBlobs,
+    config: VaultConfig,
+    store: FsBlobStore,
     key: Key,
     lock: bool,
 }
 
 pub const LOCK_FILE_NAME: &str = "quoco.lock";
+/// Suffix of files objects are written to before they're committed.
+const TEMP_FILE_SUFFIX: &str = ".partial";
+
+/// The object files in a vault directory.
+struct FsBlobStore {
+    path: PathBuf,
+    /// Layout new files are written in
+    layout: ObjectLayout,
+}
+
+impl FsBlobStore {
+    fn layout_path(path: &Path, layout: ObjectLayout, blob_id: &ObjectId) -> PathBuf {
+        let hex = bytes_to_hex_str(blob_id);
+        match layout {
+            ObjectLayout::Flat => path.join(hex),
+            ObjectLayout::Sharded => path.join(&hex[0..2]).join(&hex[2..4]).join(hex),
+        }
+    }
+
+    /// Where a blob is stored. Blobs written before the vault switched layouts may still be in
+    /// the old one.
+    fn stored_blob_path(&self, blob_id: &ObjectId) -> PathBuf {
+        let path = Self::layout_path(&self.path, self.layout, blob_id);
+        if path.exists() {
+            return path;
+        }
+        let old_path = Self::layout_path(&self.path, self.layout.other(), blob_id);
+        if old_path.exists() {
+            old_path
+        } else {
+            path
+        }
+    }
+
+    /// Where a blob should be written, creating the directories it goes in.
+    fn new_blob_path(&self, blob_id: &ObjectId) -> Result<PathBuf> {
+        let path = Self::layout_path(&self.path, self.layout, blob_id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(path)
+    }
+
+    /// Removes the copy of a blob left in the old layout once it's been rewritten in the current
+    /// one.
+    fn remove_old_blob(&self, blob_id: &ObjectId) -> Result<()> {
+        let old_path = Self::layout_path(&self.path, self.layout.other(), blob_id);
+        match fs::remove_file(old_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn read_blob(&self, blob_id: &ObjectId) -> Result<Box<dyn Read>> {
+        Ok(Box::new(File::open(self.stored_blob_path(blob_id))?))
+    }
+
+    /// Stages blobs in the vault directory, so storing them is just a rename.
+    fn stage_blob(&self) -> Result<NamedTempFile> {
+        Ok(tempfile::Builder::new()
+            .suffix(TEMP_FILE_SUFFIX)
+            .tempfile_in(&self.path)?)
+    }
+
+    fn put_blob(&self, blob_id: &ObjectId, staged: NamedTempFile) -> Result<()> {
+        staged
+            .persist(self.new_blob_path(blob_id)?)
+            .map_err(|err| err.error)?;
+        self.remove_old_blob(blob_id)
+    }
+
+    fn delete_blob(&self, blob_id: &ObjectId) -> Result<()> {
+        fs::remove_file(self.stored_blob_path(blob_id))?;
+        Ok(())
+    }
+
+    fn copy_blob(&self, from: &ObjectId, to: &ObjectId) -> Result<()> {
+        fs::copy(self.stored_blob_path(from), self.new_blob_path(to)?)?;
+        Ok(())
+    }
+}
 
 impl FsObjectSource {
     pub fn open(path: &Path, key: &Key) -> Result<Self> {
         Self::check_no_lock(path)?;
 
+        let config = Self::load_reference_format(VaultConfig::new(), path, key)?;
         let source = FsObjectSource {
-            path: path.into(),
-            names: FsObjectSource::load_reference_format(Names::new(), path, key)?,
-            hashes: FsObjectSource::load_reference_format(Hashes::new(), path, key)?,
+            blobs: LinkedBlobs::new(
+                Self::load_reference_format(Names::new(), path, key)?,
+                Self::load_reference_format(Hashes::new(), path, key)?,
+                Self::load_reference_format(Links::new(), path, key)?,
+            ),
+            store: FsBlobStore {
+                path: path.into(),
+                layout: config.layout,
+            },
+            config,
             key: *key,
             lock: true,
         };
@@ -42,7 +139,7 @@ impl FsObjectSource {
     }
 
     pub fn unlock(&mut self) -> Result<()> {
-        fs::remove_file(self.path.join(LOCK_FILE_NAME))?;
+        fs::remove_file(self.store.path.join(LOCK_FILE_NAME))?;
         self.lock = false;
         Ok(())
     }
@@ -55,28 +152,109 @@ impl FsObjectSource {
         Ok(())
     }
 
-    fn modify_object_unchecked<R: Read + Seek>(
-        &mut self,
-        id: &ObjectId,
-        reader: &mut R,
-    ) -> Result<()> {
-        let object_file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(self.path.join(Path::new(&bytes_to_hex_str(id))))?;
-        let mut writer = QuocoWriter::new(object_file, &self.key);
-
-        self.hashes.insert(id, &sha256(reader)?);
-        reader.seek(SeekFrom::Start(0))?;
-        io::copy(reader, &mut writer)
-            .expect("Error when attempting to modify object on filesystem.");
-        writer
-            .finish()
-            .expect("Couldn't finish writing to object on filesystem.");
+    /// When enabled, objects with identical contents share a single encrypted file. Objects
+    /// that already share files keep sharing them after this is disabled.
+    pub fn set_dedupe(&mut self, enabled: bool) {
+        self.blobs.dedupe = enabled;
+    }
+
+    pub fn layout(&self) -> ObjectLayout {
+        self.config.layout
+    }
+
+    /// Switches the vault to `layout`, moving every object file into place, and saves the choice
+    /// in the vault's config so objects written from now on follow it. Returns the number of
+    /// files moved.
+    ///
+    /// Reads fall back to the other layout, so the vault stays usable if moving is interrupted,
+    /// and switching again picks up where it left off.
+    pub fn set_layout(&mut self, layout: ObjectLayout) -> Result<usize> {
+        self.check_lock()?;
+
+        let path = &self.store.path;
+        let mut moved = 0;
+        for (blob_id, file) in Self::blob_files(path)? {
+            let target = FsBlobStore::layout_path(path, layout, &blob_id);
+            if file == target {
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::rename(&file, &target)?;
+            moved += 1;
+        }
+        if layout == ObjectLayout::Flat {
+            Self::remove_empty_shards(path)?;
+        }
+
+        self.store.layout = layout;
+        self.config.layout = layout;
+        self.save_reference_format(&self.config)?;
+        Ok(moved)
+    }
+
+    fn blob_path(&self, id: &ObjectId) -> PathBuf {
+        self.store.stored_blob_path(&self.blobs.blob_id(id))
+    }
+
+    /// Every object file in the vault at `path`, in either layout.
+    fn blob_files(path: &Path) -> Result<Vec<(ObjectId, PathBuf)>> {
+        let mut files = Vec::new();
+        let mut directories = vec![(path.to_path_buf(), 0)];
+        while let Some((directory, depth)) = directories.pop() {
+            for entry in fs::read_dir(&directory)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name = match name.to_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                if let Some(id) = hex_str_to_object_id(name) {
+                    files.push((id, entry.path()));
+                } else if depth < 2 && Self::is_shard_name(name) && entry.file_type()?.is_dir() {
+                    directories.push((entry.path(), depth + 1));
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    fn is_shard_name(name: &str) -> bool {
+        name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    /// Cleans up shard directories after moving everything out of them. Directories that still
+    /// hold something are left alone.
+    fn remove_empty_shards(path: &Path) -> Result<()> {
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let is_shard = entry.file_name().to_str().is_some_and(Self::is_shard_name);
+            if !is_shard || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for inner in fs::read_dir(entry.path())? {
+                let _ = fs::remove_dir(inner?.path());
+            }
+            let _ = fs::remove_dir(entry.path());
+        }
         Ok(())
     }
 
+    /// Fills in the size and timestamps of a stored object, leaving them empty if its file is
+    /// missing.
+    fn with_file_info(mut info: ObjectInfo, path: &Path) -> Result<ObjectInfo> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(info),
+            Err(err) => return Err(err.into()),
+        };
+        info.size = Some(metadata.len());
+        info.created = metadata.created().ok();
+        info.modified = metadata.modified().ok();
+        Ok(info)
+    }
+
     fn load_reference_format<F: ReferenceFormat>(
         mut format: F,
         path: &Path,
@@ -91,14 +269,69 @@ impl FsObjectSource {
     }
 
     fn save_reference_format<F: ReferenceFormat>(&self, format: &F) -> Result<()> {
-        let path = self.path.join(F::specification().name);
-        let mut file_writer = QuocoWriter::new(File::create(&path)?, &self.key);
+        Self::write_reference_format(format, &self.store.path, &self.key)
+    }
+
+    fn save_reference_formats(blobs: &LinkedBlobs, path: &Path, key: &Key) -> Result<()> {
+        Self::write_reference_format(&blobs.hashes, path, key)?;
+        Self::write_reference_format(&blobs.names, path, key)?;
+        Self::write_reference_format(&blobs.links, path, key)?;
+        Ok(())
+    }
+
+    fn write_reference_format<F: ReferenceFormat>(
+        format: &F,
+        path: &Path,
+        key: &Key,
+    ) -> Result<()> {
+        let path = path.join(F::specification().name);
+        let mut file_writer = QuocoWriter::new(File::create(&path)?, key);
 
         format.save(&mut file_writer)?;
         file_writer.finish()?;
         Ok(())
     }
 
+    /// Rewrites reference format files at `path` that were saved by an older version of quocofs,
+    /// copying each original to `<name>.v<version>.bak` first. Returns the paths of the backups.
+    ///
+    /// Older formats are also upgraded transparently by [`open`](Self::open), but only written
+    /// back on the next flush and without a backup.
+    pub fn migrate(path: &Path, key: &Key) -> Result<Vec<PathBuf>> {
+        Self::check_no_lock(path)?;
+
+        Ok(vec![
+            Self::migrate_reference_format::<Names>(path, key)?,
+            Self::migrate_reference_format::<Hashes>(path, key)?,
+        ]
+        .into_iter()
+        .flatten()
+        .collect())
+    }
+
+    fn migrate_reference_format<F: ReferenceFormat + Default>(
+        path: &Path,
+        key: &Key,
+    ) -> Result<Option<PathBuf>> {
+        let specification = F::specification();
+        let format_path = path.join(specification.name);
+        if !format_path.exists() {
+            return Ok(None);
+        }
+
+        let version = F::read_header(&mut QuocoReader::new(File::open(&format_path)?, key))?;
+        if version == specification.version {
+            return Ok(None);
+        }
+
+        let format = Self::load_reference_format(F::default(), path, key)?;
+        let backup_path = path.join(format!("{}.v{}.bak", specification.name, version));
+        fs::copy(&format_path, &backup_path)?;
+        Self::write_reference_format(&format, path, key)?;
+
+        Ok(Some(backup_path))
+    }
+
     pub fn touch_lock(path: &Path) -> Result<()> {
         fs::OpenOptions::new()
             .create(true)
@@ -117,72 +350,97 @@ impl FsObjectSource {
 }
 
 impl ObjectSource for FsObjectSource {
-    fn object(&mut self, id: &ObjectId) -> Result<Box<dyn Read>> {
-        let object_path = self.path.join(&bytes_to_hex_str(id));
-
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
         Ok(Box::new(QuocoReader::new(
-            fs::File::open(object_path)?,
+            self.store.read_blob(&self.blobs.blob_id(id))?,
             &self.key,
         )))
     }
 
-    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+    fn object_range(&self, id: &ObjectId, offset: u64, len: u64) -> Result<Box<dyn Read>> {
         self.check_lock()?;
 
-        Ok(self.path.join(Path::new(&bytes_to_hex_str(id))).exists())
+        // Decrypts straight from the file, so nothing past the range is ever read from disk
+        let file = BufReader::new(fs::File::open(self.blob_path(id))?);
+        read_range(Box::new(QuocoReader::new(file, &self.key)), offset, len)
     }
 
-    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
         self.check_lock()?;
 
-        self.hashes.remove(id);
-        self.names.remove(id);
+        self.blobs.begin_write(&self.store, &self.key, None)
+    }
 
-        fs::remove_file(self.path.join(Path::new(&bytes_to_hex_str(id))))?;
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
 
-        Ok(())
+        self.blobs.begin_write(&self.store, &self.key, Some(*id))
     }
 
-    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
         self.check_lock()?;
 
-        let new_id = {
-            let uuid = Uuid::new_v4();
-            *uuid.as_bytes()
-        };
-        self.modify_object_unchecked(&new_id, reader)?;
+        self.blobs.copy(&self.store, id)
+    }
+
+    fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>> {
+        self.check_lock()?;
+
+        let (path, key) = (&self.store.path, &self.key);
+        self.blobs.apply_batch(&self.store, key, batch, |blobs| {
+            Self::save_reference_formats(blobs, path, key)
+        })
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        self.check_lock()?;
+
+        let blob_id = self.blobs.blob_id(id);
+        Ok(!self.blobs.is_released(&blob_id) && self.store.stored_blob_path(&blob_id).exists())
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.delete(&self.store, id)
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        self.check_lock()?;
 
-        Ok(new_id)
+        self.blobs.write(&self.store, &self.key, None, reader)
     }
 
     fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
         self.check_lock()?;
 
-        self.modify_object_unchecked(id, reader)
+        self.blobs
+            .write(&self.store, &self.key, Some(*id), reader)
+            .map(|_| ())
     }
 
     fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
         self.check_lock()?;
 
-        Ok(self.hashes.get_hash(id))
+        Ok(self.blobs.hashes.get_hash(id))
     }
 
     fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
         self.check_lock()?;
 
-        Ok(self.names.get_name(id))
+        Ok(self.blobs.names.get_name(id))
     }
 
     fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
         self.check_lock()?;
 
-        Ok(self.names.get_id(name))
+        Ok(self.blobs.names.get_id(name))
     }
 
     fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
         self.check_lock()?;
 
-        self.names.insert(id, name);
+        self.blobs.set_name(id, name);
 
         Ok(())
     }
@@ -190,31 +448,88 @@ impl ObjectSource for FsObjectSource {
     fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
         self.check_lock()?;
 
-        self.names.remove(id);
+        self.blobs.remove_name(id);
 
         Ok(())
     }
 
     fn last_updated(&self) -> &SystemTime {
-        &self.hashes.get_last_updated()
+        self.blobs.hashes.get_last_updated()
     }
 
     fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
-        self.hashes.get_ids()
+        self.blobs.hashes.get_ids()
     }
 
     fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
-        self.names.get_ids()
+        self.blobs.names.get_ids()
     }
 
-    fn flush(&mut self) -> Result<()> {
+    fn names(&self) -> &Names {
+        &self.blobs.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.blobs.hashes
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
         self.check_lock()?;
 
-        self.save_reference_format(&self.hashes)?;
-        self.save_reference_format(&self.names)?;
+        self.blobs.replace(names, hashes);
 
         Ok(())
     }
+
+    fn flush(&mut self) -> Result<()> {
+        self.check_lock()?;
+
+        let (store, key) = (&self.store, &self.key);
+        self.blobs.flush(store, |blobs| {
+            Self::save_reference_formats(blobs, &store.path, key)
+        })
+    }
+
+    fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>> {
+        self.check_lock()?;
+
+        let info = match ObjectInfo::from_metadata(id, &self.blobs.names, &self.blobs.hashes) {
+            Some(info) => info,
+            // Could be an orphan
+            None if !self.blobs.is_released(id) && self.blob_path(id).is_file() => {
+                ObjectInfo::orphan(id)
+            }
+            None => return Ok(None),
+        };
+
+        Self::with_file_info(info, &self.blob_path(id)).map(Some)
+    }
+
+    fn list(&self) -> Result<Box<dyn Iterator<Item = ObjectInfo> + '_>> {
+        self.check_lock()?;
+
+        let known_ids: HashSet<&ObjectId> = self
+            .blobs
+            .hashes
+            .get_ids()
+            .chain(self.blobs.names.get_ids())
+            .collect();
+        let blob_ids: HashSet<ObjectId> =
+            known_ids.iter().map(|id| self.blobs.blob_id(id)).collect();
+
+        let mut infos = known_ids
+            .into_iter()
+            .map(|id| self.stat(id).map(Option::unwrap))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (id, file) in Self::blob_files(&self.store.path)? {
+            if !blob_ids.contains(&id) && !self.blobs.is_released(&id) {
+                infos.push(Self::with_file_info(ObjectInfo::orphan(&id), &file)?);
+            }
+        }
+
+        Ok(Box::new(infos.into_iter()))
+    }
 }
 
 impl Drop for FsObjectSource {
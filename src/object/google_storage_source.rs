@@ -1,291 +1,425 @@
-use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
-use std::path::Path;
-use std::str;
-
-use cloud_storage::{Error, Object};
-use uuid::Uuid;
+use std::cmp;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::DateTime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::StatusCode;
+use ring::rand::SystemRandom;
+use ring::signature::{RsaKeyPair, RSA_PKCS1_SHA256};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 
 use crate::error::QuocoError;
-use crate::formats::{Hashes, Names, ReferenceFormat};
-use crate::object::fs_source::LOCK_FILE_NAME;
-use crate::object::{Finish, Key, ObjectHash, ObjectId, ObjectSource, QuocoReader, QuocoWriter};
-use crate::util::{bytes_to_hex_str, sha256};
-use crate::{ReadSeek, Result};
-use std::collections::hash_map::Keys;
-use std::time::SystemTime;
+use crate::object::bucket_source::{BucketClient, BucketObjectSource, StoredObject};
+use crate::object::Key;
+use crate::Result;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 const OBJECT_MIME_TYPE: &str = "application/octet-stream";
-
-pub struct GoogleStorageObjectSource {
-    names: Names,
-    hashes: Hashes,
-    bucket: String,
-    key: Key,
-    lock: bool,
+const DEFAULT_ENDPOINT: &str = "https://storage.googleapis.com";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// Tokens are refreshed this long before they expire so they don't lapse mid-request.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+/// Everything but unreserved characters has to be escaped in object names in URL paths,
+/// including slashes.
+const OBJECT_NAME_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+pub type AccessTokenProvider = Arc<dyn Fn() -> Result<String> + Send + Sync>;
+
+/// How a [`GoogleStorageObjectSource`] authenticates with Cloud Storage.
+#[derive(Clone)]
+pub enum GoogleCredentials {
+    /// Path to a service account key file, as downloaded from the Cloud Console.
+    ServiceAccountFile(PathBuf),
+    /// Contents of a service account key file.
+    ServiceAccountJson(String),
+    /// Called for an OAuth 2.0 access token before every request. Tokens aren't cached, so the
+    /// provider should cache them itself if fetching one is expensive.
+    TokenProvider(AccessTokenProvider),
+    /// Sends requests without credentials, for local emulators like fake-gcs-server.
+    Anonymous,
 }
 
-impl GoogleStorageObjectSource {
-    pub fn open(bucket: &str, config_path: &Path, key: &Key) -> Result<Self> {
-        // Sort of a kludge
-        std::env::set_var("SERVICE_ACCOUNT", config_path);
-
-        Self::check_no_lock(bucket)?;
-        Self::touch_lock(bucket)?;
-
-        let mut source = GoogleStorageObjectSource {
-            names: Names::default(),
-            hashes: Hashes::default(),
-            bucket: bucket.into(),
-            key: *key,
-            lock: true,
-        };
-
-        Self::load_reference_formats(&mut source)?;
-
-        Ok(source)
-    }
-
-    // pub async fn storage_hub(config_path: &Path) -> Result<Storage> {
-    //     // Get an ApplicationSecret instance by some means. It contains the `client_id` and
-    //     // `client_secret`, among other things.
-    //     let secret: yup_oauth2::ConsoleApplicationSecret =
-    //         serde_json::from_reader(BufReader::new(File::open(config_path)?))?;
-    //     // Instantiate the authenticator. It will choose a suitable authentication flow for you,
-    //     // unless you replace  `None` with the desired Flow.
-    //     // Provide your own `AuthenticatorDelegate` to adjust the way it operates and get feedback about
-    //     // what's going on. You probably want to bring in your own `TokenStorage` to persist tokens and
-    //     // retrieve them from storage.
-    //     let auth = yup_oauth2::InstalledFlowAuthenticator::builder(
-    //         secret
-    //             .installed
-    //             .expect("Couldn't load console application secret"),
-    //         yup_oauth2::InstalledFlowReturnMethod::HTTPRedirect,
-    //     )
-    //     .persist_tokens_to_disk(config_path.parent().unwrap().join("tokencache.json"))
-    //     .build()
-    //     .await?;
-    //
-    //     Ok(Storage::new(
-    //         hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots()),
-    //         auth,
-    //     ))
-    // }
-
-    pub fn unlock(&mut self) -> Result<()> {
-        self.delete(LOCK_FILE_NAME)?;
-        self.lock = false;
-        Ok(())
-    }
-
-    // TODO: Work out naming/semantic division between this and check_no_lock
-    fn check_lock(&self) -> Result<()> {
-        if !self.lock {
-            return Err(QuocoError::SessionDisposed);
+impl fmt::Debug for GoogleCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print keys
+        match self {
+            GoogleCredentials::ServiceAccountFile(path) => {
+                f.debug_tuple("ServiceAccountFile").field(path).finish()
+            }
+            GoogleCredentials::ServiceAccountJson(_) => write!(f, "ServiceAccountJson(..)"),
+            GoogleCredentials::TokenProvider(_) => write!(f, "TokenProvider(..)"),
+            GoogleCredentials::Anonymous => write!(f, "Anonymous"),
         }
-        Ok(())
-    }
-
-    fn get_object_bytes(&self, name: &str) -> Result<Vec<u8>> {
-        Object::download_sync(self.bucket.as_str(), name).map_err(QuocoError::from)
-    }
-
-    fn modify_unchecked(&self, name: &str, data: Vec<u8>) -> Result<()> {
-        Object::create_sync(self.bucket.as_str(), data, name, OBJECT_MIME_TYPE)?;
-        Ok(())
     }
+}
 
-    fn modify_unchecked_reader<R: Read + Seek>(&self, name: &str, reader: &mut R) -> Result<()> {
-        // Warning: This loads everything into memory before uploading. As far as I can tell, this
-        // is a limitation of cloud_storage but I don't know if it's a limitation of Google's API.
-        let data = Vec::new();
-        let mut writer = QuocoWriter::new(data, &self.key);
-        std::io::copy(reader, &mut writer)?;
-        self.modify_unchecked(name, writer.finish()?)?;
-
-        Ok(())
-    }
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
 
-    fn modify_object_unchecked_reader<R: Read + Seek>(
-        &mut self,
-        id: &ObjectId,
-        reader: &mut R,
-    ) -> Result<()> {
-        self.hashes.insert(id, &sha256(reader)?);
-        reader.seek(SeekFrom::Start(0))?;
-        self.modify_unchecked_reader(&bytes_to_hex_str(id), reader)
-    }
+fn default_token_uri() -> String {
+    DEFAULT_TOKEN_URI.into()
+}
 
-    fn delete(&self, name: &str) -> Result<()> {
-        Object::delete_sync(self.bucket.as_str(), name)?;
-        Ok(())
-    }
+/// The parts of an object resource we use. See
+/// https://cloud.google.com/storage/docs/json_api/v1/objects#resource.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectResource {
+    name: String,
+    /// Sent as a string because it can exceed what JSON numbers can hold exactly
+    size: String,
+    time_created: Option<String>,
+    updated: Option<String>,
+}
 
-    fn with_name_exists(bucket: &str, name: &str) -> Result<bool> {
-        match Object::read_sync(bucket, name) {
-            Ok(_) => Ok(true),
-            Err(err) => match err {
-                Error::Google(ref response) => {
-                    if response.errors_has_reason(&cloud_storage::Reason::NotFound) {
-                        Ok(false)
-                    } else {
-                        Err(err.into())
-                    }
-                }
-                _ => Err(err.into()),
-            },
+impl From<ObjectResource> for StoredObject {
+    fn from(resource: ObjectResource) -> Self {
+        let parse_time = |time: &Option<String>| {
+            time.as_deref()
+                .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                .map(SystemTime::from)
+        };
+        StoredObject {
+            size: resource.size.parse().ok(),
+            created: parse_time(&resource.time_created),
+            modified: parse_time(&resource.updated),
+            name: resource.name,
         }
     }
+}
 
-    fn load_reference_formats(&mut self) -> Result<()> {
-        let names_name = Names::specification().name;
-        let hashes_name = Hashes::specification().name;
-
-        if Self::with_name_exists(self.bucket.as_str(), names_name)? {
-            // TODO: See if there's any way to make this mess cleaner
-            self.names.load(&mut BufReader::new(&mut QuocoReader::new(
-                &mut Cursor::new(self.get_object_bytes(names_name)?),
-                &self.key,
-            )))?;
-        }
-        if Self::with_name_exists(self.bucket.as_str(), hashes_name)? {
-            self.hashes.load(&mut BufReader::new(&mut QuocoReader::new(
-                &mut Cursor::new(self.get_object_bytes(hashes_name)?),
-                &self.key,
-            )))?;
-        }
-
-        Ok(())
-    }
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectList {
+    #[serde(default)]
+    items: Vec<ObjectResource>,
+    next_page_token: Option<String>,
+}
 
-    fn save_reference_format<F: ReferenceFormat>(&self, format: &F) -> Result<()> {
-        let object_name = F::specification().name;
-        let format_data = Cursor::new(Vec::new());
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
 
-        let mut writer = QuocoWriter::new(format_data, &self.key);
-        format.save(&mut writer)?;
-        self.modify_unchecked(object_name, writer.finish()?.into_inner())?;
+/// Signs JWTs with a service account key and exchanges them for access tokens, as described in
+/// https://developers.google.com/identity/protocols/oauth2/service-account#httprest.
+struct ServiceAccount {
+    client_email: String,
+    key_pair: RsaKeyPair,
+    token_uri: String,
+    /// Current token and when it should be replaced
+    token: Mutex<Option<(String, Instant)>>,
+}
 
-        Ok(())
+impl ServiceAccount {
+    fn from_json(json: &str) -> Result<Self> {
+        let key: ServiceAccountKey = serde_json::from_str(json).map_err(|err| {
+            QuocoError::GoogleStorageError(format!("Invalid service account key: {}", err))
+        })?;
+
+        let der_base64: String = key
+            .private_key
+            .lines()
+            .filter(|line| !line.starts_with("-----"))
+            .collect();
+        let key_pair = base64::decode(der_base64)
+            .ok()
+            .and_then(|der| RsaKeyPair::from_pkcs8(&der).ok())
+            .ok_or_else(|| {
+                QuocoError::GoogleStorageError("Invalid service account private key".into())
+            })?;
+
+        Ok(ServiceAccount {
+            client_email: key.client_email,
+            key_pair,
+            token_uri: key.token_uri,
+            token: Mutex::new(None),
+        })
     }
 
-    fn touch_lock(bucket: &str) -> Result<()> {
-        Object::create_sync(bucket, Vec::new(), LOCK_FILE_NAME, OBJECT_MIME_TYPE)?;
+    fn access_token(&self, http: &Client) -> Result<String> {
+        let mut token = self.token.lock().unwrap();
+        if let Some((token, refresh_at)) = &*token {
+            if Instant::now() < *refresh_at {
+                return Ok(token.clone());
+            }
+        }
 
-        Ok(())
+        let response = check_status(
+            http.post(&self.token_uri)
+                .form(&[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                    ("assertion", &self.assertion()?),
+                ])
+                .send()?,
+        )?;
+        let response: TokenResponse = parse_json(response)?;
+
+        let lifetime = Duration::from_secs(response.expires_in);
+        *token = Some((
+            response.access_token.clone(),
+            Instant::now()
+                + lifetime
+                    .checked_sub(TOKEN_EXPIRY_MARGIN)
+                    .unwrap_or_default(),
+        ));
+        Ok(response.access_token)
     }
 
-    fn check_no_lock(bucket: &str) -> Result<()> {
-        if let true = Self::with_name_exists(bucket, LOCK_FILE_NAME)? {
-            return Err(QuocoError::SessionPathLocked(format!("gs://{}", bucket)));
-        }
-        Ok(())
+    fn assertion(&self) -> Result<String> {
+        let issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+
+        let header = encode(br#"{"alg":"RS256","typ":"JWT"}"#);
+        let claims = encode(
+            serde_json::json!({
+                "iss": self.client_email,
+                "scope": STORAGE_SCOPE,
+                "aud": self.token_uri,
+                "iat": issued_at,
+                "exp": issued_at + 3600,
+            })
+            .to_string()
+            .as_bytes(),
+        );
+        let message = format!("{}.{}", header, claims);
+
+        let mut signature = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                message.as_bytes(),
+                &mut signature,
+            )
+            .map_err(|_| QuocoError::GoogleStorageError("Failed to sign token request".into()))?;
+
+        Ok(format!("{}.{}", message, encode(&signature)))
     }
 }
 
-impl ObjectSource for GoogleStorageObjectSource {
-    fn object(&mut self, id: &ObjectId) -> Result<Box<dyn Read>> {
-        self.check_lock()?;
+enum Authorization {
+    ServiceAccount(Box<ServiceAccount>),
+    TokenProvider(AccessTokenProvider),
+    Anonymous,
+}
 
-        Ok(Box::new(QuocoReader::new(
-            Cursor::new(self.get_object_bytes(&bytes_to_hex_str(id))?),
-            &self.key,
-        )))
-    }
+/// Minimal client for the Cloud Storage JSON API covering the handful of calls we need.
+pub struct GoogleStorageClient {
+    http: Client,
+    endpoint: String,
+    bucket: String,
+    authorization: Authorization,
+}
 
-    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
-        self.check_lock()?;
+impl GoogleStorageClient {
+    fn new(endpoint: Option<&str>, bucket: &str, credentials: GoogleCredentials) -> Result<Self> {
+        let authorization = match credentials {
+            GoogleCredentials::ServiceAccountFile(path) => Authorization::ServiceAccount(Box::new(
+                ServiceAccount::from_json(&fs::read_to_string(path)?)?,
+            )),
+            GoogleCredentials::ServiceAccountJson(json) => {
+                Authorization::ServiceAccount(Box::new(ServiceAccount::from_json(&json)?))
+            }
+            GoogleCredentials::TokenProvider(provider) => Authorization::TokenProvider(provider),
+            GoogleCredentials::Anonymous => Authorization::Anonymous,
+        };
 
-        Self::with_name_exists(self.bucket.as_str(), &bytes_to_hex_str(id))
+        Ok(GoogleStorageClient {
+            http: Client::new(),
+            endpoint: endpoint
+                .unwrap_or(DEFAULT_ENDPOINT)
+                .trim_end_matches('/')
+                .to_string(),
+            bucket: bucket.into(),
+            authorization,
+        })
     }
 
-    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
-        self.check_lock()?;
-
-        self.hashes.remove(id);
-        self.names.remove(id);
-
-        self.delete(&bytes_to_hex_str(id))
+    fn object_url(&self, name: &str) -> String {
+        format!(
+            "{}/storage/v1/b/{}/o/{}",
+            self.endpoint,
+            utf8_percent_encode(&self.bucket, OBJECT_NAME_ENCODE_SET),
+            utf8_percent_encode(name, OBJECT_NAME_ENCODE_SET)
+        )
     }
 
-    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
-        self.check_lock()?;
-
-        let new_id = {
-            let uuid = Uuid::new_v4();
-            *uuid.as_bytes()
+    fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        let token = match &self.authorization {
+            Authorization::ServiceAccount(account) => account.access_token(&self.http)?,
+            Authorization::TokenProvider(provider) => provider()?,
+            Authorization::Anonymous => return Ok(request),
         };
-        self.modify_object_unchecked_reader(&new_id, reader)?;
-        Ok(new_id)
+        Ok(request.bearer_auth(token))
     }
+}
 
-    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
-        self.check_lock()?;
-
-        // TODO: Is it worth making an extra network call to check if the document doesn't exist?
-        self.modify_object_unchecked_reader(id, reader)
+impl BucketClient for GoogleStorageClient {
+    fn get(&self, name: &str) -> Result<Option<Box<dyn Read + Send>>> {
+        let request = self
+            .http
+            .get(self.object_url(name))
+            .query(&[("alt", "media")]);
+        let response = self.authorize(request)?.send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(check_status(response)?)))
     }
 
-    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
-        self.check_lock()?;
-
-        Ok(self.hashes.get_hash(id))
+    fn get_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let range = format!("bytes={}-{}", start, start.saturating_add(len - 1));
+        let request = self
+            .http
+            .get(self.object_url(name))
+            .query(&[("alt", "media")])
+            .header("range", range);
+        let response = self.authorize(request)?.send()?;
+        match response.status() {
+            StatusCode::RANGE_NOT_SATISFIABLE => Ok(Vec::new()),
+            StatusCode::PARTIAL_CONTENT => Ok(response.bytes()?.to_vec()),
+            // Whole object, from servers that ignore ranges
+            _ => {
+                let data = check_status(response)?.bytes()?;
+                let start = cmp::min(start, data.len() as u64) as usize;
+                let end = cmp::min((start as u64).saturating_add(len), data.len() as u64) as usize;
+                Ok(data[start..end].to_vec())
+            }
+        }
     }
 
-    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
-        self.check_lock()?;
-
-        Ok(self.names.get_name(id))
+    fn metadata(&self, name: &str) -> Result<Option<StoredObject>> {
+        let response = self
+            .authorize(self.http.get(self.object_url(name)))?
+            .send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        parse_json::<ObjectResource>(check_status(response)?).map(|resource| Some(resource.into()))
     }
 
-    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
-        self.check_lock()?;
-
-        Ok(self.names.get_id(name))
+    fn list(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let url = format!(
+            "{}/storage/v1/b/{}/o",
+            self.endpoint,
+            utf8_percent_encode(&self.bucket, OBJECT_NAME_ENCODE_SET)
+        );
+        let mut objects = Vec::new();
+        let mut page_token = None;
+        loop {
+            let mut request = self.http.get(&url).query(&[("prefix", prefix)]);
+            if let Some(page_token) = &page_token {
+                request = request.query(&[("pageToken", page_token)]);
+            }
+            let page: ObjectList = parse_json(check_status(self.authorize(request)?.send()?)?)?;
+            objects.extend(page.items.into_iter().map(StoredObject::from));
+
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                return Ok(objects);
+            }
+        }
     }
 
-    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
-        self.check_lock()?;
-
-        self.names.insert(id, name);
-
+    fn put(&self, name: &str, data: File) -> Result<()> {
+        let request = self
+            .http
+            .post(format!(
+                "{}/upload/storage/v1/b/{}/o",
+                self.endpoint,
+                utf8_percent_encode(&self.bucket, OBJECT_NAME_ENCODE_SET)
+            ))
+            .query(&[("uploadType", "media"), ("name", name)])
+            .header("content-type", OBJECT_MIME_TYPE)
+            .body(data);
+        check_status(self.authorize(request)?.send()?)?;
         Ok(())
     }
 
-    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
-        self.check_lock()?;
-
-        self.names.remove(id);
-
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let request = self.http.post(format!(
+            "{}/copyTo/b/{}/o/{}",
+            self.object_url(from),
+            utf8_percent_encode(&self.bucket, OBJECT_NAME_ENCODE_SET),
+            utf8_percent_encode(to, OBJECT_NAME_ENCODE_SET)
+        ));
+        check_status(self.authorize(request)?.send()?)?;
         Ok(())
     }
 
-    fn last_updated(&self) -> &SystemTime {
-        &self.hashes.get_last_updated()
-    }
-
-    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
-        self.hashes.get_ids()
+    fn delete(&self, name: &str) -> Result<()> {
+        let request = self.http.delete(self.object_url(name));
+        check_status(self.authorize(request)?.send()?)?;
+        Ok(())
     }
 
-    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
-        self.names.get_ids()
+    fn location(&self, prefix: &str) -> String {
+        format!("gs://{}/{}", self.bucket, prefix)
     }
+}
 
-    fn flush(&mut self) -> Result<()> {
-        self.check_lock()?;
-
-        self.save_reference_format(&self.hashes)?;
-        self.save_reference_format(&self.names)?;
+fn parse_json<T: DeserializeOwned>(response: Response) -> Result<T> {
+    serde_json::from_slice(&response.bytes()?)
+        .map_err(|err| QuocoError::GoogleStorageError(format!("Invalid response: {}", err)))
+}
 
-        Ok(())
+fn check_status(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        return Ok(response);
     }
+    Err(QuocoError::GoogleStorageError(format!(
+        "Request failed with status {}: {}",
+        response.status(),
+        response.text().unwrap_or_default()
+    )))
 }
 
-impl Drop for GoogleStorageObjectSource {
-    fn drop(&mut self) {
-        // TODO: Based on BufWriter's Drop impl, I think it might be an anti-pattern to do anything
-        //  that could raise errors in drop. Look into this and refactor accordingly.
-        self.unlock()
-            .expect("Failed to release lock. You may have to release it manually.");
+/// Stores a vault in a Google Cloud Storage bucket, optionally under a key prefix so several
+/// vaults can share a bucket.
+pub type GoogleStorageObjectSource = BucketObjectSource<GoogleStorageClient>;
+
+impl BucketObjectSource<GoogleStorageClient> {
+    /// Opens the vault under `prefix` in `bucket`, so one bucket can hold several vaults. An
+    /// empty prefix puts the vault at the root of the bucket.
+    ///
+    /// `endpoint` defaults to Google's and can point at an emulator instead.
+    pub fn open(
+        endpoint: Option<&str>,
+        bucket: &str,
+        prefix: &str,
+        credentials: GoogleCredentials,
+        key: &Key,
+    ) -> Result<Self> {
+        Self::open_with_client(
+            GoogleStorageClient::new(endpoint, bucket, credentials)?,
+            prefix,
+            key,
+        )
     }
 }
@@ -0,0 +1,166 @@
+use crate::formats::{Hashes, Names};
+use crate::object::{Finish, Key, ObjectHash, ObjectId, ObjectSource, QuocoReader, QuocoWriter};
+use crate::util::sha256;
+use crate::{ReadSeek, Result};
+use std::collections::hash_map::Keys;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+/// Keeps a vault entirely in memory, for tests and for vaults that shouldn't outlive the
+/// process. Objects are still encrypted, exactly as they would be on disk, so code using this
+/// source exercises the same paths as with persistent sources.
+pub struct MemoryObjectSource {
+    names: Names,
+    hashes: Hashes,
+    objects: HashMap<ObjectId, Vec<u8>>,
+    key: Key,
+}
+
+impl MemoryObjectSource {
+    pub fn new(key: &Key) -> Self {
+        MemoryObjectSource {
+            names: Names::new(),
+            hashes: Hashes::new(),
+            objects: HashMap::new(),
+            key: *key,
+        }
+    }
+
+    /// Total size of all encrypted objects in bytes.
+    pub fn size(&self) -> usize {
+        self.objects.values().map(Vec::len).sum()
+    }
+
+    fn modify_object_unchecked<R: Read + Seek>(
+        &mut self,
+        id: &ObjectId,
+        reader: &mut R,
+    ) -> Result<()> {
+        let hash = sha256(reader)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let mut writer = QuocoWriter::new(Vec::new(), &self.key);
+        io::copy(reader, &mut writer)?;
+        self.objects.insert(*id, writer.finish()?);
+        self.hashes.insert(id, &hash);
+
+        Ok(())
+    }
+}
+
+impl ObjectSource for MemoryObjectSource {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
+        let data = self
+            .objects
+            .get(id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        Ok(Box::new(QuocoReader::new(
+            Cursor::new(data.clone()),
+            &self.key,
+        )))
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        Ok(self.objects.contains_key(id))
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.objects
+            .remove(id)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        self.hashes.remove(id);
+        self.names.remove(id);
+        Ok(())
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        let new_id = {
+            let uuid = Uuid::new_v4();
+            *uuid.as_bytes()
+        };
+        self.modify_object_unchecked(&new_id, reader)?;
+
+        Ok(new_id)
+    }
+
+    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
+        self.modify_object_unchecked(id, reader)
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        let (data, hash) = match (self.objects.get(id), self.hashes.get_hash(id)) {
+            (Some(data), Some(hash)) => (data.clone(), *hash),
+            _ => return Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        };
+        let new_id = *Uuid::new_v4().as_bytes();
+        self.objects.insert(new_id, data);
+        self.hashes.insert(&new_id, &hash);
+
+        Ok(new_id)
+    }
+
+    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
+        Ok(self.hashes.get_hash(id))
+    }
+
+    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
+        Ok(self.names.get_name(id))
+    }
+
+    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
+        Ok(self.names.get_id(name))
+    }
+
+    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        // Name changes count as updates, like changes to hashes
+        if self.names.insert(id, name).as_deref() != Some(name) {
+            self.hashes.set_last_updated(SystemTime::now());
+        }
+
+        Ok(())
+    }
+
+    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        if self.names.remove(id).is_some() {
+            self.hashes.set_last_updated(SystemTime::now());
+        }
+
+        Ok(())
+    }
+
+    fn last_updated(&self) -> &SystemTime {
+        self.hashes.get_last_updated()
+    }
+
+    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
+        self.hashes.get_ids()
+    }
+
+    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
+        self.names.get_ids()
+    }
+
+    fn names(&self) -> &Names {
+        &self.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.hashes
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        self.names = names;
+        self.hashes = hashes;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Names and hashes only ever live in memory, so there's nowhere to write them
+        Ok(())
+    }
+}
@@ -3,28 +3,62 @@ use libsodium_sys::{
     crypto_secretstream_xchacha20poly1305_ABYTES,
 };
 
+#[cfg(feature = "async")]
+pub use crate::object::async_quoco::{AsyncQuocoReader, AsyncQuocoWriter};
+#[cfg(feature = "async")]
+pub use crate::object::async_source::{AsyncObjectReader, AsyncObjectSource, TokioObjectSource};
+pub use crate::object::batch::{Batch, BatchOperation, DEFAULT_BATCH_PARALLELISM};
+pub use crate::object::bucket_source::BucketObjectSource;
 pub use crate::object::cached_source::CachedObjectSource;
+pub use crate::object::chunked_source::ChunkedObjectSource;
+pub use crate::object::chunker::{Chunker, MAX_CONTENT_CHUNK_LENGTH, MIN_CONTENT_CHUNK_LENGTH};
 pub use crate::object::decrypt_reader::DecryptReader;
 pub use crate::object::encrypt_writer::EncrypterWriter;
 pub use crate::object::finish::Finish;
 pub use crate::object::fs_source::FsObjectSource;
-pub use crate::object::google_storage_source::GoogleStorageObjectSource;
+pub use crate::object::google_storage_source::{
+    AccessTokenProvider, GoogleCredentials, GoogleStorageClient, GoogleStorageObjectSource,
+};
+pub use crate::object::memory_source::MemoryObjectSource;
+pub use crate::object::object_writer::ObjectWriter;
 pub use crate::object::quoco_reader::QuocoReader;
 pub use crate::object::quoco_writer::QuocoWriter;
-pub use crate::object::remote_source::{RemoteSource, RemoteSourceConfig};
+pub use crate::object::remote_source::RemoteSourceConfig;
+pub use crate::object::s3_source::{S3Client, S3Credentials, S3ObjectSource};
+pub use crate::object::sftp_source::{SftpObjectSource, SshCredentials};
 pub use crate::object::source::BoxedObjectSource;
-pub use crate::object::source::ObjectSource;
+pub use crate::object::source::{ObjectInfo, ObjectSource};
+pub use crate::object::source_registry::{
+    open_source_url, register_source_factory, registered_schemes, unregister_source_factory,
+    SourceFactory,
+};
+pub use crate::object::webdav_source::{WebDavCredentials, WebDavObjectSource};
 
+#[cfg(feature = "async")]
+mod async_quoco;
+#[cfg(feature = "async")]
+mod async_source;
+mod batch;
+mod blob_store;
+mod bucket_source;
 mod cached_source;
+mod chunked_source;
+mod chunker;
 mod decrypt_reader;
 mod encrypt_writer;
 mod finish;
 mod fs_source;
 mod google_storage_source;
+mod memory_source;
+mod object_writer;
 mod quoco_reader;
 mod quoco_writer;
 mod remote_source;
+mod s3_source;
+mod sftp_source;
 mod source;
+mod source_registry;
+mod webdav_source;
 
 pub const CHUNK_LENGTH: usize = 4096;
 const ENCRYPTED_CHUNK_LENGTH: usize =
@@ -0,0 +1,97 @@
+use crate::object::{Finish, Key, ObjectHash, ObjectId, ObjectSource, QuocoWriter};
+use crate::util::Sha256;
+use crate::{ReadSeek, Result};
+use std::io;
+use std::io::{Cursor, Write};
+
+/// An object being written, from [`ObjectSource::begin_object`] or
+/// [`ObjectSource::begin_modify_object`]. Data can be written in as many pieces as it arrives in,
+/// and nothing changes in the source until [`commit`](Self::commit). Dropping a writer without
+/// committing it discards what was written.
+pub trait ObjectWriter: Write {
+    /// Stores what was written and returns the ID of the object it's stored as.
+    fn commit(self: Box<Self>) -> Result<ObjectId>;
+}
+
+/// Collects everything written in memory and hands it to
+/// [`create_object`](ObjectSource::create_object) or
+/// [`modify_object`](ObjectSource::modify_object) on commit, for sources that don't stream.
+pub(crate) struct BufferedObjectWriter<'a, S: ObjectSource + ?Sized> {
+    source: &'a mut S,
+    /// Object to modify, or `None` to create a new one
+    id: Option<ObjectId>,
+    data: Vec<u8>,
+}
+
+impl<'a, S: ObjectSource + ?Sized> BufferedObjectWriter<'a, S> {
+    pub(crate) fn new(source: &'a mut S, id: Option<ObjectId>) -> Self {
+        BufferedObjectWriter {
+            source,
+            id,
+            data: Vec::new(),
+        }
+    }
+}
+
+impl<'a, S: ObjectSource + ?Sized> Write for BufferedObjectWriter<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, S: ObjectSource + ?Sized> ObjectWriter for BufferedObjectWriter<'a, S> {
+    fn commit(self: Box<Self>) -> Result<ObjectId> {
+        let BufferedObjectWriter { source, id, data } = *self;
+        let mut reader = Box::new(Cursor::new(data)) as Box<dyn ReadSeek>;
+        match id {
+            Some(id) => {
+                source.modify_object(&id, &mut reader)?;
+                Ok(id)
+            }
+            None => source.create_object(&mut reader),
+        }
+    }
+}
+
+/// Encrypts data into `W` and hashes it in the same pass, for sources that can take data without
+/// knowing its hash up front.
+pub(crate) struct HashingQuocoWriter<W: Write> {
+    inner: QuocoWriter<W>,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingQuocoWriter<W> {
+    pub(crate) fn new(writer: W, key: &Key) -> Result<Self> {
+        Ok(HashingQuocoWriter {
+            inner: QuocoWriter::new(writer, key),
+            hasher: Sha256::new()?,
+        })
+    }
+
+    /// Finishes encrypting, returning the inner writer and the hash of the unencrypted data.
+    pub(crate) fn finish(self) -> Result<(W, ObjectHash)> {
+        Ok((self.inner.finish()?, self.hasher.finish()?))
+    }
+
+    /// Throws away everything written, for writers dropped without being committed. Encryption
+    /// still has to be finished, since it insists on it.
+    pub(crate) fn discard(self) {
+        let _ = self.inner.finish();
+    }
+}
+
+impl<W: Write> Write for HashingQuocoWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -1,29 +1,102 @@
-use crate::object::{GoogleStorageObjectSource, Key};
+use crate::object::{
+    open_source_url, BoxedObjectSource, FsObjectSource, GoogleCredentials,
+    GoogleStorageObjectSource, Key, MemoryObjectSource, S3Credentials, S3ObjectSource,
+    SftpObjectSource, SshCredentials, WebDavCredentials, WebDavObjectSource,
+};
 use crate::Result;
 use std::path::PathBuf;
 
 pub enum RemoteSourceConfig {
+    /// Any URL with a scheme registered through [`register_source_factory`].
+    ///
+    /// [`register_source_factory`]: crate::object::register_source_factory
+    Url(String),
+    /// Another directory on this machine, like an external drive or a network mount.
+    Filesystem { path: PathBuf },
+    /// A vault that only exists for the lifetime of the session, mostly useful for testing.
+    Memory,
     GoogleStorage {
+        /// Defaults to Google's when `None`. Set this to use an emulator.
+        endpoint: Option<String>,
         bucket: String,
-        config_path: PathBuf,
+        /// Key prefix the vault lives under, so several vaults can share a bucket. Empty for the
+        /// root of the bucket.
+        prefix: String,
+        credentials: GoogleCredentials,
+    },
+    S3 {
+        /// Defaults to AWS in `region` when `None`.
+        endpoint: Option<String>,
+        region: String,
+        bucket: String,
+        prefix: String,
+        credentials: S3Credentials,
+    },
+    Sftp {
+        host: String,
+        port: u16,
+        /// OpenSSH known hosts file that must already contain the server's host key.
+        known_hosts: PathBuf,
+        credentials: SshCredentials,
+        /// Directory on the server holding the vault.
+        path: PathBuf,
+    },
+    WebDav {
+        /// URL of the collection holding the vault.
+        url: String,
+        credentials: WebDavCredentials,
     },
 }
 
-pub enum RemoteSource {
-    GoogleStorage(GoogleStorageObjectSource),
-}
-
-impl RemoteSource {
-    pub fn initialize(config: RemoteSourceConfig, key: &Key) -> Result<Self> {
-        Ok(match config {
+impl RemoteSourceConfig {
+    pub fn open(self, key: &Key) -> Result<BoxedObjectSource> {
+        Ok(match self {
+            RemoteSourceConfig::Url(url) => open_source_url(&url, key)?,
+            RemoteSourceConfig::Filesystem { path } => Box::new(FsObjectSource::open(&path, key)?),
+            RemoteSourceConfig::Memory => Box::new(MemoryObjectSource::new(key)),
             RemoteSourceConfig::GoogleStorage {
+                endpoint,
                 bucket,
-                config_path,
-            } => Self::GoogleStorage(GoogleStorageObjectSource::open(
+                prefix,
+                credentials,
+            } => Box::new(GoogleStorageObjectSource::open(
+                endpoint.as_deref(),
                 &bucket,
-                config_path.as_path(),
+                &prefix,
+                credentials,
+                key,
+            )?),
+            RemoteSourceConfig::S3 {
+                endpoint,
+                region,
+                bucket,
+                prefix,
+                credentials,
+            } => Box::new(S3ObjectSource::open(
+                endpoint.as_deref(),
+                &region,
+                &bucket,
+                &prefix,
+                credentials,
+                key,
+            )?),
+            RemoteSourceConfig::Sftp {
+                host,
+                port,
+                known_hosts,
+                credentials,
+                path,
+            } => Box::new(SftpObjectSource::open(
+                &host,
+                port,
+                &known_hosts,
+                &credentials,
+                &path,
                 key,
             )?),
+            RemoteSourceConfig::WebDav { url, credentials } => {
+                Box::new(WebDavObjectSource::open(&url, credentials, key)?)
+            }
         })
     }
 }
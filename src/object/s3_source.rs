@@ -0,0 +1,406 @@
+use std::cmp;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Utc};
+use reqwest::blocking::{Body, Client, Response};
+use reqwest::{Method, StatusCode, Url};
+
+use crate::error::QuocoError;
+use crate::object::bucket_source::{BucketClient, BucketObjectSource, StoredObject};
+use crate::object::Key;
+use crate::util::{bytes_to_hex_str, hmac_sha256, sha256};
+use crate::Result;
+
+const SIGNING_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const SERVICE_NAME: &str = "s3";
+
+#[derive(Clone)]
+pub struct S3Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Only needed for temporary credentials.
+    pub session_token: Option<String>,
+}
+
+/// Minimal S3 REST client covering the handful of calls we need, signed with AWS Signature
+/// Version 4.
+pub struct S3Client {
+    http: Client,
+    bucket: String,
+    /// Scheme, host and port that requests are sent to.
+    base_url: String,
+    host: String,
+    /// Path prepended to every key: the endpoint's own path, followed by the bucket name with
+    /// path-style addressing.
+    base_path: String,
+    region: String,
+    credentials: S3Credentials,
+}
+
+impl S3Client {
+    /// Uses virtual-hosted-style addressing against AWS when `endpoint` is `None` and path-style
+    /// addressing otherwise, which is what MinIO and most other S3-compatible servers expect.
+    fn new(
+        endpoint: Option<&str>,
+        region: &str,
+        bucket: &str,
+        credentials: S3Credentials,
+    ) -> Result<Self> {
+        let (endpoint, bucket_path) = match endpoint {
+            Some(endpoint) => (endpoint.to_string(), format!("/{}", bucket)),
+            None => (
+                format!("https://{}.s3.{}.amazonaws.com", bucket, region),
+                String::new(),
+            ),
+        };
+
+        let url = Url::parse(&endpoint)
+            .map_err(|_| QuocoError::S3Error(format!("Invalid endpoint {}", endpoint)))?;
+        // Endpoints behind a gateway can have a path of their own, like https://host/s3
+        let base_path = format!("{}{}", url.path().trim_end_matches('/'), bucket_path);
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => {
+                return Err(QuocoError::S3Error(format!(
+                    "Endpoint {} has no host",
+                    endpoint
+                )))
+            }
+        };
+
+        Ok(S3Client {
+            http: Client::new(),
+            bucket: bucket.into(),
+            base_url: format!("{}://{}", url.scheme(), host),
+            host,
+            base_path,
+            region: region.into(),
+            credentials,
+        })
+    }
+
+    fn check_status(response: Response) -> Result<Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        Err(QuocoError::S3Error(format!(
+            "Request failed with status {}: {}",
+            response.status(),
+            response.text().unwrap_or_default()
+        )))
+    }
+
+    /// Sends a signed request for `key`. `query` and `extra_headers` are signed along with the
+    /// standard headers, and `body` is uploaded from its start.
+    fn request(
+        &self,
+        method: Method,
+        key: &str,
+        query: &[(&str, &str)],
+        extra_headers: &[(&'static str, String)],
+        body: Option<File>,
+    ) -> Result<Response> {
+        let path = format!("{}/{}", self.base_path, uri_encode(key, false));
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let (body, payload_hash) = match body {
+            Some(mut file) => {
+                let hash = sha256(&mut file)?;
+                let len = file.stream_position()?;
+                file.seek(SeekFrom::Start(0))?;
+                (Body::sized(file, len), hash)
+            }
+            None => (Body::from(Vec::new()), sha256(&mut [].as_ref())?),
+        };
+        let payload_hash = bytes_to_hex_str(&payload_hash);
+
+        // Query parameters and headers have to be sorted by name for signing
+        let mut query = query
+            .iter()
+            .map(|(name, value)| (uri_encode(name, true), uri_encode(value, true)))
+            .collect::<Vec<_>>();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let mut headers = vec![
+            ("host", self.host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.extend(extra_headers.iter().cloned());
+        headers.sort();
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method, path, canonical_query, canonical_headers, signed_headers, payload_hash
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE_NAME);
+        let string_to_sign = format!(
+            "{}\n{}\n{}\n{}",
+            SIGNING_ALGORITHM,
+            timestamp,
+            scope,
+            bytes_to_hex_str(&sha256(&mut canonical_request.as_bytes())?)
+        );
+
+        let mut signing_key = hmac_sha256(
+            format!("AWS4{}", self.credentials.secret_access_key).as_bytes(),
+            date.as_bytes(),
+        )?;
+        for part in [self.region.as_str(), SERVICE_NAME, "aws4_request"].iter() {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes())?;
+        }
+        let signature = bytes_to_hex_str(&hmac_sha256(&signing_key, string_to_sign.as_bytes())?);
+
+        let url = if canonical_query.is_empty() {
+            format!("{}{}", self.base_url, path)
+        } else {
+            format!("{}{}?{}", self.base_url, path, canonical_query)
+        };
+        let mut request = self.http.request(method, url.as_str()).header(
+            "authorization",
+            format!(
+                "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+                SIGNING_ALGORITHM, self.credentials.access_key_id, scope, signed_headers, signature
+            ),
+        );
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+
+        Ok(request.body(body).send()?)
+    }
+}
+
+impl BucketClient for S3Client {
+    fn get(&self, name: &str) -> Result<Option<Box<dyn Read + Send>>> {
+        let response = self.request(Method::GET, name, &[], &[], None)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(Self::check_status(response)?)))
+    }
+
+    fn get_range(&self, name: &str, start: u64, len: u64) -> Result<Vec<u8>> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let range = format!("bytes={}-{}", start, start.saturating_add(len - 1));
+        let response = self.request(Method::GET, name, &[], &[("range", range)], None)?;
+        match response.status() {
+            StatusCode::RANGE_NOT_SATISFIABLE => Ok(Vec::new()),
+            StatusCode::PARTIAL_CONTENT => Ok(response.bytes()?.to_vec()),
+            // Whole object, from servers that ignore ranges
+            _ => {
+                let data = Self::check_status(response)?.bytes()?;
+                let start = cmp::min(start, data.len() as u64) as usize;
+                let end = cmp::min((start as u64).saturating_add(len), data.len() as u64) as usize;
+                Ok(data[start..end].to_vec())
+            }
+        }
+    }
+
+    fn metadata(&self, name: &str) -> Result<Option<StoredObject>> {
+        let response = self.request(Method::HEAD, name, &[], &[], None)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Self::check_status(response)?;
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+        };
+        Ok(Some(StoredObject {
+            name: name.into(),
+            size: header("content-length").and_then(|size| size.parse().ok()),
+            // S3 doesn't keep when objects were first created
+            created: None,
+            modified: header("last-modified").and_then(parse_http_date),
+        }))
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let mut objects = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let response = Self::check_status(self.request(Method::GET, "", &query, &[], None)?)?;
+            let page = response.text()?;
+
+            for contents in xml_elements(&page, "Contents") {
+                let name = match xml_elements(contents, "Key").next() {
+                    Some(name) => xml_unescape(name),
+                    None => continue,
+                };
+                objects.push(StoredObject {
+                    name,
+                    size: xml_elements(contents, "Size")
+                        .next()
+                        .and_then(|size| size.parse().ok()),
+                    created: None,
+                    modified: xml_elements(contents, "LastModified")
+                        .next()
+                        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                        .map(SystemTime::from),
+                });
+            }
+
+            let truncated = xml_elements(&page, "IsTruncated").next() == Some("true");
+            continuation_token = xml_elements(&page, "NextContinuationToken")
+                .next()
+                .map(xml_unescape);
+            if !truncated || continuation_token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    fn put(&self, name: &str, data: File) -> Result<()> {
+        Self::check_status(self.request(Method::PUT, name, &[], &[], Some(data))?)?;
+        Ok(())
+    }
+
+    fn copy(&self, from: &str, to: &str) -> Result<()> {
+        let source = format!("/{}/{}", self.bucket, uri_encode(from, false));
+        Self::check_status(self.request(
+            Method::PUT,
+            to,
+            &[],
+            &[("x-amz-copy-source", source)],
+            None,
+        )?)?;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        Self::check_status(self.request(Method::DELETE, name, &[], &[], None)?)?;
+        Ok(())
+    }
+
+    fn location(&self, prefix: &str) -> String {
+        format!("s3://{}/{}", self.bucket, prefix)
+    }
+}
+
+/// Percent-encodes everything except unreserved characters, as required for canonical URIs and
+/// query strings. Slashes are left alone in keys but encoded in query parameters.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if !encode_slash => "/".to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn parse_http_date(date: &str) -> Option<SystemTime> {
+    DateTime::parse_from_rfc2822(date)
+        .ok()
+        .map(SystemTime::from)
+}
+
+/// The contents of every `<tag>` element in `xml`. Only good enough for the flat responses S3
+/// sends, where elements never nest inside others with the same tag.
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut rest = xml;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let contents = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(contents)
+    })
+}
+
+/// Decodes the predefined entities and numeric character references, like `&#x0D;`, which S3
+/// uses for characters in keys that XML can't hold as is. Anything else is left alone.
+fn xml_unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "amp" => '&',
+                reference => {
+                    let code = match reference.strip_prefix("#x") {
+                        Some(hex) => u32::from_str_radix(hex, 16),
+                        None => reference.strip_prefix('#')?.parse(),
+                    };
+                    std::char::from_u32(code.ok()?)?
+                }
+            };
+            Some((character, end + 1))
+        });
+        match decoded {
+            Some((character, len)) => {
+                unescaped.push(character);
+                rest = &rest[len..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Stores a vault in an S3 bucket (or any S3-compatible service, like MinIO), optionally under a
+/// key prefix so several vaults can share a bucket.
+pub type S3ObjectSource = BucketObjectSource<S3Client>;
+
+impl BucketObjectSource<S3Client> {
+    pub fn open(
+        endpoint: Option<&str>,
+        region: &str,
+        bucket: &str,
+        prefix: &str,
+        credentials: S3Credentials,
+        key: &Key,
+    ) -> Result<Self> {
+        Self::open_with_client(
+            S3Client::new(endpoint, region, bucket, credentials)?,
+            prefix,
+            key,
+        )
+    }
+}
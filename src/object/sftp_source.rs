@@ -0,0 +1,398 @@
+use crate::error::QuocoError;
+use crate::formats::{Hashes, Links, Names, ReferenceFormat};
+use crate::object::blob_store::{BlobStore, LinkedBlobs};
+use crate::object::finish::Finish;
+use crate::object::fs_source::LOCK_FILE_NAME;
+use crate::object::{
+    Key, ObjectHash, ObjectId, ObjectSource, ObjectWriter, QuocoReader, QuocoWriter,
+};
+use crate::util::bytes_to_hex_str;
+use crate::{ReadSeek, Result};
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, RenameFlags, Session, Sftp};
+use std::collections::hash_map::Keys;
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+
+/// `LIBSSH2_FX_NO_SUCH_FILE`
+const SFTP_NO_SUCH_FILE: i32 = 2;
+
+#[derive(Clone)]
+pub struct SshCredentials {
+    pub username: String,
+    /// Private key file to authenticate with. When `None`, keys from a running SSH agent are
+    /// tried instead.
+    pub private_key: Option<PathBuf>,
+    pub passphrase: Option<String>,
+}
+
+/// Stores a vault in a directory on an SSH server, laid out exactly like [`FsObjectSource`]
+/// lays out a local vault.
+///
+/// The server's host key must already be in the given known hosts file; unknown or changed keys
+/// are rejected rather than trusted on first use.
+///
+/// [`FsObjectSource`]: crate::object::FsObjectSource
+pub struct SftpObjectSource {
+    blobs: LinkedBlobs,
+    store: SftpBlobStore,
+    host: String,
+    key: Key,
+    lock: bool,
+}
+
+/// The object files in a vault directory on the server.
+struct SftpBlobStore {
+    // Kept alive for as long as the SFTP channel is in use
+    _session: Session,
+    sftp: Sftp,
+    path: PathBuf,
+}
+
+impl SftpBlobStore {
+    fn blob_path(&self, blob_id: &ObjectId) -> PathBuf {
+        self.path.join(bytes_to_hex_str(blob_id))
+    }
+
+    fn exists(&self, path: &Path) -> Result<bool> {
+        match self.sftp.stat(path) {
+            Ok(_) => Ok(true),
+            Err(err) if err.code() == ErrorCode::SFTP(SFTP_NO_SUCH_FILE) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// `path` with `extension` added to its file name, for files written alongside it.
+    fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(extension);
+        path.with_file_name(file_name)
+    }
+
+    /// Writes a file under a temporary name and only moves it to `path` once it's complete, so
+    /// a dropped connection never leaves a truncated file behind.
+    fn write_file<F: FnOnce(ssh2::File) -> Result<()>>(&self, path: &Path, write: F) -> Result<()> {
+        let temp_path = Self::sibling_path(path, "tmp");
+        write(self.sftp.create(&temp_path)?)?;
+
+        let flags = RenameFlags::OVERWRITE | RenameFlags::ATOMIC | RenameFlags::NATIVE;
+        if self.sftp.rename(&temp_path, path, Some(flags)).is_ok() {
+            return Ok(());
+        }
+        // Servers speaking SFTP version 3, like OpenSSH, won't rename over an existing file, so
+        // the old one is moved aside and only removed once the new one is in place.
+        // load_reference_format falls back to it if the connection drops in between.
+        let old_path = Self::sibling_path(path, "old");
+        if self.exists(&old_path)? {
+            self.sftp.unlink(&old_path)?;
+        }
+        self.sftp.rename(path, &old_path, None)?;
+        self.sftp.rename(&temp_path, path, None)?;
+        self.sftp.unlink(&old_path)?;
+        Ok(())
+    }
+
+    fn load_reference_format<F: ReferenceFormat>(&self, mut format: F, key: &Key) -> Result<F> {
+        let mut path = self.path.join(F::specification().name);
+        if !self.exists(&path)? {
+            // Left behind if saving was interrupted while replacing the file
+            path = Self::sibling_path(&path, "old");
+        }
+        if self.exists(&path)? {
+            let mut file_reader = BufReader::new(QuocoReader::new(self.sftp.open(&path)?, key));
+            format.load(&mut file_reader)?;
+        }
+        Ok(format)
+    }
+
+    fn save_reference_format<F: ReferenceFormat>(&self, format: &F, key: &Key) -> Result<()> {
+        self.write_file(&self.path.join(F::specification().name), |file| {
+            let mut file_writer = QuocoWriter::new(file, key);
+            format.save(&mut file_writer)?;
+            file_writer.finish()?;
+            Ok(())
+        })
+    }
+
+    fn save_reference_formats(&self, blobs: &LinkedBlobs, key: &Key) -> Result<()> {
+        self.save_reference_format(&blobs.hashes, key)?;
+        self.save_reference_format(&blobs.names, key)?;
+        self.save_reference_format(&blobs.links, key)?;
+        Ok(())
+    }
+}
+
+impl BlobStore for SftpBlobStore {
+    fn read_blob(&self, blob_id: &ObjectId) -> Result<Box<dyn Read>> {
+        Ok(Box::new(self.sftp.open(self.blob_path(blob_id))?))
+    }
+
+    fn put_blob(&self, blob_id: &ObjectId, staged: NamedTempFile) -> Result<()> {
+        let mut file = staged.into_file();
+        file.seek(SeekFrom::Start(0))?;
+        self.write_file(&self.blob_path(blob_id), |mut remote_file| {
+            io::copy(&mut file, &mut remote_file)?;
+            Ok(())
+        })
+    }
+
+    fn delete_blob(&self, blob_id: &ObjectId) -> Result<()> {
+        self.sftp.unlink(&self.blob_path(blob_id))?;
+        Ok(())
+    }
+}
+
+impl SftpObjectSource {
+    pub fn open(
+        host: &str,
+        port: u16,
+        known_hosts: &Path,
+        credentials: &SshCredentials,
+        path: &Path,
+        key: &Key,
+    ) -> Result<Self> {
+        let session = Self::connect(host, port, known_hosts, credentials)?;
+        let sftp = session.sftp()?;
+
+        let mut source = SftpObjectSource {
+            blobs: LinkedBlobs::default(),
+            store: SftpBlobStore {
+                _session: session,
+                sftp,
+                path: path.into(),
+            },
+            host: format!("{}:{}", host, port),
+            key: *key,
+            // Not held yet, so a failed open doesn't delete another session's lock on drop
+            lock: false,
+        };
+
+        source.check_no_lock()?;
+        let store = &source.store;
+        source.blobs = LinkedBlobs::new(
+            store.load_reference_format(Names::new(), key)?,
+            store.load_reference_format(Hashes::new(), key)?,
+            store.load_reference_format(Links::new(), key)?,
+        );
+
+        // Only acquire lock after decryption works
+        source.touch_lock()?;
+        source.lock = true;
+
+        Ok(source)
+    }
+
+    fn connect(
+        host: &str,
+        port: u16,
+        known_hosts_path: &Path,
+        credentials: &SshCredentials,
+    ) -> Result<Session> {
+        let mut session = Session::new()?;
+        session.set_tcp_stream(TcpStream::connect((host, port))?);
+        session.handshake()?;
+
+        let mut known_hosts = session.known_hosts()?;
+        known_hosts.read_file(known_hosts_path, KnownHostFileKind::OpenSSH)?;
+        let (host_key, _) = session
+            .host_key()
+            .ok_or_else(|| QuocoError::HostKeyRejected(host.into()))?;
+        match known_hosts.check_port(host, port, host_key) {
+            CheckResult::Match => {}
+            CheckResult::Mismatch | CheckResult::NotFound | CheckResult::Failure => {
+                return Err(QuocoError::HostKeyRejected(host.into()))
+            }
+        }
+
+        match &credentials.private_key {
+            Some(private_key) => session.userauth_pubkey_file(
+                &credentials.username,
+                None,
+                private_key,
+                credentials.passphrase.as_deref(),
+            )?,
+            None => session.userauth_agent(&credentials.username)?,
+        }
+
+        Ok(session)
+    }
+
+    pub fn unlock(&mut self) -> Result<()> {
+        self.store
+            .sftp
+            .unlink(&self.store.path.join(LOCK_FILE_NAME))?;
+        self.lock = false;
+        Ok(())
+    }
+
+    fn check_lock(&self) -> Result<()> {
+        if !self.lock {
+            return Err(QuocoError::SessionDisposed);
+        }
+        Ok(())
+    }
+
+    /// When enabled, objects with identical contents share a single encrypted file. Objects
+    /// that already share files keep sharing them after this is disabled.
+    pub fn set_dedupe(&mut self, enabled: bool) {
+        self.blobs.dedupe = enabled;
+    }
+
+    fn touch_lock(&self) -> Result<()> {
+        self.store
+            .sftp
+            .create(&self.store.path.join(LOCK_FILE_NAME))?;
+        Ok(())
+    }
+
+    fn check_no_lock(&self) -> Result<()> {
+        if self.store.exists(&self.store.path.join(LOCK_FILE_NAME))? {
+            return Err(QuocoError::SessionPathLocked(format!(
+                "sftp://{}{}",
+                self.host,
+                self.store.path.to_string_lossy()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl ObjectSource for SftpObjectSource {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
+        self.check_lock()?;
+
+        Ok(Box::new(QuocoReader::new(
+            self.store.read_blob(&self.blobs.blob_id(id))?,
+            &self.key,
+        )))
+    }
+
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.store, &self.key, None)
+    }
+
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.store, &self.key, Some(*id))
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.copy(&self.store, id)
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        self.check_lock()?;
+
+        let blob_id = self.blobs.blob_id(id);
+        Ok(!self.blobs.is_released(&blob_id)
+            && self.store.exists(&self.store.blob_path(&blob_id))?)
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.delete(&self.store, id)
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.write(&self.store, &self.key, None, reader)
+    }
+
+    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs
+            .write(&self.store, &self.key, Some(*id), reader)
+            .map(|_| ())
+    }
+
+    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.hashes.get_hash(id))
+    }
+
+    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_name(id))
+    }
+
+    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_id(name))
+    }
+
+    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.set_name(id, name);
+
+        Ok(())
+    }
+
+    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.remove_name(id);
+
+        Ok(())
+    }
+
+    fn last_updated(&self) -> &SystemTime {
+        self.blobs.hashes.get_last_updated()
+    }
+
+    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
+        self.blobs.hashes.get_ids()
+    }
+
+    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
+        self.blobs.names.get_ids()
+    }
+
+    fn names(&self) -> &Names {
+        &self.blobs.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.blobs.hashes
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.replace(names, hashes);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.check_lock()?;
+
+        let (store, key) = (&self.store, &self.key);
+        self.blobs
+            .flush(store, |blobs| store.save_reference_formats(blobs, key))
+    }
+}
+
+impl Drop for SftpObjectSource {
+    fn drop(&mut self) {
+        if self.lock {
+            self.unlock()
+                .expect("Failed to release lock. You may have to release it manually.");
+        }
+    }
+}
@@ -1,15 +1,85 @@
-use crate::object::{ObjectHash, ObjectId};
+use crate::error::QuocoError::NameTaken;
+use crate::formats::{Hashes, Names};
+use crate::object::batch::apply_batch_sequentially;
+use crate::object::object_writer::BufferedObjectWriter;
+use crate::object::{Batch, ObjectHash, ObjectId, ObjectWriter};
 use crate::{ReadSeek, Result};
-use std::collections::hash_map;
+use std::collections::{hash_map, HashSet};
+use std::io;
 use std::io::Read;
 use std::time::SystemTime;
 
+/// What a source knows about an object without reading it, as returned by
+/// [`ObjectSource::stat`] and [`ObjectSource::list`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+    pub id: ObjectId,
+    pub name: Option<String>,
+    /// `None` for orphans: stored objects missing from the source's hashes, like files left
+    /// behind by an interrupted write.
+    pub hash: Option<ObjectHash>,
+    /// Size of the stored object in bytes, which includes encryption overhead. `None` when the
+    /// source can't tell without reading the object.
+    pub size: Option<u64>,
+    pub created: Option<SystemTime>,
+    pub modified: Option<SystemTime>,
+}
+
+impl ObjectInfo {
+    /// Info from names and hashes alone, without anything about the stored object.
+    pub fn from_metadata(id: &ObjectId, names: &Names, hashes: &Hashes) -> Option<Self> {
+        let name = names.get_name(id).cloned();
+        let hash = hashes.get_hash(id).copied();
+        if name.is_none() && hash.is_none() {
+            return None;
+        }
+
+        Some(ObjectInfo {
+            id: *id,
+            name,
+            hash,
+            size: None,
+            created: None,
+            modified: None,
+        })
+    }
+
+    /// Info about a stored object that names and hashes don't mention.
+    pub fn orphan(id: &ObjectId) -> Self {
+        ObjectInfo {
+            id: *id,
+            name: None,
+            hash: None,
+            size: None,
+            created: None,
+            modified: None,
+        }
+    }
+}
+
+/// Skips to `offset` in a decrypted object and stops `len` bytes later.
+///
+/// Objects are compressed and encrypted as one stream, so there's no way to start decrypting
+/// partway through; everything before `offset` still has to be read. What sources can avoid is
+/// reading anything past the end of the range.
+pub(crate) fn read_range(
+    mut reader: Box<dyn Read>,
+    offset: u64,
+    len: u64,
+) -> Result<Box<dyn Read>> {
+    io::copy(&mut (&mut reader).take(offset), &mut io::sink())?;
+    Ok(Box::new(reader.take(len)))
+}
+
 // pub type BoxedObjectSource = Box<dyn ObjectSource<OutReader = dyn Read + Send> + Send>;
-pub type BoxedObjectSource = Box<dyn ObjectSource + Send>;
+pub type BoxedObjectSource = Box<dyn ObjectSource + Send + Sync>;
 
 pub trait ObjectSource {
-    fn object(&mut self, id: &ObjectId) -> Result<Box<dyn Read>>;
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>>;
     fn object_exists(&self, id: &ObjectId) -> Result<bool>;
+    /// Removes `id` straight away. Sources that store objects as blobs keep the deleted data, and
+    /// the old data of modified objects, until the next [`flush`](Self::flush), so the saved
+    /// reference formats never point at missing data.
     fn delete_object(&mut self, id: &ObjectId) -> Result<()>;
     fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId>;
     fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()>;
@@ -18,8 +88,93 @@ pub trait ObjectSource {
     fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>>;
     fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()>;
     fn remove_object_name(&mut self, id: &ObjectId) -> Result<()>;
+    /// When an object was last created, modified, deleted or renamed, as saved with the hashes.
     fn last_updated(&self) -> &SystemTime;
     fn hashes_ids(&mut self) -> hash_map::Keys<'_, ObjectId, ObjectHash>;
     fn names_ids(&mut self) -> hash_map::Keys<'_, ObjectId, String>;
+    fn names(&self) -> &Names;
+    fn hashes(&self) -> &Hashes;
+    /// Replaces all names and hashes at once, for example when restoring exported metadata. Like
+    /// other changes to reference formats, this is only persisted on [`flush`](Self::flush).
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()>;
+    /// Saves the reference formats, then deletes data that deleted and modified objects no
+    /// longer use.
     fn flush(&mut self) -> Result<()>;
+
+    /// Starts writing a new object without having all of its data up front, unlike
+    /// [`create_object`](Self::create_object), which reads its data twice to hash it first.
+    ///
+    /// Sources that can't stream buffer the data in memory until the writer is committed.
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
+        Ok(Box::new(BufferedObjectWriter::new(self, None)))
+    }
+
+    /// Like [`begin_object`](Self::begin_object), but replaces the data of an existing object.
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        Ok(Box::new(BufferedObjectWriter::new(self, Some(*id))))
+    }
+
+    /// Duplicates an object's data under a new ID, which starts out without a name, and returns
+    /// the new ID. Sources that can copy stored objects directly do so without reading them.
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        let mut reader = self.object(id)?;
+        let mut writer = self.begin_object()?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.commit()
+    }
+
+    /// Gives an object a new name in one step, replacing any name it had. Fails with
+    /// [`NameTaken`](crate::error::QuocoError::NameTaken) without changing anything if another
+    /// object already has the name.
+    fn rename_object(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        if self.hashes().get_hash(id).is_none() && self.names().get_name(id).is_none() {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        match self.object_id_with_name(name)? {
+            Some(other_id) if other_id != id => Err(NameTaken(name.into())),
+            _ => self.set_object_name(id, name),
+        }
+    }
+
+    /// Applies a batch of changes and saves names and hashes once at the end, returning the IDs
+    /// of created objects in the order they were added. If any change fails, the source's
+    /// metadata is left as it was before the batch.
+    ///
+    /// Sources that keep their own links between objects and stored data write in parallel and
+    /// only replace data once the batch is saved. Others apply changes one at a time and can't
+    /// undo modified data if a later change fails.
+    fn apply_batch(&mut self, batch: Batch) -> Result<Vec<ObjectId>> {
+        apply_batch_sequentially(self, batch)
+    }
+
+    /// Reads `len` bytes of an object starting at `offset`, or fewer if the object ends first.
+    ///
+    /// This only saves work past the end of the range: sources that can fetch stored data in
+    /// pieces stop once the range is read, so reading the start of a large object is cheap.
+    /// Objects are encrypted as one stream, so everything before `offset` is still fetched and
+    /// decrypted, and reading near the end costs as much as reading the whole object.
+    fn object_range(&self, id: &ObjectId, offset: u64, len: u64) -> Result<Box<dyn Read>> {
+        read_range(self.object(id)?, offset, len)
+    }
+
+    /// Info about an object, or `None` if the source doesn't know of it.
+    fn stat(&self, id: &ObjectId) -> Result<Option<ObjectInfo>> {
+        Ok(ObjectInfo::from_metadata(id, self.names(), self.hashes()))
+    }
+
+    /// Info about every object in no particular order. Sources that can see their storage
+    /// directly list orphans too.
+    fn list(&self) -> Result<Box<dyn Iterator<Item = ObjectInfo> + '_>> {
+        let ids: HashSet<&ObjectId> = self
+            .hashes()
+            .get_ids()
+            .chain(self.names().get_ids())
+            .collect();
+        let infos = ids
+            .into_iter()
+            .filter_map(|id| self.stat(id).transpose())
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Box::new(infos.into_iter()))
+    }
 }
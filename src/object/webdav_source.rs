@@ -0,0 +1,409 @@
+use std::io;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+use reqwest::blocking::{Body, Client, RequestBuilder, Response};
+use reqwest::{Method, StatusCode, Url};
+use tempfile::NamedTempFile;
+
+use crate::error::QuocoError;
+use crate::formats::{Hashes, Links, Names, ReferenceFormat};
+use crate::object::blob_store::{BlobStore, LinkedBlobs};
+use crate::object::fs_source::LOCK_FILE_NAME;
+use crate::object::{
+    Finish, Key, ObjectHash, ObjectId, ObjectSource, ObjectWriter, QuocoReader, QuocoWriter,
+};
+use crate::util::bytes_to_hex_str;
+use crate::{ReadSeek, Result};
+use std::collections::hash_map::Keys;
+use std::time::SystemTime;
+
+const LOCK_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:lockinfo xmlns:D="DAV:">
+  <D:lockscope><D:exclusive/></D:lockscope>
+  <D:locktype><D:write/></D:locktype>
+  <D:owner>quocofs</D:owner>
+</D:lockinfo>"#;
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:resourcetype/></D:prop></D:propfind>"#;
+
+#[derive(Clone)]
+pub struct WebDavCredentials {
+    pub username: String,
+    /// For Nextcloud and ownCloud, this should be an app password.
+    pub password: String,
+}
+
+/// How the vault is locked on the server.
+enum WebDavLock {
+    None,
+    /// A WebDAV `LOCK` on the lock file, released with `UNLOCK` using this token.
+    Token(String),
+    /// A plain lock file, for servers without `LOCK` support.
+    File,
+}
+
+/// Minimal WebDAV client for the files in one collection.
+struct WebDavClient {
+    http: Client,
+    /// URL of the collection holding the vault, always ending with `/`.
+    url: Url,
+    credentials: WebDavCredentials,
+}
+
+impl WebDavClient {
+    fn request(&self, method: Method, name: &str) -> Result<RequestBuilder> {
+        let url = self
+            .url
+            .join(name)
+            .map_err(|_| QuocoError::WebDavError(format!("Invalid resource name {}", name)))?;
+        Ok(self
+            .http
+            .request(method, url)
+            .basic_auth(&self.credentials.username, Some(&self.credentials.password)))
+    }
+
+    fn check_status(response: Response) -> Result<Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        Err(QuocoError::WebDavError(format!(
+            "Request failed with status {}: {}",
+            response.status(),
+            response.text().unwrap_or_default()
+        )))
+    }
+
+    fn get(&self, name: &str) -> Result<Option<Response>> {
+        let response = self.request(Method::GET, name)?.send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Self::check_status(response).map(Some)
+    }
+
+    fn exists(&self, name: &str) -> Result<bool> {
+        let response = self
+            .request(Method::from_bytes(b"PROPFIND").unwrap(), name)?
+            .header("Depth", "0")
+            .header("Content-Type", "application/xml")
+            .body(PROPFIND_BODY)
+            .send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Self::check_status(response)?;
+        Ok(true)
+    }
+
+    fn put<B: Into<Body>>(&self, name: &str, data: B) -> Result<()> {
+        Self::check_status(self.request(Method::PUT, name)?.body(data).send()?)?;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> Result<()> {
+        let response = self.request(Method::DELETE, name)?.send()?;
+        if response.status() != StatusCode::NOT_FOUND {
+            Self::check_status(response)?;
+        }
+        Ok(())
+    }
+
+    fn load_reference_format<F: ReferenceFormat>(&self, mut format: F, key: &Key) -> Result<F> {
+        if let Some(data) = self.get(F::specification().name)? {
+            format.load(&mut BufReader::new(QuocoReader::new(data, key)))?;
+        }
+        Ok(format)
+    }
+
+    fn save_reference_format<F: ReferenceFormat>(&self, format: &F, key: &Key) -> Result<()> {
+        let mut writer = QuocoWriter::new(tempfile::tempfile()?, key);
+        format.save(&mut writer)?;
+        let mut file = writer.finish()?;
+        file.seek(SeekFrom::Start(0))?;
+        self.put(F::specification().name, file)
+    }
+
+    fn save_reference_formats(&self, blobs: &LinkedBlobs, key: &Key) -> Result<()> {
+        self.save_reference_format(&blobs.hashes, key)?;
+        self.save_reference_format(&blobs.names, key)?;
+        self.save_reference_format(&blobs.links, key)?;
+        Ok(())
+    }
+}
+
+impl BlobStore for WebDavClient {
+    fn read_blob(&self, blob_id: &ObjectId) -> Result<Box<dyn Read>> {
+        match self.get(&bytes_to_hex_str(blob_id))? {
+            Some(response) => Ok(Box::new(response)),
+            None => Err(io::Error::from(io::ErrorKind::NotFound).into()),
+        }
+    }
+
+    fn put_blob(&self, blob_id: &ObjectId, staged: NamedTempFile) -> Result<()> {
+        let mut file = staged.into_file();
+        file.seek(SeekFrom::Start(0))?;
+        self.put(&bytes_to_hex_str(blob_id), file)
+    }
+
+    fn delete_blob(&self, blob_id: &ObjectId) -> Result<()> {
+        self.delete(&bytes_to_hex_str(blob_id))
+    }
+}
+
+/// Stores a vault in a WebDAV collection, such as a Nextcloud or ownCloud folder.
+///
+/// The vault is locked with a WebDAV `LOCK` on its lock file where the server supports it, and
+/// with a plain lock file (as [`FsObjectSource`] does) where it doesn't. Either kind blocks other
+/// sessions.
+///
+/// [`FsObjectSource`]: crate::object::FsObjectSource
+pub struct WebDavObjectSource {
+    blobs: LinkedBlobs,
+    client: WebDavClient,
+    key: Key,
+    lock: WebDavLock,
+}
+
+impl WebDavObjectSource {
+    pub fn open(url: &str, credentials: WebDavCredentials, key: &Key) -> Result<Self> {
+        let url = if url.ends_with('/') {
+            url.to_string()
+        } else {
+            format!("{}/", url)
+        };
+
+        let mut source = WebDavObjectSource {
+            blobs: LinkedBlobs::default(),
+            client: WebDavClient {
+                http: Client::new(),
+                url: Url::parse(&url)
+                    .map_err(|_| QuocoError::WebDavError(format!("Invalid URL {}", url)))?,
+                credentials,
+            },
+            key: *key,
+            lock: WebDavLock::None,
+        };
+
+        source.check_no_lock()?;
+        let client = &source.client;
+        source.blobs = LinkedBlobs::new(
+            client.load_reference_format(Names::new(), key)?,
+            client.load_reference_format(Hashes::new(), key)?,
+            client.load_reference_format(Links::new(), key)?,
+        );
+
+        // Only acquire lock after decryption works
+        source.acquire_lock()?;
+
+        Ok(source)
+    }
+
+    pub fn unlock(&mut self) -> Result<()> {
+        if let WebDavLock::Token(token) = &self.lock {
+            WebDavClient::check_status(
+                self.client
+                    .request(Method::from_bytes(b"UNLOCK").unwrap(), LOCK_FILE_NAME)?
+                    .header("Lock-Token", format!("<{}>", token))
+                    .send()?,
+            )?;
+        }
+        // LOCK creates the lock file if it doesn't exist, so it's removed either way
+        self.client.delete(LOCK_FILE_NAME)?;
+        self.lock = WebDavLock::None;
+        Ok(())
+    }
+
+    fn check_lock(&self) -> Result<()> {
+        if let WebDavLock::None = self.lock {
+            return Err(QuocoError::SessionDisposed);
+        }
+        Ok(())
+    }
+
+    /// When enabled, objects with identical contents share a single stored file, so they are
+    /// only uploaded once. Objects that already share storage keep sharing it after this is
+    /// disabled.
+    pub fn set_dedupe(&mut self, enabled: bool) {
+        self.blobs.dedupe = enabled;
+    }
+
+    /// Locks the lock file with `LOCK`, falling back to creating it when the server doesn't
+    /// support locking.
+    fn acquire_lock(&mut self) -> Result<()> {
+        let response = self
+            .client
+            .request(Method::from_bytes(b"LOCK").unwrap(), LOCK_FILE_NAME)?
+            .header("Timeout", "Infinite")
+            .header("Content-Type", "application/xml")
+            .body(LOCK_BODY)
+            .send()?;
+
+        match response.status() {
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED => {
+                self.client.put(LOCK_FILE_NAME, Vec::new())?;
+                self.lock = WebDavLock::File;
+            }
+            StatusCode::LOCKED => return Err(self.locked_error()),
+            _ => {
+                let response = WebDavClient::check_status(response)?;
+                let token = response
+                    .headers()
+                    .get("Lock-Token")
+                    .and_then(|token| token.to_str().ok())
+                    .map(|token| token.trim_matches(|c| c == '<' || c == '>').to_string())
+                    .ok_or_else(|| {
+                        QuocoError::WebDavError("LOCK response has no lock token".into())
+                    })?;
+                self.lock = WebDavLock::Token(token);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_no_lock(&self) -> Result<()> {
+        if self.client.exists(LOCK_FILE_NAME)? {
+            return Err(self.locked_error());
+        }
+        Ok(())
+    }
+
+    fn locked_error(&self) -> QuocoError {
+        QuocoError::SessionPathLocked(self.client.url.to_string())
+    }
+}
+
+impl ObjectSource for WebDavObjectSource {
+    fn object(&self, id: &ObjectId) -> Result<Box<dyn Read>> {
+        self.check_lock()?;
+
+        Ok(Box::new(QuocoReader::new(
+            self.client.read_blob(&self.blobs.blob_id(id))?,
+            &self.key,
+        )))
+    }
+
+    fn begin_object(&mut self) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.client, &self.key, None)
+    }
+
+    fn begin_modify_object(&mut self, id: &ObjectId) -> Result<Box<dyn ObjectWriter + '_>> {
+        self.check_lock()?;
+
+        self.blobs.begin_write(&self.client, &self.key, Some(*id))
+    }
+
+    fn copy_object(&mut self, id: &ObjectId) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.copy(&self.client, id)
+    }
+
+    fn object_exists(&self, id: &ObjectId) -> Result<bool> {
+        self.check_lock()?;
+
+        let blob_id = self.blobs.blob_id(id);
+        Ok(!self.blobs.is_released(&blob_id) && self.client.exists(&bytes_to_hex_str(&blob_id))?)
+    }
+
+    fn delete_object(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.delete(&self.client, id)
+    }
+
+    fn create_object(&mut self, reader: &mut Box<dyn ReadSeek>) -> Result<ObjectId> {
+        self.check_lock()?;
+
+        self.blobs.write(&self.client, &self.key, None, reader)
+    }
+
+    fn modify_object(&mut self, id: &ObjectId, reader: &mut Box<dyn ReadSeek>) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs
+            .write(&self.client, &self.key, Some(*id), reader)
+            .map(|_| ())
+    }
+
+    fn object_hash(&self, id: &ObjectId) -> Result<Option<&ObjectHash>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.hashes.get_hash(id))
+    }
+
+    fn object_name(&self, id: &ObjectId) -> Result<Option<&String>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_name(id))
+    }
+
+    fn object_id_with_name(&self, name: &str) -> Result<Option<&ObjectId>> {
+        self.check_lock()?;
+
+        Ok(self.blobs.names.get_id(name))
+    }
+
+    fn set_object_name(&mut self, id: &ObjectId, name: &str) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.set_name(id, name);
+
+        Ok(())
+    }
+
+    fn remove_object_name(&mut self, id: &ObjectId) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.remove_name(id);
+
+        Ok(())
+    }
+
+    fn last_updated(&self) -> &SystemTime {
+        self.blobs.hashes.get_last_updated()
+    }
+
+    fn hashes_ids(&mut self) -> Keys<'_, ObjectId, ObjectHash> {
+        self.blobs.hashes.get_ids()
+    }
+
+    fn names_ids(&mut self) -> Keys<'_, ObjectId, String> {
+        self.blobs.names.get_ids()
+    }
+
+    fn names(&self) -> &Names {
+        &self.blobs.names
+    }
+
+    fn hashes(&self) -> &Hashes {
+        &self.blobs.hashes
+    }
+
+    fn replace_reference_formats(&mut self, names: Names, hashes: Hashes) -> Result<()> {
+        self.check_lock()?;
+
+        self.blobs.replace(names, hashes);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.check_lock()?;
+
+        let (client, key) = (&self.client, &self.key);
+        self.blobs
+            .flush(client, |blobs| client.save_reference_formats(blobs, key))
+    }
+}
+
+impl Drop for WebDavObjectSource {
+    fn drop(&mut self) {
+        if self.check_lock().is_ok() {
+            self.unlock()
+                .expect("Failed to release lock. You may have to release it manually.");
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use crate::directory::{export_directory, import_directory, DirectoryExport, DirectoryImport};
 use crate::error::QuocoError;
-use crate::error::QuocoError::{NoRemotes, TempFileDeletesFailed};
+use crate::error::QuocoError::{
+    InvalidUrl, RemoteNotFound, RemoteSyncsFailed, SessionNotFound, TempFileDeletesFailed,
+};
+use crate::formats::{export_json, import_json, invalid_json};
 use crate::object::{
-    BoxedObjectSource, CachedObjectSource, FsObjectSource, Key, ObjectId, ObjectSource,
-    RemoteSource, RemoteSourceConfig,
+    open_source_url, BoxedObjectSource, CachedObjectSource, ChunkedObjectSource, FsObjectSource,
+    Key, MemoryObjectSource, ObjectId, ObjectSource, RemoteSourceConfig,
 };
 use crate::util::{
     bytes_to_hex_str, delete_file, is_shred_available, shred_file, sync_primary_replica,
@@ -10,113 +14,326 @@ use crate::util::{
 use crate::UuidBytes;
 use crate::{ReadSeek, Result};
 use lazy_static::lazy_static;
-use owning_ref::{MutexGuardRef, OwningRef};
-use std::cell::RefCell;
-use std::collections::hash_map::RandomState;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::{Mutex, MutexGuard};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
 use std::{env, io};
+use url::Url;
 use uuid::Uuid;
 
+/// A session that can be shared between threads. Any number of threads can read from it at once,
+/// while changes wait for the write lock.
+pub type SessionHandle = Arc<RwLock<dyn VaultSession>>;
+
 lazy_static! {
-    pub static ref SESSIONS: Mutex<HashMap<UuidBytes, RefCell<Session>>> =
-        Mutex::new(HashMap::new());
+    /// Open sessions by ID. The map itself is only locked long enough to add, remove or look up a
+    /// session, so work on one session never waits for work on another.
+    pub static ref SESSIONS: RwLock<HashMap<UuidBytes, SessionHandle>> =
+        RwLock::new(HashMap::new());
 }
 
-type SessionMutexGuard<'a> =
-    OwningRef<MutexGuard<'a, HashMap<UuidBytes, RefCell<Session>, RandomState>>, RefCell<Session>>;
+/// How a session's local vault is opened, beyond where it is.
+#[derive(Clone, Debug, Default)]
+pub struct VaultOptions {
+    /// Makes objects with identical contents share storage, like
+    /// [`FsObjectSource::set_dedupe`]. Given as `dedupe=1` for vaults opened from URLs.
+    pub dedupe: bool,
+    /// Stores objects as chunks through a [`ChunkedObjectSource`]. Given as `chunked=1` for
+    /// vaults opened from URLs.
+    pub chunked: bool,
+}
 
-// TODO: Modularize this so remote backends can be swapped out (a factory enum might do just fine)
+/// Opens a session on the local vault at `local`, with a named remote for each of
+/// `remote_configs`.
+///
+/// `local` is either a directory path or a URL like `file:///srv/vault` or `mem://` with a
+/// registered scheme (see [`open_source_url`]).
 pub fn new_session(
-    local_path: &str,
+    local: &str,
+    key: &Key,
+    remote_configs: Vec<(String, RemoteSourceConfig)>,
+) -> Result<UuidBytes> {
+    new_session_with_options(local, key, remote_configs, &VaultOptions::default())
+}
+
+/// Like [`new_session`], opening the local vault with `options`.
+pub fn new_session_with_options(
+    local: &str,
+    key: &Key,
+    remote_configs: Vec<(String, RemoteSourceConfig)>,
+    options: &VaultOptions,
+) -> Result<UuidBytes> {
+    let local = open_local(local, key, options)?;
+    Ok(insert_session(Session::open(
+        local,
+        open_remotes(key, remote_configs)?,
+    )?))
+}
+
+/// Like [`new_session`], but the local vault is kept in memory and discarded when the session
+/// is closed.
+pub fn new_memory_session(
     key: &Key,
-    remote_config: Option<RemoteSourceConfig>,
+    remote_configs: Vec<(String, RemoteSourceConfig)>,
 ) -> Result<UuidBytes> {
+    Ok(insert_session(Session::open(
+        Box::new(MemoryObjectSource::new(key)),
+        open_remotes(key, remote_configs)?,
+    )?))
+}
+
+/// Opens a [`LocalSession`] on the local vault at `local`, given as for [`new_session`].
+pub fn new_local_session(local: &str, key: &Key) -> Result<UuidBytes> {
+    new_local_session_with_options(local, key, &VaultOptions::default())
+}
+
+/// Like [`new_local_session`], opening the vault with `options`.
+pub fn new_local_session_with_options(
+    local: &str,
+    key: &Key,
+    options: &VaultOptions,
+) -> Result<UuidBytes> {
+    Ok(insert_session(LocalSession::open(open_local(
+        local, key, options,
+    )?)))
+}
+
+/// Opens a [`RemoteSession`] that works directly on the remote described by `remote_config`.
+pub fn new_remote_session(remote_config: RemoteSourceConfig, key: &Key) -> Result<UuidBytes> {
+    Ok(insert_session(RemoteSession::open(
+        remote_config.open(key)?,
+    )))
+}
+
+fn open_local(local: &str, key: &Key, options: &VaultOptions) -> Result<BoxedObjectSource> {
+    if local.contains("://") {
+        if !options.dedupe && !options.chunked {
+            return open_source_url(local, key);
+        }
+        let mut url = Url::parse(local).map_err(|err| InvalidUrl(format!("{}: {}", local, err)))?;
+        for (name, enabled) in [("dedupe", options.dedupe), ("chunked", options.chunked)].iter() {
+            if *enabled {
+                url.query_pairs_mut().append_pair(name, "1");
+            }
+        }
+        open_source_url(url.as_str(), key)
+    } else {
+        let mut source = FsObjectSource::open(Path::new(local), key)?;
+        source.set_dedupe(options.dedupe);
+        if options.chunked {
+            Ok(Box::new(ChunkedObjectSource::open(Box::new(source), key)?))
+        } else {
+            Ok(Box::new(source))
+        }
+    }
+}
+
+fn open_remotes(
+    key: &Key,
+    remote_configs: Vec<(String, RemoteSourceConfig)>,
+) -> Result<Vec<(String, BoxedObjectSource)>> {
+    remote_configs
+        .into_iter()
+        .map(|(name, config)| config.open(key).map(|source| (name, source)))
+        .collect()
+}
+
+fn insert_session<S: VaultSession + 'static>(session: S) -> UuidBytes {
     let uuid = *Uuid::new_v4().as_bytes();
-    let remote_accessor = remote_config
-        .map(|c| {
-            RemoteSource::initialize(c, key)
-                .map(|w| match w {
-                    RemoteSource::GoogleStorage(accessor) => Box::new(accessor),
-                })
-                .map(|b| b as BoxedObjectSource)
-        })
-        .transpose()?;
-
-    let new_session = Session::open(
-        Box::new(FsObjectSource::open(
-            PathBuf::from(local_path).as_path(),
-            key,
-        )?),
-        remote_accessor,
-    )?;
     SESSIONS
-        .lock()
+        .write()
         .unwrap()
-        .insert(uuid, RefCell::new(new_session));
-    Ok(uuid)
+        .insert(uuid, Arc::new(RwLock::new(session)));
+    uuid
 }
 
-pub fn get_session<'a>(id: &UuidBytes) -> SessionMutexGuard<'a> {
-    MutexGuardRef::new(SESSIONS.lock().unwrap()).map(|g| g.get(id).unwrap())
+/// Looks up an open session, failing with [`SessionNotFound`] if there isn't one with `id`.
+pub fn get_session(id: &UuidBytes) -> Result<SessionHandle> {
+    SESSIONS
+        .read()
+        .unwrap()
+        .get(id)
+        .cloned()
+        .ok_or_else(|| SessionNotFound(bytes_to_hex_str(id)))
 }
 
+/// Removes a session from the open sessions. It's dropped, releasing its locks, once any handles
+/// other threads still hold are gone too.
 pub fn close_session(id: &UuidBytes) -> bool {
-    SESSIONS.lock().unwrap().remove(id).is_some()
+    SESSIONS.write().unwrap().remove(id).is_some()
 }
 
 pub fn clear_sessions() {
     // Sessions release locks when they are dropped
-    SESSIONS.lock().unwrap().clear()
+    SESSIONS.write().unwrap().clear()
 }
 
-// TODO: Make a local-only variant of Session
-pub struct Session {
-    pub local: CachedObjectSource,
-    pub remote: Option<CachedObjectSource>,
-    temp_files: HashMap<ObjectId, PathBuf>,
+pub struct Remote {
+    pub source: CachedObjectSource,
+    pub last_pushed: Option<SystemTime>,
+    pub last_pulled: Option<SystemTime>,
+    /// Error from the most recent push or pull, cleared by the next successful one.
+    pub last_error: Option<String>,
 }
 
-impl Session {
-    pub fn open(accessor: BoxedObjectSource, remote: Option<BoxedObjectSource>) -> Result<Self> {
-        Ok(Session {
-            local: CachedObjectSource::new(accessor),
-            remote: remote.map(|s| CachedObjectSource::new(s)),
-            temp_files: HashMap::new(),
-        })
+/// How a remote compares to the local vault, as reported by [`VaultSession::remote_status`].
+#[derive(Debug, Clone)]
+pub struct RemoteStatus {
+    pub name: String,
+    /// Objects that only exist locally.
+    pub local_only: usize,
+    /// Objects that only exist on the remote.
+    pub remote_only: usize,
+    /// Objects whose contents differ between the local vault and the remote.
+    pub modified: usize,
+    pub last_pushed: Option<SystemTime>,
+    pub last_pulled: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+
+impl RemoteStatus {
+    pub fn in_sync(&self) -> bool {
+        self.local_only == 0 && self.remote_only == 0 && self.modified == 0
+    }
+}
+
+/// What every kind of session can do, so callers don't have to care whether objects live in a
+/// local vault, on a remote, or both.
+///
+/// Sessions without remotes to sync with fail with [`RemoteNotFound`] when asked for a remote by
+/// name, and pushing or pulling all remotes does nothing.
+pub trait VaultSession: Send + Sync {
+    /// Where objects are read from and written to: the local vault, or the remote for a
+    /// [`RemoteSession`].
+    fn source(&self) -> &CachedObjectSource;
+    fn source_mut(&mut self) -> &mut CachedObjectSource;
+    /// Copies an object out to a temporary file with the extension `ext` for other programs to
+    /// use, returning the same file on later calls.
+    fn object_temp_file(&mut self, id: &ObjectId, ext: &str) -> Result<PathBuf>;
+    /// Writes temporary files back to their objects and removes them.
+    fn clear_temp_files(&mut self) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        self.source_mut().flush()
+    }
+
+    /// Names of the remotes this session syncs with, in name order.
+    fn remote_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn remote(&mut self, name: &str) -> Result<&mut CachedObjectSource> {
+        Err(RemoteNotFound(name.into()))
+    }
+
+    fn push_remote(&mut self, name: &str) -> Result<()> {
+        Err(RemoteNotFound(name.into()))
+    }
+
+    fn pull_remote(&mut self, name: &str) -> Result<()> {
+        Err(RemoteNotFound(name.into()))
+    }
+
+    /// Pushes to every remote, even if pushing to some of them fails.
+    fn push_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Pulls from every remote in name order, even if pulling from some of them fails.
+    fn pull_all(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn remote_status(&self, name: &str) -> Result<RemoteStatus> {
+        Err(RemoteNotFound(name.into()))
+    }
+
+    /// Statuses of all remotes in name order.
+    fn remote_statuses(&self) -> Result<Vec<RemoteStatus>> {
+        self.remote_names()
+            .iter()
+            .map(|name| self.remote_status(name))
+            .collect()
+    }
+
+    /// Writes the session's names and hashes as plaintext JSON. See
+    /// [`crate::formats::export_json`] for the schema.
+    fn export_metadata_json(&self, writer: &mut dyn Write) -> Result<()> {
+        export_json(self.source().names(), self.source().hashes(), writer)
     }
 
-    pub fn object_temp_file(&mut self, id: &ObjectId, ext: &str) -> Result<PathBuf> {
-        if self.temp_files.contains_key(id) {
-            return Ok(self.temp_files[id].clone());
+    /// Replaces the session's names and hashes with ones read from JSON written by
+    /// [`export_metadata_json`](Self::export_metadata_json) and flushes them. Nothing is replaced
+    /// if the JSON has names or hashes for objects that aren't stored in the vault.
+    fn import_metadata_json(&mut self, reader: &mut dyn Read) -> Result<()> {
+        let (names, hashes) = import_json(reader)?;
+        let ids = names.iter().map(|(id, _)| id).chain(hashes.get_ids());
+        for id in ids {
+            if !self.source().object_exists(id)? {
+                return Err(invalid_json(format!(
+                    "object {} isn't stored in this vault",
+                    bytes_to_hex_str(id)
+                )));
+            }
+        }
+        self.source_mut().replace_reference_formats(names, hashes)?;
+        self.source_mut().flush()
+    }
+
+    /// Creates or updates an object for every file under `path`, named by relative path.
+    fn import_directory(&mut self, path: &Path) -> Result<DirectoryImport> {
+        import_directory(self.source_mut(), path)
+    }
+
+    /// Writes every named object to its relative path under `path`.
+    fn export_directory(&mut self, path: &Path) -> Result<DirectoryExport> {
+        export_directory(self.source_mut(), path)
+    }
+}
+
+/// Objects copied out to temporary files by [`VaultSession::object_temp_file`].
+#[derive(Default)]
+struct TempFiles {
+    paths: HashMap<ObjectId, PathBuf>,
+}
+
+impl TempFiles {
+    fn path(
+        &mut self,
+        source: &mut CachedObjectSource,
+        id: &ObjectId,
+        ext: &str,
+    ) -> Result<PathBuf> {
+        if self.paths.contains_key(id) {
+            return Ok(self.paths[id].clone());
         }
 
         let temp_file_path = env::temp_dir().join(Path::new(
             format!("{}.{}", bytes_to_hex_str(id), ext).as_str(),
         ));
         io::copy(
-            &mut self.local.object(id)?,
+            &mut source.object(id)?,
             &mut File::create(temp_file_path.clone())?,
         )?;
-        self.temp_files.insert(*id, temp_file_path.clone());
+        self.paths.insert(*id, temp_file_path.clone());
 
         Ok(temp_file_path)
     }
 
-    pub fn clear_temp_files(&mut self) -> Result<()> {
+    fn clear(&mut self, source: &mut CachedObjectSource) -> Result<()> {
         let can_shred = is_shred_available();
-        let local = &mut self.local;
         let file_delete_errors = self
-            .temp_files
+            .paths
             .drain()
             .map(|(id, path)| {
                 (
                     path.clone(),
                     File::open(path.clone())
                         .map_err(QuocoError::from)
-                        .map(|f| local.modify_object(&id, &mut (Box::new(f) as Box<dyn ReadSeek>)))
+                        .map(|f| source.modify_object(&id, &mut (Box::new(f) as Box<dyn ReadSeek>)))
                         .and_then(|_| {
                             if can_shred {
                                 shred_file(path.as_path())?;
@@ -142,47 +359,163 @@ impl Session {
 
         Ok(())
     }
+}
+
+/// A session on a local vault with no remotes.
+pub struct LocalSession {
+    pub local: CachedObjectSource,
+    temp_files: TempFiles,
+}
 
-    pub fn push_remote(&mut self) -> Result<()> {
-        if self.remote.is_none() {
-            return Ok(());
+impl LocalSession {
+    pub fn open(local: BoxedObjectSource) -> Self {
+        LocalSession {
+            local: CachedObjectSource::new(local),
+            temp_files: TempFiles::default(),
         }
-        self.sync(SyncFrom::Local)
+    }
+}
+
+impl VaultSession for LocalSession {
+    fn source(&self) -> &CachedObjectSource {
+        &self.local
     }
 
-    pub fn pull_remote(&mut self) -> Result<()> {
-        if self.remote.is_none() {
-            return Ok(());
+    fn source_mut(&mut self) -> &mut CachedObjectSource {
+        &mut self.local
+    }
+
+    fn object_temp_file(&mut self, id: &ObjectId, ext: &str) -> Result<PathBuf> {
+        self.temp_files.path(&mut self.local, id, ext)
+    }
+
+    fn clear_temp_files(&mut self) -> Result<()> {
+        self.temp_files.clear(&mut self.local)
+    }
+}
+
+/// A session that reads and writes a remote directly, without keeping a local copy, for clients
+/// that keep everything in the cloud.
+pub struct RemoteSession {
+    pub remote: CachedObjectSource,
+    temp_files: TempFiles,
+}
+
+impl RemoteSession {
+    pub fn open(remote: BoxedObjectSource) -> Self {
+        RemoteSession {
+            remote: CachedObjectSource::new(remote),
+            temp_files: TempFiles::default(),
         }
-        self.sync(SyncFrom::Remote)
     }
+}
 
-    pub fn flush(&mut self) -> Result<()> {
-        self.local.flush()?;
-        if let Some(remote) = &mut self.remote {
-            remote.flush()?
+impl VaultSession for RemoteSession {
+    fn source(&self) -> &CachedObjectSource {
+        &self.remote
+    }
+
+    fn source_mut(&mut self) -> &mut CachedObjectSource {
+        &mut self.remote
+    }
+
+    fn object_temp_file(&mut self, id: &ObjectId, ext: &str) -> Result<PathBuf> {
+        self.temp_files.path(&mut self.remote, id, ext)
+    }
+
+    fn clear_temp_files(&mut self) -> Result<()> {
+        self.temp_files.clear(&mut self.remote)
+    }
+}
+
+/// A session on a local vault that syncs with any number of named remotes.
+pub struct Session {
+    pub local: CachedObjectSource,
+    /// Remotes by name, in name order
+    pub remotes: BTreeMap<String, Remote>,
+    temp_files: TempFiles,
+}
+
+impl Session {
+    pub fn open(
+        accessor: BoxedObjectSource,
+        remotes: Vec<(String, BoxedObjectSource)>,
+    ) -> Result<Self> {
+        Ok(Session {
+            local: CachedObjectSource::new(accessor),
+            remotes: remotes
+                .into_iter()
+                .map(|(name, source)| {
+                    (
+                        name,
+                        Remote {
+                            source: CachedObjectSource::new(source),
+                            last_pushed: None,
+                            last_pulled: None,
+                            last_error: None,
+                        },
+                    )
+                })
+                .collect(),
+            temp_files: TempFiles::default(),
+        })
+    }
+
+    fn sync_all(&mut self, direction: SyncFrom) -> Result<()> {
+        let names: Vec<String> = self.remotes.keys().cloned().collect();
+        let errors: Vec<(String, QuocoError)> = names
+            .into_iter()
+            .filter_map(|name| match self.sync(&name, direction) {
+                Ok(()) => None,
+                Err(err) => Some((name, err)),
+            })
+            .collect();
+
+        if !errors.is_empty() {
+            return Err(RemoteSyncsFailed(errors));
         }
+
         Ok(())
     }
 
-    fn sync(&mut self, direction: SyncFrom) -> Result<()> {
+    fn sync(&mut self, name: &str, direction: SyncFrom) -> Result<()> {
+        if !self.remotes.contains_key(name) {
+            return Err(RemoteNotFound(name.into()));
+        }
+        let result = self.sync_unchecked(name, direction);
+
+        let remote = self.remotes.get_mut(name).unwrap();
+        match &result {
+            Ok(()) => {
+                let now = Some(SystemTime::now());
+                match direction {
+                    SyncFrom::Local => remote.last_pushed = now,
+                    SyncFrom::Remote => remote.last_pulled = now,
+                }
+                remote.last_error = None;
+            }
+            Err(err) => remote.last_error = Some(err.to_string()),
+        }
+
+        result
+    }
+
+    fn sync_unchecked(&mut self, name: &str, direction: SyncFrom) -> Result<()> {
         // TODO: Implement an actual distributed change logging system:
         //  https://github.com/vinhowe/quocofs/issues/5
-        if self.remote.is_none() {
-            return Err(NoRemotes);
-        }
+        let remote = &mut self.remotes.get_mut(name).unwrap().source;
 
         let primary;
         let replica;
 
         match direction {
             SyncFrom::Remote => {
-                primary = self.remote.as_mut().unwrap();
+                primary = remote;
                 replica = &mut self.local;
             }
             SyncFrom::Local => {
                 primary = &mut self.local;
-                replica = self.remote.as_mut().unwrap();
+                replica = remote;
             }
         }
 
@@ -240,12 +573,99 @@ impl Session {
             )
         })?;
 
-        self.flush()?;
+        primary.flush()?;
+        replica.flush()?;
 
         Ok(())
     }
 }
 
+impl VaultSession for Session {
+    fn source(&self) -> &CachedObjectSource {
+        &self.local
+    }
+
+    fn source_mut(&mut self) -> &mut CachedObjectSource {
+        &mut self.local
+    }
+
+    fn object_temp_file(&mut self, id: &ObjectId, ext: &str) -> Result<PathBuf> {
+        self.temp_files.path(&mut self.local, id, ext)
+    }
+
+    fn clear_temp_files(&mut self) -> Result<()> {
+        self.temp_files.clear(&mut self.local)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.local.flush()?;
+        for remote in self.remotes.values_mut() {
+            remote.source.flush()?;
+        }
+        Ok(())
+    }
+
+    fn remote_names(&self) -> Vec<String> {
+        self.remotes.keys().cloned().collect()
+    }
+
+    fn remote(&mut self, name: &str) -> Result<&mut CachedObjectSource> {
+        self.remotes
+            .get_mut(name)
+            .map(|remote| &mut remote.source)
+            .ok_or_else(|| RemoteNotFound(name.into()))
+    }
+
+    fn push_remote(&mut self, name: &str) -> Result<()> {
+        self.sync(name, SyncFrom::Local)
+    }
+
+    fn pull_remote(&mut self, name: &str) -> Result<()> {
+        self.sync(name, SyncFrom::Remote)
+    }
+
+    fn push_all(&mut self) -> Result<()> {
+        self.sync_all(SyncFrom::Local)
+    }
+
+    fn pull_all(&mut self) -> Result<()> {
+        self.sync_all(SyncFrom::Remote)
+    }
+
+    fn remote_status(&self, name: &str) -> Result<RemoteStatus> {
+        let remote = self
+            .remotes
+            .get(name)
+            .ok_or_else(|| RemoteNotFound(name.into()))?;
+        let local_hashes = self.local.hashes();
+        let remote_hashes = remote.source.hashes();
+
+        let mut status = RemoteStatus {
+            name: name.into(),
+            local_only: 0,
+            remote_only: 0,
+            modified: 0,
+            last_pushed: remote.last_pushed,
+            last_pulled: remote.last_pulled,
+            last_error: remote.last_error.clone(),
+        };
+        for (id, hash) in local_hashes.iter() {
+            match remote_hashes.get_hash(id) {
+                None => status.local_only += 1,
+                Some(remote_hash) if remote_hash != hash => status.modified += 1,
+                Some(_) => {}
+            }
+        }
+        status.remote_only = remote_hashes
+            .get_ids()
+            .filter(|id| local_hashes.get_hash(id).is_none())
+            .count();
+
+        Ok(status)
+    }
+}
+
+#[derive(Clone, Copy)]
 enum SyncFrom {
     Remote,
     Local,
@@ -257,3 +677,15 @@ impl Drop for Session {
         self.clear_temp_files().expect("Failed to clear temp files")
     }
 }
+
+impl Drop for LocalSession {
+    fn drop(&mut self) {
+        self.clear_temp_files().expect("Failed to clear temp files")
+    }
+}
+
+impl Drop for RemoteSession {
+    fn drop(&mut self) {
+        self.clear_temp_files().expect("Failed to clear temp files")
+    }
+}
@@ -1,9 +1,11 @@
 use crate::error::QuocoError::{KeyGenerationError, TempFileDeleteFailed, UndeterminedError};
-use crate::object::{CHUNK_LENGTH, HASH_LENGTH, SALT_LENGTH};
+use crate::object::{Key, ObjectId, CHUNK_LENGTH, HASH_LENGTH, SALT_LENGTH};
 use crate::Result;
 use libsodium_sys::{
-    crypto_box_SEEDBYTES, crypto_hash_sha256_final, crypto_hash_sha256_init,
-    crypto_hash_sha256_state, crypto_hash_sha256_update, crypto_pwhash, crypto_pwhash_ALG_DEFAULT,
+    crypto_auth_hmacsha256_final, crypto_auth_hmacsha256_init, crypto_auth_hmacsha256_state,
+    crypto_auth_hmacsha256_update, crypto_box_SEEDBYTES, crypto_generichash,
+    crypto_hash_sha256_final, crypto_hash_sha256_init, crypto_hash_sha256_state,
+    crypto_hash_sha256_update, crypto_pwhash, crypto_pwhash_ALG_DEFAULT,
     crypto_pwhash_MEMLIMIT_INTERACTIVE, crypto_pwhash_OPSLIMIT_INTERACTIVE,
 };
 use std::fs;
@@ -36,18 +38,50 @@ pub fn generate_key<'a>(
     Ok(key)
 }
 
-pub fn sha256<R: Read>(reader: &mut R) -> Result<[u8; HASH_LENGTH]> {
-    let mut state = MaybeUninit::<crypto_hash_sha256_state>::uninit();
-    unsafe {
-        if crypto_hash_sha256_init(state.as_mut_ptr()) != 0 {
-            return Err(UndeterminedError);
+/// Incremental SHA-256, for hashing data as it streams past instead of reading it twice.
+pub struct Sha256 {
+    state: crypto_hash_sha256_state,
+}
+
+impl Sha256 {
+    pub fn new() -> Result<Self> {
+        let mut state = MaybeUninit::<crypto_hash_sha256_state>::uninit();
+        unsafe {
+            if crypto_hash_sha256_init(state.as_mut_ptr()) != 0 {
+                return Err(UndeterminedError);
+            }
         }
+
+        Ok(Sha256 {
+            state: unsafe { state.assume_init() },
+        })
     }
-    let mut state = unsafe { state.assume_init() };
 
-    let mut in_chunk = [0u8; CHUNK_LENGTH as usize];
+    pub fn update(&mut self, data: &[u8]) -> Result<()> {
+        unsafe {
+            if crypto_hash_sha256_update(&mut self.state, data.as_ptr(), data.len() as u64) != 0 {
+                return Err(UndeterminedError);
+            };
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<[u8; HASH_LENGTH]> {
+        let mut hash = [0u8; HASH_LENGTH];
+        unsafe {
+            if crypto_hash_sha256_final(&mut self.state, hash.as_mut_ptr()) != 0 {
+                return Err(UndeterminedError);
+            }
+        }
+        Ok(hash)
+    }
+}
+
+pub fn sha256<R: Read>(reader: &mut R) -> Result<[u8; HASH_LENGTH]> {
+    let mut hasher = Sha256::new()?;
+
+    let mut in_chunk = [0u8; CHUNK_LENGTH];
     let mut bytes_read;
-    let mut hash = [0u8; HASH_LENGTH];
     loop {
         bytes_read = reader.read(&mut in_chunk)?;
 
@@ -55,24 +89,45 @@ pub fn sha256<R: Read>(reader: &mut R) -> Result<[u8; HASH_LENGTH]> {
             break;
         }
 
-        unsafe {
-            if crypto_hash_sha256_update(
-                &mut state,
-                in_chunk[..bytes_read].as_ptr(),
-                bytes_read as u64,
-            ) != 0
-            {
-                return Err(UndeterminedError);
-            };
+        hasher.update(&in_chunk[..bytes_read])?;
+    }
+
+    hasher.finish()
+}
+
+/// Keyed BLAKE2b hash of `data`, filling all of `out` (16 to 64 bytes).
+pub fn keyed_hash(key: &Key, data: &[u8], out: &mut [u8]) -> Result<()> {
+    unsafe {
+        if crypto_generichash(
+            out.as_mut_ptr(),
+            out.len(),
+            data.as_ptr(),
+            data.len() as u64,
+            key.as_ptr(),
+            key.len(),
+        ) != 0
+        {
+            return Err(UndeterminedError);
         }
     }
+    Ok(())
+}
 
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Result<[u8; HASH_LENGTH]> {
+    let mut state = MaybeUninit::<crypto_auth_hmacsha256_state>::uninit();
+    let mut mac = [0u8; HASH_LENGTH];
     unsafe {
-        if crypto_hash_sha256_final(&mut state, hash.as_mut_ptr()) != 0 {
+        if crypto_auth_hmacsha256_init(state.as_mut_ptr(), key.as_ptr(), key.len()) != 0 {
+            return Err(UndeterminedError);
+        }
+        let mut state = state.assume_init();
+        if crypto_auth_hmacsha256_update(&mut state, data.as_ptr(), data.len() as u64) != 0
+            || crypto_auth_hmacsha256_final(&mut state, mac.as_mut_ptr()) != 0
+        {
             return Err(UndeterminedError);
         }
     }
-    Ok(hash)
+    Ok(mac)
 }
 
 pub fn is_shred_available() -> bool {
@@ -118,6 +173,13 @@ pub fn hex_str_to_bytes(hex: &str) -> Vec<u8> {
     hex::decode(hex).expect("Couldn't decode byte string")
 }
 
+/// Parses a stored object's file name back into its ID, or returns `None` if `hex` isn't one.
+pub fn hex_str_to_object_id(hex: &str) -> Option<ObjectId> {
+    let mut id = ObjectId::default();
+    hex::decode_to_slice(hex, &mut id).ok()?;
+    Some(id)
+}
+
 // TODO: Come up with a more descriptive name for this and its arguments
 pub fn sync_primary_replica<T, Ra, A>(
     primary: &Option<T>,
@@ -0,0 +1,230 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::archive::{export_archive, import_archive, NameConflict};
+use quocofs::object::HASH_LENGTH;
+use quocofs::object::{FsObjectSource, Key, ObjectId, ObjectSource};
+use quocofs::ReadSeek;
+use std::convert::TryInto;
+use std::fs;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use tempfile::tempdir;
+
+const ARCHIVE_KEY: &Key = &[3u8; 32];
+
+fn create_named(source: &mut dyn ObjectSource, name: &str, data: &[u8]) -> ObjectId {
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(data.to_vec())) as Box<dyn ReadSeek>))
+        .unwrap();
+    source.set_object_name(&id, name).unwrap();
+    id
+}
+
+fn read_named(source: &mut dyn ObjectSource, name: &str) -> Vec<u8> {
+    let id = *source.object_id_with_name(name).unwrap().unwrap();
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn archive_of(source: &mut dyn ObjectSource) -> Cursor<Vec<u8>> {
+    let mut archive = Cursor::new(Vec::new());
+    export_archive(source, &mut archive, ARCHIVE_KEY).unwrap();
+    archive.seek(SeekFrom::Start(0)).unwrap();
+    archive
+}
+
+#[test]
+fn export_import_round_trip() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut source, "a.txt", b"first object");
+    create_named(&mut source, "b.txt", b"second object");
+    let mut archive = archive_of(&mut source);
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    let import = import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Skip,
+    )
+    .unwrap();
+
+    assert_eq!(import.imported.len(), 2);
+    assert_eq!(read_named(&mut destination, "a.txt"), b"first object");
+    assert_eq!(read_named(&mut destination, "b.txt"), b"second object");
+}
+
+#[test]
+fn import_name_conflicts() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut source, "a.txt", b"archived");
+    let mut archive = archive_of(&mut source);
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut destination, "a.txt", b"existing");
+
+    let import = import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Skip,
+    )
+    .unwrap();
+    assert_eq!(import.skipped.len(), 1);
+    assert_eq!(read_named(&mut destination, "a.txt"), b"existing");
+
+    import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Rename,
+    )
+    .unwrap();
+    assert_eq!(read_named(&mut destination, "a.txt"), b"existing");
+    assert_eq!(read_named(&mut destination, "a.txt (1)"), b"archived");
+
+    import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Overwrite,
+    )
+    .unwrap();
+    assert_eq!(read_named(&mut destination, "a.txt"), b"archived");
+}
+
+#[test]
+fn reject_corrupted_archive() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut source, "a.txt", b"archived");
+    let mut archive = archive_of(&mut source).into_inner();
+    archive[10] ^= 0xff;
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    assert!(import_archive(
+        &mut Cursor::new(archive),
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Skip
+    )
+    .is_err());
+}
+
+#[test]
+fn import_into_source_with_same_ids() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    let id = create_named(&mut source, "a.txt", b"archived");
+    let mut archive = archive_of(&mut source);
+    source
+        .modify_object(
+            &id,
+            &mut (Box::new(Cursor::new(b"changed since".to_vec())) as Box<dyn ReadSeek>),
+        )
+        .unwrap();
+
+    let import =
+        import_archive(&mut archive, ARCHIVE_KEY, &mut source, NameConflict::Rename).unwrap();
+    assert_ne!(import.imported, vec![id]);
+    assert_eq!(read_named(&mut source, "a.txt"), b"changed since");
+    assert_eq!(read_named(&mut source, "a.txt (1)"), b"archived");
+}
+
+#[test]
+fn failed_import_leaves_destination_unchanged() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    for name in ["a.txt", "b.txt", "c.txt"].iter() {
+        create_named(&mut source, name, name.as_bytes());
+    }
+    let mut archive = archive_of(&mut source).into_inner();
+
+    // Corrupt the last object's data, which comes just before its length and the index, and fix
+    //  up the checksum so the import gets as far as reading it
+    let footer_offset = archive.len() - (8 + HASH_LENGTH + 4);
+    let index_offset = u64::from_le_bytes(
+        archive[footer_offset..footer_offset + 8]
+            .try_into()
+            .unwrap(),
+    ) as usize;
+    let last_length =
+        u64::from_le_bytes(archive[index_offset - 8..index_offset].try_into().unwrap()) as usize;
+    archive[index_offset - 8 - last_length / 2] ^= 0xff;
+    let checksum = ring::digest::digest(&ring::digest::SHA256, &archive[..footer_offset]);
+    archive[footer_offset + 8..footer_offset + 8 + HASH_LENGTH].copy_from_slice(checksum.as_ref());
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut destination, "a.txt", b"existing");
+    assert!(import_archive(
+        &mut Cursor::new(archive),
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Overwrite
+    )
+    .is_err());
+
+    assert_eq!(read_named(&mut destination, "a.txt"), b"existing");
+    assert_eq!(destination.list().unwrap().count(), 1);
+}
+
+#[test]
+fn renamed_imports_never_collide() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut source, "a.txt", b"archived");
+    create_named(&mut source, "a.txt (1)", b"archived copy");
+    let mut archive = archive_of(&mut source);
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut destination, "a.txt", b"existing");
+
+    import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Rename,
+    )
+    .unwrap();
+    assert_eq!(read_named(&mut destination, "a.txt"), b"existing");
+    assert_eq!(read_named(&mut destination, "a.txt (1)"), b"archived copy");
+    assert_eq!(read_named(&mut destination, "a.txt (2)"), b"archived");
+}
+
+#[test]
+fn failed_naming_leaves_destination_unchanged() {
+    let source_dir = tempdir().unwrap();
+    let mut source = FsObjectSource::open(source_dir.path(), TEST_KEY).unwrap();
+    for name in ["a.txt", "b.txt"].iter() {
+        create_named(&mut source, name, name.as_bytes());
+    }
+    let mut archive = archive_of(&mut source);
+
+    let destination_dir = tempdir().unwrap();
+    let mut destination = FsObjectSource::open(destination_dir.path(), TEST_KEY).unwrap();
+    create_named(&mut destination, "a.txt", b"existing");
+    destination.flush().unwrap();
+    // Every object imports, but saving the names they're given fails
+    fs::remove_file(destination_dir.path().join("links")).unwrap();
+    fs::create_dir(destination_dir.path().join("links")).unwrap();
+
+    assert!(import_archive(
+        &mut archive,
+        ARCHIVE_KEY,
+        &mut destination,
+        NameConflict::Overwrite
+    )
+    .is_err());
+
+    assert_eq!(read_named(&mut destination, "a.txt"), b"existing");
+    assert_eq!(destination.object_id_with_name("b.txt").unwrap(), None);
+    assert_eq!(destination.list().unwrap().count(), 1);
+}
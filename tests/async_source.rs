@@ -0,0 +1,119 @@
+#![cfg(feature = "async")]
+
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{
+    AsyncObjectReader, AsyncObjectSource, AsyncQuocoReader, AsyncQuocoWriter, FsObjectSource,
+    ObjectSource, QuocoReader, TokioObjectSource,
+};
+use std::io::{Cursor, Read};
+use tempfile::tempdir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+fn reader(data: &[u8]) -> AsyncObjectReader {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+async fn read(mut reader: AsyncObjectReader) -> Vec<u8> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.unwrap();
+    data
+}
+
+fn test_data() -> Vec<u8> {
+    // Enough to fill the pipes between async code and the blocking pool several times over
+    (0..1024 * 1024).map(|i| (i * 7 % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn async_reader_and_writer_match_sync_ones() {
+    let data = test_data();
+    let mut writer = AsyncQuocoWriter::new(Vec::new(), TEST_KEY);
+    for piece in data.chunks(10_000) {
+        writer.write_all(piece).await.unwrap();
+    }
+    let encrypted = writer.finish().await.unwrap();
+
+    let mut decrypted = Vec::new();
+    QuocoReader::new(Cursor::new(encrypted.clone()), TEST_KEY)
+        .read_to_end(&mut decrypted)
+        .unwrap();
+    assert_eq!(decrypted, data);
+
+    let decrypted = read(Box::new(AsyncQuocoReader::new(
+        Cursor::new(encrypted),
+        TEST_KEY,
+    )))
+    .await;
+    assert_eq!(decrypted, data);
+}
+
+#[tokio::test]
+async fn async_reader_reports_bad_data() {
+    let mut writer = AsyncQuocoWriter::new(Vec::new(), TEST_KEY);
+    writer.write_all(&test_data()).await.unwrap();
+    let mut encrypted = writer.finish().await.unwrap();
+    let middle = encrypted.len() / 2;
+    encrypted[middle] ^= 1;
+
+    let mut reader = AsyncQuocoReader::new(Cursor::new(encrypted), TEST_KEY);
+    assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+}
+
+#[tokio::test]
+async fn tokio_source_round_trip() {
+    let vault = tempdir().unwrap();
+    let data = test_data();
+    let (id, copy) = {
+        let mut source = TokioObjectSource::new(Box::new(
+            FsObjectSource::open(vault.path(), TEST_KEY).unwrap(),
+        ));
+        let id = source.create_object(reader(&data)).await.unwrap();
+        source.set_object_name(&id, "data.bin").await.unwrap();
+        assert_eq!(read(source.object(&id).await.unwrap()).await, data);
+        assert_eq!(
+            read(source.object_range(&id, 10, 5).await.unwrap()).await,
+            &data[10..15]
+        );
+        assert_eq!(
+            source.object_id_with_name("data.bin").await.unwrap(),
+            Some(id)
+        );
+        assert_eq!(
+            source.stat(&id).await.unwrap().unwrap().name.as_deref(),
+            Some("data.bin")
+        );
+
+        let copy = source.copy_object(&id).await.unwrap();
+        source.modify_object(&copy, reader(b"copy")).await.unwrap();
+        source.rename_object(&copy, "copy.txt").await.unwrap();
+        assert_eq!(source.list().await.unwrap().len(), 2);
+        source.flush().await.unwrap();
+        (id, copy)
+    };
+
+    let source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let mut copied = Vec::new();
+    source
+        .object(&copy)
+        .unwrap()
+        .read_to_end(&mut copied)
+        .unwrap();
+    assert_eq!(copied, b"copy");
+    assert_eq!(
+        source.object_name(&id).unwrap().map(String::as_str),
+        Some("data.bin")
+    );
+}
+
+#[tokio::test]
+async fn tokio_source_reports_missing_objects() {
+    let vault = tempdir().unwrap();
+    let mut source = TokioObjectSource::new(Box::new(
+        FsObjectSource::open(vault.path(), TEST_KEY).unwrap(),
+    ));
+    assert!(source.object(&[8; 16]).await.is_err());
+    assert!(!source.object_exists(&[8; 16]).await.unwrap());
+    assert!(source.delete_object(&[8; 16]).await.is_err());
+}
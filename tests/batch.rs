@@ -0,0 +1,186 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{Batch, FsObjectSource, MemoryObjectSource, ObjectId, ObjectSource};
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tempfile::tempdir;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read(source: &mut dyn ObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn file_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn filesystem_batch_is_saved_once_applied() {
+    let vault = tempdir().unwrap();
+    let (kept, dropped) = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        let kept = source.create_object(&mut reader(b"kept")).unwrap();
+        let dropped = source.create_object(&mut reader(b"dropped")).unwrap();
+        source.set_object_name(&kept, "kept.txt").unwrap();
+        source.set_object_name(&dropped, "dropped.txt").unwrap();
+        source.flush().unwrap();
+
+        let mut batch = Batch::new();
+        batch.set_parallelism(3);
+        for i in 0..20 {
+            let name = format!("{}.txt", i);
+            batch.create(Cursor::new(name.clone().into_bytes()), Some(&name));
+        }
+        batch
+            .modify(&kept, Cursor::new(b"modified".to_vec()))
+            .delete(&dropped)
+            // The deleted object's name is free for later operations
+            .rename(&kept, "dropped.txt");
+        let created = source.apply_batch(batch).unwrap();
+        assert_eq!(created.len(), 20);
+        (kept, dropped)
+    };
+
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert_eq!(source.hashes().get_ids().count(), 21);
+    for i in 0..20 {
+        let name = format!("{}.txt", i);
+        let id = *source.object_id_with_name(&name).unwrap().unwrap();
+        assert_eq!(read(&mut source, &id), name.as_bytes());
+    }
+    assert_eq!(read(&mut source, &kept), b"modified");
+    assert_eq!(
+        source.object_id_with_name("dropped.txt").unwrap(),
+        Some(&kept)
+    );
+    assert!(!source.object_exists(&dropped).unwrap());
+    // Nothing is left behind from the old data
+    assert_eq!(source.list().unwrap().count(), 21);
+}
+
+#[test]
+fn filesystem_batch_renames_update_last_updated() {
+    let vault = tempdir().unwrap();
+    {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        let id = source.create_object(&mut reader(b"renamed")).unwrap();
+        let mut hashes = source.hashes().clone();
+        hashes.set_last_updated(UNIX_EPOCH);
+        source
+            .replace_reference_formats(source.names().clone(), hashes)
+            .unwrap();
+
+        let mut batch = Batch::new();
+        batch.rename(&id, "renamed.txt");
+        source.apply_batch(batch).unwrap();
+    }
+
+    let source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert!(*source.last_updated() > UNIX_EPOCH);
+}
+
+#[test]
+fn failed_filesystem_batch_changes_nothing() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let first = source.create_object(&mut reader(b"first")).unwrap();
+    let second = source.create_object(&mut reader(b"second")).unwrap();
+    source.set_object_name(&first, "first.txt").unwrap();
+    source.set_object_name(&second, "second.txt").unwrap();
+    source.flush().unwrap();
+    let files = file_names(vault.path());
+
+    let mut batch = Batch::new();
+    batch
+        .create(Cursor::new(b"new".to_vec()), Some("new.txt"))
+        .modify(&first, Cursor::new(b"modified".to_vec()))
+        .delete(&second)
+        .rename(&first, "new.txt");
+    assert!(matches!(
+        source.apply_batch(batch),
+        Err(QuocoError::NameTaken(_))
+    ));
+
+    assert_eq!(file_names(vault.path()), files);
+    assert_eq!(read(&mut source, &first), b"first");
+    assert_eq!(read(&mut source, &second), b"second");
+    assert_eq!(source.object_id_with_name("new.txt").unwrap(), None);
+    assert_eq!(source.hashes().get_ids().count(), 2);
+
+    let mut batch = Batch::new();
+    batch.delete(&[8; 16]);
+    assert!(source.apply_batch(batch).is_err());
+    assert_eq!(file_names(vault.path()), files);
+}
+
+#[test]
+fn filesystem_batch_dedupes_within_itself() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+    let existing = source.create_object(&mut reader(b"same")).unwrap();
+    source.flush().unwrap();
+    let files = file_names(vault.path()).len();
+
+    let mut batch = Batch::new();
+    batch
+        .create(Cursor::new(b"same".to_vec()), None)
+        .create(Cursor::new(b"other".to_vec()), None)
+        .create(Cursor::new(b"other".to_vec()), None);
+    let created = source.apply_batch(batch).unwrap();
+
+    // Only the first "other" needed a new blob
+    assert_eq!(file_names(vault.path()).len(), files + 1);
+    assert_eq!(read(&mut source, &created[0]), b"same");
+    assert_eq!(read(&mut source, &created[2]), b"other");
+    source.delete_object(&existing).unwrap();
+    assert_eq!(read(&mut source, &created[0]), b"same");
+}
+
+#[test]
+fn failed_default_batch_restores_metadata() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+    let id = source.create_object(&mut reader(b"in memory")).unwrap();
+    source.set_object_name(&id, "memory.txt").unwrap();
+
+    let mut batch = Batch::new();
+    batch
+        .create(Cursor::new(b"new".to_vec()), Some("new.txt"))
+        .delete(&id)
+        .rename(&[8; 16], "missing.txt");
+    assert!(source.apply_batch(batch).is_err());
+
+    assert_eq!(source.hashes().get_ids().count(), 1);
+    assert_eq!(source.object_id_with_name("memory.txt").unwrap(), Some(&id));
+    assert_eq!(source.object_id_with_name("new.txt").unwrap(), None);
+    assert_eq!(read(&mut source, &id), b"in memory");
+
+    let mut batch = Batch::new();
+    batch
+        .create(Cursor::new(b"new".to_vec()), Some("new.txt"))
+        .rename(&id, "renamed.txt");
+    let created = source.apply_batch(batch).unwrap();
+    assert_eq!(
+        source.object_id_with_name("new.txt").unwrap(),
+        Some(&created[0])
+    );
+    assert_eq!(
+        source.object_id_with_name("renamed.txt").unwrap(),
+        Some(&id)
+    );
+}
@@ -0,0 +1,332 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{
+    open_source_url, ChunkedObjectSource, Chunker, FsObjectSource, MemoryObjectSource,
+    ObjectSource, MAX_CONTENT_CHUNK_LENGTH,
+};
+use quocofs::session::{close_session, get_session, new_local_session_with_options, VaultOptions};
+use quocofs::ReadSeek;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tempfile::tempdir;
+use url::Url;
+
+/// Deterministic incompressible data (xorshift64)
+fn test_data(length: usize) -> Vec<u8> {
+    let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+    Chunker::new(data).map(|chunk| chunk.unwrap()).collect()
+}
+
+fn file_count(path: &Path) -> usize {
+    fs::read_dir(path).unwrap().count()
+}
+
+#[test]
+fn chunks_reassemble() {
+    let data = test_data(2 * 1024 * 1024);
+    let chunks = chunks(&data);
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|c| c.len() <= MAX_CONTENT_CHUNK_LENGTH));
+    assert_eq!(chunks.concat(), data);
+}
+
+#[test]
+fn insertion_only_changes_nearby_chunks() {
+    let data = test_data(2 * 1024 * 1024);
+    let mut edited = data.clone();
+    edited.splice(1024 * 1024..1024 * 1024, b"inserted".iter().copied());
+
+    let original: HashSet<Vec<u8>> = chunks(&data).into_iter().collect();
+    let edited = chunks(&edited);
+    let changed = edited.iter().filter(|c| !original.contains(*c)).count();
+
+    assert!(
+        changed <= 2,
+        "{} of {} chunks changed",
+        changed,
+        edited.len()
+    );
+}
+
+#[test]
+fn modify_only_writes_changed_chunks() {
+    let vault = tempdir().unwrap();
+    let mut source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+
+    let mut data = test_data(2 * 1024 * 1024);
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(data.clone())) as Box<dyn ReadSeek>))
+        .unwrap();
+    source.flush().unwrap();
+    let files_before = file_count(vault.path());
+
+    data[1024 * 1024] ^= 0xff;
+    source
+        .modify_object(
+            &id,
+            &mut (Box::new(Cursor::new(data.clone())) as Box<dyn ReadSeek>),
+        )
+        .unwrap();
+    // The changed chunk replaces the old one on flush, so the count only moves if a boundary moved
+    source.flush().unwrap();
+    assert!(file_count(vault.path()) <= files_before + 1);
+
+    let mut read_back = Vec::new();
+    source
+        .object(&id)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, data);
+    source.flush().unwrap();
+    drop(source);
+
+    let mut source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+    let mut read_back = Vec::new();
+    source
+        .object(&id)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, data);
+
+    source.delete_object(&id).unwrap();
+    source.flush().unwrap();
+    drop(source);
+    // Only the manifests object and reference formats are left
+    let blobs = fs::read_dir(vault.path())
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name().len() == 32)
+        .count();
+    assert_eq!(blobs, 1);
+}
+
+#[test]
+fn released_chunks_are_deleted_after_manifests_are_written() {
+    let vault = tempdir().unwrap();
+    let mut source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(test_data(1024 * 1024))) as Box<dyn ReadSeek>))
+        .unwrap();
+    source.flush().unwrap();
+    let files_before = file_count(vault.path());
+
+    // The written manifests still use the chunks, so they stay until the next flush
+    source.delete_object(&id).unwrap();
+    assert_eq!(file_count(vault.path()), files_before);
+    drop(source);
+    let source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+    let mut read_back = Vec::new();
+    source
+        .object(&id)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, test_data(1024 * 1024));
+
+    let mut source = source;
+    source.delete_object(&id).unwrap();
+    source.flush().unwrap();
+    assert!(file_count(vault.path()) < files_before);
+}
+
+#[test]
+fn unchanged_manifests_are_not_rewritten() {
+    let vault = tempdir().unwrap();
+    let blobs = || -> HashSet<_> {
+        fs::read_dir(vault.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name.len() == 32)
+            .collect()
+    };
+    let mut source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(test_data(1024))) as Box<dyn ReadSeek>))
+        .unwrap();
+    source.flush().unwrap();
+    let written = blobs();
+
+    // Manifests are written to a new blob every time, so an unchanged set means no rewrite
+    source.set_object_name(&id, "renamed").unwrap();
+    source.flush().unwrap();
+    assert_eq!(blobs(), written);
+
+    source.copy_object(&id).unwrap();
+    source.flush().unwrap();
+    assert_ne!(blobs(), written);
+}
+
+#[test]
+fn shared_chunks_outlive_deleted_objects() {
+    let mut source =
+        ChunkedObjectSource::open(Box::new(MemoryObjectSource::new(TEST_KEY)), TEST_KEY).unwrap();
+    let data = test_data(1024 * 1024);
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(data.clone())) as Box<dyn ReadSeek>))
+        .unwrap();
+    let copy = source.copy_object(&id).unwrap();
+
+    source.delete_object(&id).unwrap();
+    let mut read_back = Vec::new();
+    source
+        .object(&copy)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, data);
+}
+
+#[test]
+fn objects_are_read_a_chunk_at_a_time() {
+    let vault = tempdir().unwrap();
+    let mut source = ChunkedObjectSource::open(
+        Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+        TEST_KEY,
+    )
+    .unwrap();
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(test_data(1024 * 1024))) as Box<dyn ReadSeek>))
+        .unwrap();
+    source.flush().unwrap();
+
+    // Nothing is read until the reader is used, so losing the chunks only fails the read
+    let mut reader = source.object(&id).unwrap();
+    for entry in fs::read_dir(vault.path()).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_name().len() == 32 && entry.file_name() != *"ff".repeat(16) {
+            fs::remove_file(entry.path()).unwrap();
+        }
+    }
+    assert!(reader.read_to_end(&mut Vec::new()).is_err());
+}
+
+#[test]
+fn chunked_option_opens_chunked_sources() {
+    let vault = tempdir().unwrap();
+    let mut url = Url::from_directory_path(vault.path()).unwrap();
+    url.set_query(Some("chunked=1"));
+    let data = test_data(1024 * 1024);
+    {
+        let mut source = open_source_url(url.as_str(), TEST_KEY).unwrap();
+        let id = source
+            .create_object(&mut (Box::new(Cursor::new(data.clone())) as Box<dyn ReadSeek>))
+            .unwrap();
+        source.set_object_name(&id, "chunked.bin").unwrap();
+        source.flush().unwrap();
+    }
+    assert!(vault.path().join("ff".repeat(16)).exists());
+    assert!(file_count(vault.path()) > data.len() / MAX_CONTENT_CHUNK_LENGTH);
+
+    let id = new_local_session_with_options(
+        vault.path().to_str().unwrap(),
+        TEST_KEY,
+        &VaultOptions {
+            chunked: true,
+            ..VaultOptions::default()
+        },
+    )
+    .unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let source = session.source_mut();
+        let object_id = *source.object_id_with_name("chunked.bin").unwrap().unwrap();
+        let mut read_back = Vec::new();
+        source
+            .object(&object_id)
+            .unwrap()
+            .read_to_end(&mut read_back)
+            .unwrap();
+        assert_eq!(read_back, data);
+    }
+    assert!(close_session(&id));
+}
+
+#[test]
+fn replaced_formats_keep_manifests_in_step() {
+    let vault = tempdir().unwrap();
+    let open = || {
+        ChunkedObjectSource::open(
+            Box::new(FsObjectSource::open(vault.path(), TEST_KEY).unwrap()),
+            TEST_KEY,
+        )
+        .unwrap()
+    };
+    let (kept, dropped) = {
+        let mut source = open();
+        let kept = source
+            .create_object(
+                &mut (Box::new(Cursor::new(test_data(1024 * 1024))) as Box<dyn ReadSeek>),
+            )
+            .unwrap();
+        let (names, hashes) = (source.names().clone(), source.hashes().clone());
+        let dropped = source
+            .create_object(&mut (Box::new(Cursor::new(b"dropped".to_vec())) as Box<dyn ReadSeek>))
+            .unwrap();
+
+        // Hashes that don't match the chunks would be lost on reopen
+        let mut wrong = hashes.clone();
+        wrong.insert(&kept, &[0; 32]);
+        assert!(source
+            .replace_reference_formats(names.clone(), wrong)
+            .is_err());
+        let mut unknown = hashes.clone();
+        unknown.insert(&[1; 16], &[1; 32]);
+        assert!(source
+            .replace_reference_formats(names.clone(), unknown)
+            .is_err());
+
+        // Like importing metadata that only knows of the first object
+        source.replace_reference_formats(names, hashes).unwrap();
+        source.flush().unwrap();
+        (kept, dropped)
+    };
+
+    let source = open();
+    assert!(source.object_hash(&kept).unwrap().is_some());
+    assert!(source.object_hash(&dropped).unwrap().is_none());
+    assert!(!source.object_exists(&dropped).unwrap());
+    let mut read_back = Vec::new();
+    source
+        .object(&kept)
+        .unwrap()
+        .read_to_end(&mut read_back)
+        .unwrap();
+    assert_eq!(read_back, test_data(1024 * 1024));
+}
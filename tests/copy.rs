@@ -0,0 +1,131 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{
+    BoxedObjectSource, CachedObjectSource, ChunkedObjectSource, FsObjectSource, MemoryObjectSource,
+    ObjectId, ObjectSource,
+};
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read(source: &mut dyn ObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn blob_count(path: &Path) -> usize {
+    fs::read_dir(path)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            hex::decode(name.to_str().unwrap()).is_ok()
+        })
+        .count()
+}
+
+/// Copies are independent of their originals, whichever way a source stores them.
+fn check_copy(source: &mut dyn ObjectSource) {
+    let id = source.create_object(&mut reader(b"original")).unwrap();
+    source.set_object_name(&id, "original.txt").unwrap();
+
+    let copy = source.copy_object(&id).unwrap();
+    assert_ne!(copy, id);
+    assert_eq!(source.object_name(&copy).unwrap(), None);
+    assert_eq!(
+        source.object_hash(&copy).unwrap(),
+        source.object_hash(&id).unwrap()
+    );
+    assert_eq!(read(source, &copy), b"original");
+
+    source
+        .modify_object(&copy, &mut reader(b"changed"))
+        .unwrap();
+    assert_eq!(read(source, &id), b"original");
+    source.delete_object(&id).unwrap();
+    assert_eq!(read(source, &copy), b"changed");
+
+    assert!(source.copy_object(&id).is_err());
+}
+
+#[test]
+fn filesystem_copies() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    check_copy(&mut source);
+
+    let id = source.create_object(&mut reader(b"copied file")).unwrap();
+    let blobs = blob_count(vault.path());
+    source.copy_object(&id).unwrap();
+    assert_eq!(blob_count(vault.path()), blobs + 1);
+}
+
+#[test]
+fn deduped_filesystem_copies_share_blobs() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+    check_copy(&mut source);
+
+    let id = source.create_object(&mut reader(b"shared blob")).unwrap();
+    let blobs = blob_count(vault.path());
+    source.copy_object(&id).unwrap();
+    assert_eq!(blob_count(vault.path()), blobs);
+}
+
+#[test]
+fn other_sources_copy() {
+    check_copy(&mut MemoryObjectSource::new(TEST_KEY));
+    check_copy(&mut CachedObjectSource::new(Box::new(
+        MemoryObjectSource::new(TEST_KEY),
+    )));
+    check_copy(
+        &mut ChunkedObjectSource::open(
+            Box::new(MemoryObjectSource::new(TEST_KEY)) as BoxedObjectSource,
+            TEST_KEY,
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn renames_refuse_taken_names() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let first = source.create_object(&mut reader(b"first")).unwrap();
+    let second = source.create_object(&mut reader(b"second")).unwrap();
+    source.set_object_name(&first, "first.txt").unwrap();
+    source.set_object_name(&second, "second.txt").unwrap();
+
+    source.rename_object(&first, "renamed.txt").unwrap();
+    assert_eq!(
+        source.object_name(&first).unwrap().map(String::as_str),
+        Some("renamed.txt")
+    );
+    assert_eq!(source.object_id_with_name("first.txt").unwrap(), None);
+
+    assert!(matches!(
+        source.rename_object(&first, "second.txt"),
+        Err(QuocoError::NameTaken(_))
+    ));
+    assert_eq!(
+        source.object_id_with_name("second.txt").unwrap(),
+        Some(&second)
+    );
+    assert_eq!(
+        source.object_name(&first).unwrap().map(String::as_str),
+        Some("renamed.txt")
+    );
+
+    // Renaming an object to its own name is a no-op, not a conflict
+    source.rename_object(&second, "second.txt").unwrap();
+    assert!(source.rename_object(&[8; 16], "missing.txt").is_err());
+}
@@ -0,0 +1,150 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{FsObjectSource, ObjectId, ObjectSource};
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn blob_count(path: &Path) -> usize {
+    fs::read_dir(path)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            hex::decode(name.to_str().unwrap()).is_ok()
+        })
+        .count()
+}
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read(source: &mut FsObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn identical_objects_share_blob() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+
+    let first = source.create_object(&mut reader(b"same")).unwrap();
+    let second = source.create_object(&mut reader(b"same")).unwrap();
+    assert_ne!(first, second);
+    assert_eq!(blob_count(vault.path()), 1);
+
+    // Modifying the object that owns the shared blob must not change the other one
+    source
+        .modify_object(&first, &mut reader(b"different"))
+        .unwrap();
+    source.flush().unwrap();
+    assert_eq!(blob_count(vault.path()), 2);
+    assert_eq!(read(&mut source, &first), b"different");
+    assert_eq!(read(&mut source, &second), b"same");
+
+    source.modify_object(&first, &mut reader(b"same")).unwrap();
+    source.flush().unwrap();
+    assert_eq!(blob_count(vault.path()), 1);
+
+    source.delete_object(&second).unwrap();
+    source.flush().unwrap();
+    assert_eq!(blob_count(vault.path()), 1);
+    assert_eq!(read(&mut source, &first), b"same");
+
+    source.delete_object(&first).unwrap();
+    source.flush().unwrap();
+    assert_eq!(blob_count(vault.path()), 0);
+}
+
+#[test]
+fn links_survive_reopen() {
+    let vault = tempdir().unwrap();
+    let (first, second) = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        source.set_dedupe(true);
+        let first = source.create_object(&mut reader(b"same")).unwrap();
+        let second = source.create_object(&mut reader(b"same")).unwrap();
+        source.flush().unwrap();
+        (first, second)
+    };
+
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.delete_object(&first).unwrap();
+    source.flush().unwrap();
+    assert_eq!(read(&mut source, &second), b"same");
+    assert_eq!(blob_count(vault.path()), 1);
+}
+
+#[test]
+fn replaced_hashes_update_blob_references() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+
+    let first = source.create_object(&mut reader(b"same")).unwrap();
+    let (names, hashes) = (source.names().clone(), source.hashes().clone());
+    source.create_object(&mut reader(b"same")).unwrap();
+
+    // Puts things back as they were before the second object, which no longer uses the blob
+    source.replace_reference_formats(names, hashes).unwrap();
+    source.delete_object(&first).unwrap();
+    source.flush().unwrap();
+    assert_eq!(blob_count(vault.path()), 0);
+}
+
+#[test]
+fn replaced_formats_release_left_out_objects() {
+    let vault = tempdir().unwrap();
+    let (kept, shared, dropped) = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        source.set_dedupe(true);
+        let kept = source.create_object(&mut reader(b"same")).unwrap();
+        let (names, hashes) = (source.names().clone(), source.hashes().clone());
+        let shared = source.create_object(&mut reader(b"same")).unwrap();
+        let dropped = source.create_object(&mut reader(b"dropped")).unwrap();
+        source.flush().unwrap();
+        assert_eq!(blob_count(vault.path()), 2);
+
+        // Like importing metadata that only knows of the first object
+        source.replace_reference_formats(names, hashes).unwrap();
+        source.flush().unwrap();
+        (kept, shared, dropped)
+    };
+    assert_eq!(blob_count(vault.path()), 1);
+
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert_eq!(read(&mut source, &kept), b"same");
+    // Its link to the shared blob was dropped along with it
+    assert!(!source.object_exists(&shared).unwrap());
+    assert!(!source.object_exists(&dropped).unwrap());
+}
+
+#[test]
+fn saved_objects_keep_their_data_until_flushed() {
+    let vault = tempdir().unwrap();
+    let (modified, deleted) = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        let modified = source.create_object(&mut reader(b"saved")).unwrap();
+        let deleted = source.create_object(&mut reader(b"also saved")).unwrap();
+        source.flush().unwrap();
+
+        // Dropped without flushing, as if the process had died
+        source
+            .modify_object(&modified, &mut reader(b"unsaved"))
+            .unwrap();
+        source.delete_object(&deleted).unwrap();
+        assert!(!source.object_exists(&deleted).unwrap());
+        assert_eq!(source.list().unwrap().count(), 1);
+        (modified, deleted)
+    };
+
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert_eq!(read(&mut source, &modified), b"saved");
+    assert_eq!(read(&mut source, &deleted), b"also saved");
+}
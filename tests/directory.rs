@@ -0,0 +1,154 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::directory::{export_directory, import_directory};
+use quocofs::error::QuocoError;
+use quocofs::object::{FsObjectSource, ObjectSource};
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+#[test]
+fn import_export_round_trip() {
+    let input = tempdir().unwrap();
+    fs::create_dir_all(input.path().join("docs/2021")).unwrap();
+    fs::write(input.path().join("top.txt"), b"top").unwrap();
+    fs::write(input.path().join("docs/2021/nested.txt"), b"nested").unwrap();
+
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+
+    let import = import_directory(&mut source, input.path()).unwrap();
+    assert_eq!(import.created, 2);
+    assert!(source
+        .object_id_with_name("docs/2021/nested.txt")
+        .unwrap()
+        .is_some());
+
+    fs::write(input.path().join("top.txt"), b"changed").unwrap();
+    let import = import_directory(&mut source, input.path()).unwrap();
+    assert_eq!((import.modified, import.unchanged), (1, 1));
+
+    let output = tempdir().unwrap();
+    let export = export_directory(&mut source, output.path()).unwrap();
+    assert_eq!(export.written, 2);
+    assert_eq!(fs::read(output.path().join("top.txt")).unwrap(), b"changed");
+    assert_eq!(
+        fs::read(output.path().join("docs/2021/nested.txt")).unwrap(),
+        b"nested"
+    );
+
+    let export = export_directory(&mut source, output.path()).unwrap();
+    assert_eq!(export.unchanged, 2);
+}
+
+#[test]
+fn import_skips_unusable_paths() {
+    let input = tempdir().unwrap();
+    let long = "a".repeat(200);
+    let nested: PathBuf = [&long, &long, &long].iter().collect();
+    fs::create_dir_all(input.path().join(nested.parent().unwrap())).unwrap();
+    fs::write(input.path().join(&nested), b"too long").unwrap();
+    fs::write(input.path().join("kept.txt"), b"kept").unwrap();
+
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let import = import_directory(&mut source, input.path()).unwrap();
+    assert_eq!(import.created, 1);
+    assert_eq!(import.skipped.len(), 1);
+    assert_eq!(import.skipped[0].0, nested.to_string_lossy());
+    assert!(matches!(import.skipped[0].1, QuocoError::NameTooLong(_)));
+    assert!(source.object_id_with_name("kept.txt").unwrap().is_some());
+}
+
+#[cfg(unix)]
+#[test]
+fn import_skips_non_utf8_paths() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let input = tempdir().unwrap();
+    fs::write(input.path().join(OsStr::from_bytes(b"bad\xff.txt")), b"bad").unwrap();
+    fs::write(input.path().join("kept.txt"), b"kept").unwrap();
+
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let import = import_directory(&mut source, input.path()).unwrap();
+    assert_eq!(import.created, 1);
+    assert_eq!(import.skipped.len(), 1);
+    assert_eq!(import.skipped[0].0, "bad\u{fffd}.txt");
+    assert!(matches!(import.skipped[0].1, QuocoError::InvalidPath(_)));
+}
+
+#[test]
+fn export_refuses_escaping_names() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let id = source
+        .create_object(&mut (Box::new(Cursor::new(b"x".to_vec())) as Box<_>))
+        .unwrap();
+    source.set_object_name(&id, "../escaped.txt").unwrap();
+
+    let output = tempdir().unwrap();
+    let export = export_directory(&mut source, &output.path().join("root")).unwrap();
+    assert_eq!(export.written, 0);
+    assert_eq!(export.skipped.len(), 1);
+    assert_eq!(export.skipped[0].0, "../escaped.txt");
+    assert!(!output.path().join("escaped.txt").exists());
+}
+
+#[test]
+fn export_skips_colliding_names() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    for name in ["a", "a/b", "c"].iter() {
+        let id = source
+            .create_object(&mut (Box::new(Cursor::new(name.as_bytes().to_vec())) as Box<_>))
+            .unwrap();
+        source.set_object_name(&id, name).unwrap();
+    }
+
+    let output = tempdir().unwrap();
+    let export = export_directory(&mut source, output.path()).unwrap();
+    assert_eq!(export.written, 2);
+    let skipped: Vec<_> = export
+        .skipped
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    assert_eq!(skipped, ["a/b"]);
+    assert_eq!(fs::read(output.path().join("c")).unwrap(), b"c");
+}
+
+#[cfg(unix)]
+#[test]
+fn export_does_not_follow_symlinks() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    for name in ["linked.txt", "linked_dir/inner.txt"].iter() {
+        let id = source
+            .create_object(&mut (Box::new(Cursor::new(b"exported".to_vec())) as Box<_>))
+            .unwrap();
+        source.set_object_name(&id, name).unwrap();
+    }
+
+    let outside = tempdir().unwrap();
+    fs::write(outside.path().join("target.txt"), b"outside").unwrap();
+    let output = tempdir().unwrap();
+    std::os::unix::fs::symlink(
+        outside.path().join("target.txt"),
+        output.path().join("linked.txt"),
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(outside.path(), output.path().join("linked_dir")).unwrap();
+
+    let export = export_directory(&mut source, output.path()).unwrap();
+    assert_eq!(export.written, 0);
+    assert_eq!(export.skipped.len(), 2);
+    assert_eq!(
+        fs::read(outside.path().join("target.txt")).unwrap(),
+        b"outside"
+    );
+    assert!(!outside.path().join("inner.txt").exists());
+}
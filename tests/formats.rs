@@ -0,0 +1,173 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::formats::{
+    export_json, import_json, Hashes, Names, ReferenceFormat, ReferenceFormatSpecification, HASHES,
+    NAMES,
+};
+use quocofs::object::{Finish, FsObjectSource, ObjectSource, QuocoReader, QuocoWriter};
+use std::fs;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+use tempfile::tempdir;
+
+const TEST_ID: [u8; 16] = *b"\x8a\x1f\x0e\x91\x13\x4c\x4d\x0b\xa5\x3e\x6d\x3c\x0f\x2a\x11\x07";
+
+#[test]
+fn load_unversioned_names() {
+    let mut legacy = NAMES.unversioned_magic_bytes.unwrap().to_vec();
+    legacy.extend_from_slice(&TEST_ID);
+    legacy.extend_from_slice(b"notes.txt\0");
+
+    let mut names = Names::new();
+    names.load(&mut Cursor::new(legacy)).unwrap();
+    assert_eq!(names.get_name(&TEST_ID).unwrap(), "notes.txt");
+
+    let mut saved = Vec::new();
+    names.save(&mut saved).unwrap();
+    assert_eq!(&saved[..NAMES.magic_bytes.len()], NAMES.magic_bytes);
+
+    let mut reloaded = Names::new();
+    reloaded.load(&mut Cursor::new(saved)).unwrap();
+    assert_eq!(reloaded.get_name(&TEST_ID).unwrap(), "notes.txt");
+}
+
+#[test]
+fn load_unversioned_hashes() {
+    let mut legacy = HASHES.unversioned_magic_bytes.unwrap().to_vec();
+    legacy.extend_from_slice(&0u64.to_le_bytes());
+    legacy.extend_from_slice(&TEST_ID);
+    legacy.extend_from_slice(&[7u8; 32]);
+
+    let mut hashes = Hashes::new();
+    hashes.load(&mut Cursor::new(legacy)).unwrap();
+    assert_eq!(hashes.get_hash(&TEST_ID).unwrap(), &[7u8; 32]);
+}
+
+/// Decrypts a reference format file and returns its magic bytes and version, if it has one.
+fn read_header(path: &Path) -> Vec<u8> {
+    let mut data = Vec::new();
+    QuocoReader::new(File::open(path).unwrap(), TEST_KEY)
+        .read_to_end(&mut data)
+        .unwrap();
+    data.truncate(6);
+    data
+}
+
+fn current_header(specification: &ReferenceFormatSpecification) -> Vec<u8> {
+    let mut header = specification.magic_bytes.to_vec();
+    header.extend_from_slice(&specification.version.to_le_bytes());
+    header
+}
+
+#[test]
+fn migrate_backs_up_and_rewrites_unversioned_formats() {
+    let vault = tempdir().unwrap();
+    let mut legacy_names = NAMES.unversioned_magic_bytes.unwrap().to_vec();
+    legacy_names.extend_from_slice(&TEST_ID);
+    legacy_names.extend_from_slice(b"notes.txt\0");
+    let mut legacy_hashes = HASHES.unversioned_magic_bytes.unwrap().to_vec();
+    legacy_hashes.extend_from_slice(&0u64.to_le_bytes());
+    legacy_hashes.extend_from_slice(&TEST_ID);
+    legacy_hashes.extend_from_slice(&[7u8; 32]);
+    for (name, legacy) in [("names", legacy_names), ("hashes", legacy_hashes)].iter() {
+        let mut writer = QuocoWriter::new(File::create(vault.path().join(name)).unwrap(), TEST_KEY);
+        writer.write_all(legacy).unwrap();
+        writer.finish().unwrap();
+    }
+
+    let mut backups = FsObjectSource::migrate(vault.path(), TEST_KEY).unwrap();
+    backups.sort();
+    assert_eq!(
+        backups,
+        [
+            vault.path().join("hashes.v0.bak"),
+            vault.path().join("names.v0.bak")
+        ]
+    );
+    assert_eq!(
+        read_header(&backups[1])[..4],
+        *NAMES.unversioned_magic_bytes.unwrap()
+    );
+    assert_eq!(
+        read_header(&vault.path().join("names")),
+        current_header(&NAMES)
+    );
+    assert_eq!(
+        read_header(&vault.path().join("hashes")),
+        current_header(&HASHES)
+    );
+
+    let source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert_eq!(source.object_name(&TEST_ID).unwrap().unwrap(), "notes.txt");
+    assert_eq!(source.object_hash(&TEST_ID).unwrap(), Some(&[7u8; 32]));
+    drop(source);
+
+    // Already current, so there's nothing left to back up
+    assert!(FsObjectSource::migrate(vault.path(), TEST_KEY)
+        .unwrap()
+        .is_empty());
+    assert_eq!(fs::read_dir(vault.path()).unwrap().count(), 4);
+}
+
+#[test]
+fn reject_newer_version() {
+    let mut future = NAMES.magic_bytes.to_vec();
+    future.extend_from_slice(&(NAMES.version + 1).to_le_bytes());
+
+    assert!(Names::new().load(&mut Cursor::new(future)).is_err());
+}
+
+#[test]
+fn json_round_trip() {
+    let mut names = Names::new();
+    names.insert(&TEST_ID, "notes.txt");
+    let mut hashes = Hashes::new();
+    hashes.insert(&TEST_ID, &[7u8; 32]);
+
+    let mut json = Vec::new();
+    export_json(&names, &hashes, &mut json).unwrap();
+    let (imported_names, imported_hashes) = import_json(json.as_slice()).unwrap();
+
+    assert_eq!(imported_names.get_name(&TEST_ID).unwrap(), "notes.txt");
+    assert_eq!(imported_hashes.get_hash(&TEST_ID).unwrap(), &[7u8; 32]);
+}
+
+#[test]
+fn json_rejects_bad_id() {
+    let json = br#"{"version": 1, "last_updated": 0, "names": {"zz": "a"}, "hashes": {}}"#;
+
+    assert!(import_json(&json[..]).is_err());
+}
+
+#[test]
+fn imported_last_updated_is_saved() {
+    let json = br#"{"version": 1, "last_updated": 1617235200000, "names": {}, "hashes": {}}"#;
+    let (_, hashes) = import_json(&json[..]).unwrap();
+
+    let mut saved = Vec::new();
+    hashes.save(&mut saved).unwrap();
+    let mut reloaded = Hashes::new();
+    reloaded.load(&mut Cursor::new(saved)).unwrap();
+    assert_eq!(
+        *reloaded.get_last_updated(),
+        UNIX_EPOCH + Duration::from_millis(1617235200000)
+    );
+
+    reloaded.insert(&TEST_ID, &[7u8; 32]);
+    assert!(*reloaded.get_last_updated() > UNIX_EPOCH + Duration::from_millis(1617235200000));
+}
+
+#[test]
+fn reinserting_same_hash_keeps_last_updated() {
+    let mut hashes = Hashes::new();
+    hashes.insert(&TEST_ID, &[7u8; 32]);
+    hashes.set_last_updated(UNIX_EPOCH);
+
+    hashes.insert(&TEST_ID, &[7u8; 32]);
+    assert_eq!(*hashes.get_last_updated(), UNIX_EPOCH);
+    hashes.insert(&TEST_ID, &[8u8; 32]);
+    assert!(*hashes.get_last_updated() > UNIX_EPOCH);
+}
@@ -0,0 +1,534 @@
+mod util;
+
+use crate::util::{serve_http, tests_data_dir, HttpResponse, TEST_KEY};
+use quocofs::error::QuocoError;
+use quocofs::object::{
+    open_source_url, Batch, GoogleCredentials, GoogleStorageObjectSource, ObjectSource,
+};
+use quocofs::ReadSeek;
+use ring::signature::{RsaKeyPair, UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+const ACCESS_TOKEN: &str = "ya29.test-token";
+const CLIENT_EMAIL: &str = "quocofs@example.iam.gserviceaccount.com";
+/// Google's token endpoint's response to a valid JWT bearer grant, recorded with only the token
+/// replaced
+const RECORDED_TOKEN_RESPONSE: &str =
+    r#"{"access_token":"ya29.test-token","expires_in":3599,"token_type":"Bearer"}"#;
+/// Google's token endpoint's response to an assertion signed with the wrong key, as recorded
+const RECORDED_TOKEN_ERROR: &str =
+    r#"{"error":"invalid_grant","error_description":"Invalid JWT Signature."}"#;
+
+#[derive(Default)]
+struct Storage {
+    /// Objects by "bucket/name"
+    objects: HashMap<String, Vec<u8>>,
+    token_requests: usize,
+    /// Form fields of every token request, in order
+    token_forms: Vec<HashMap<String, String>>,
+    /// Replaces the recorded successful token response when set
+    token_response: Option<(&'static str, String)>,
+    /// Bytes of object data sent in responses to media downloads
+    bytes_served: usize,
+    /// Names of uploaded objects, in order
+    uploads: Vec<String>,
+}
+
+/// Stand-in for the Cloud Storage JSON API and Google's OAuth token endpoint, like a local
+/// emulator. Only requires an access token when `require_token` is set.
+fn start_server(require_token: bool) -> (String, Arc<Mutex<Storage>>) {
+    let storage = Arc::new(Mutex::new(Storage::default()));
+
+    let server_storage = storage.clone();
+    let endpoint = serve_http(move |request| {
+        let url = Url::parse(&format!("http://localhost{}", request.path)).unwrap();
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let mut storage = server_storage.lock().unwrap();
+
+        if url.path() == "/token" {
+            let form: HashMap<String, String> = url::form_urlencoded::parse(&request.body)
+                .into_owned()
+                .collect();
+            if form.get("assertion").map_or(0, |a| a.split('.').count()) != 3 {
+                return HttpResponse::new("400 Bad Request");
+            }
+            storage.token_requests += 1;
+            storage.token_forms.push(form);
+            let (status, body) = storage
+                .token_response
+                .clone()
+                .unwrap_or(("200 OK", RECORDED_TOKEN_RESPONSE.into()));
+            return HttpResponse::with_body(status, body.into_bytes());
+        }
+
+        if require_token
+            && request.headers.get("authorization") != Some(&format!("Bearer {}", ACCESS_TOKEN))
+        {
+            return HttpResponse::new("401 Unauthorized");
+        }
+
+        let segments: Vec<String> = url
+            .path_segments()
+            .unwrap()
+            .map(|s| {
+                percent_encoding::percent_decode_str(s)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            })
+            .collect();
+        let resource = |name: &str, data: &[u8]| {
+            serde_json::json!({
+                "name": name,
+                "size": data.len().to_string(),
+                "timeCreated": "2021-06-01T12:00:00.000Z",
+                "updated": "2021-06-02T12:00:00.000Z",
+            })
+        };
+        match (request.method.as_str(), segments.as_slice()) {
+            ("GET", [_, _, _, bucket, _]) => {
+                let bucket_prefix = format!("{}/", bucket);
+                let items: Vec<_> = storage
+                    .objects
+                    .iter()
+                    .filter_map(|(name, data)| {
+                        let name = name.strip_prefix(&bucket_prefix)?;
+                        if name.starts_with(query["prefix"].as_str()) {
+                            Some(resource(name, data))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect();
+                HttpResponse::with_body(
+                    "200 OK",
+                    serde_json::json!({ "items": items })
+                        .to_string()
+                        .into_bytes(),
+                )
+            }
+            ("POST", [_, _, _, bucket, _, from, _, _, to_bucket, _, to]) => {
+                match storage
+                    .objects
+                    .get(&format!("{}/{}", bucket, from))
+                    .cloned()
+                {
+                    Some(data) => {
+                        storage
+                            .objects
+                            .insert(format!("{}/{}", to_bucket, to), data);
+                        HttpResponse::with_body("200 OK", b"{}".to_vec())
+                    }
+                    None => HttpResponse::new("404 Not Found"),
+                }
+            }
+            ("POST", [upload, _, _, _, bucket, _]) if upload == "upload" => {
+                let name = format!("{}/{}", bucket, query["name"]);
+                storage.uploads.push(name.clone());
+                storage.objects.insert(name, request.body);
+                HttpResponse::with_body("200 OK", b"{}".to_vec())
+            }
+            (method, [_, _, _, bucket, _, name]) => {
+                let full_name = format!("{}/{}", bucket, name);
+                match method {
+                    "GET" => match storage.objects.get(&full_name) {
+                        Some(data) if query.get("alt").map(String::as_str) == Some("media") => {
+                            let data = data.clone();
+                            let range = request.headers.get("range").map(|range| {
+                                let (start, end) =
+                                    range.trim_start_matches("bytes=").split_once('-').unwrap();
+                                let start: usize = start.parse().unwrap();
+                                let end: usize = end.parse().unwrap();
+                                (start, std::cmp::min(end + 1, data.len()))
+                            });
+                            match range {
+                                Some((start, _)) if start >= data.len() => {
+                                    HttpResponse::new("416 Range Not Satisfiable")
+                                }
+                                Some((start, end)) => {
+                                    storage.bytes_served += end - start;
+                                    HttpResponse::with_body(
+                                        "206 Partial Content",
+                                        data[start..end].to_vec(),
+                                    )
+                                }
+                                None => {
+                                    storage.bytes_served += data.len();
+                                    HttpResponse::with_body("200 OK", data)
+                                }
+                            }
+                        }
+                        Some(data) => HttpResponse::with_body(
+                            "200 OK",
+                            resource(name, data).to_string().into_bytes(),
+                        ),
+                        None => HttpResponse::new("404 Not Found"),
+                    },
+                    "DELETE" => match storage.objects.remove(&full_name) {
+                        Some(_) => HttpResponse::new("204 No Content"),
+                        None => HttpResponse::new("404 Not Found"),
+                    },
+                    _ => HttpResponse::new("405 Method Not Allowed"),
+                }
+            }
+            _ => HttpResponse::new("404 Not Found"),
+        }
+    });
+
+    (endpoint, storage)
+}
+
+fn token_provider() -> GoogleCredentials {
+    GoogleCredentials::TokenProvider(Arc::new(|| Ok(ACCESS_TOKEN.to_string())))
+}
+
+fn service_account_pem() -> String {
+    fs::read_to_string(tests_data_dir().join("service_account_key.pem")).unwrap()
+}
+
+fn service_account_json(endpoint: &str) -> String {
+    serde_json::json!({
+        "type": "service_account",
+        "client_email": CLIENT_EMAIL,
+        "private_key": service_account_pem(),
+        "token_uri": format!("{}/token", endpoint),
+    })
+    .to_string()
+}
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+#[test]
+fn objects_round_trip_under_prefix() {
+    let (endpoint, storage) = start_server(true);
+    let open = || {
+        GoogleStorageObjectSource::open(
+            Some(&endpoint),
+            "vaults",
+            "team-a",
+            token_provider(),
+            TEST_KEY,
+        )
+    };
+
+    let id = {
+        let mut source = open().unwrap();
+        let id = source.create_object(&mut reader(b"hello gcs")).unwrap();
+        source.set_object_name(&id, "greeting.txt").unwrap();
+        source.flush().unwrap();
+        id
+    };
+
+    {
+        let storage = storage.lock().unwrap();
+        assert!(storage
+            .objects
+            .keys()
+            .all(|name| name.starts_with("vaults/team-a/")));
+        assert!(storage.objects.contains_key("vaults/team-a/names"));
+        assert!(!storage.objects.contains_key("vaults/team-a/quoco.lock"));
+    }
+
+    let source = open().unwrap();
+    assert!(matches!(open(), Err(QuocoError::SessionPathLocked(_))));
+    assert_eq!(
+        source.object_id_with_name("greeting.txt").unwrap(),
+        Some(&id)
+    );
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello gcs");
+}
+
+#[test]
+fn service_account_tokens_are_reused() {
+    let (endpoint, storage) = start_server(true);
+
+    let mut source = GoogleStorageObjectSource::open(
+        Some(&endpoint),
+        "vaults",
+        "",
+        GoogleCredentials::ServiceAccountJson(service_account_json(&endpoint)),
+        TEST_KEY,
+    )
+    .unwrap();
+    source.create_object(&mut reader(b"signed")).unwrap();
+    source.flush().unwrap();
+
+    assert_eq!(storage.lock().unwrap().token_requests, 1);
+}
+
+fn open_with_service_account(endpoint: &str) -> Result<GoogleStorageObjectSource, QuocoError> {
+    GoogleStorageObjectSource::open(
+        Some(endpoint),
+        "vaults",
+        "",
+        GoogleCredentials::ServiceAccountJson(service_account_json(endpoint)),
+        TEST_KEY,
+    )
+}
+
+#[test]
+fn token_requests_are_signed_jwt_grants() {
+    let (endpoint, storage) = start_server(true);
+    open_with_service_account(&endpoint).unwrap();
+
+    let form = storage.lock().unwrap().token_forms[0].clone();
+    assert_eq!(
+        form["grant_type"],
+        "urn:ietf:params:oauth:grant-type:jwt-bearer"
+    );
+    let parts: Vec<_> = form["assertion"].split('.').collect();
+    let decode = |part: &str| base64::decode_config(part, base64::URL_SAFE_NO_PAD).unwrap();
+    let json = |part: &str| serde_json::from_slice::<serde_json::Value>(&decode(part)).unwrap();
+
+    assert_eq!(
+        json(parts[0]),
+        serde_json::json!({ "alg": "RS256", "typ": "JWT" })
+    );
+    let claims = json(parts[1]);
+    assert_eq!(claims["iss"], CLIENT_EMAIL);
+    assert_eq!(
+        claims["scope"],
+        "https://www.googleapis.com/auth/devstorage.read_write"
+    );
+    assert_eq!(claims["aud"], format!("{}/token", endpoint));
+    assert_eq!(
+        claims["exp"].as_u64().unwrap() - claims["iat"].as_u64().unwrap(),
+        3600
+    );
+
+    let der: String = service_account_pem()
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let key_pair = RsaKeyPair::from_pkcs8(&base64::decode(der).unwrap()).unwrap();
+    UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, key_pair.public().as_ref())
+        .verify(
+            format!("{}.{}", parts[0], parts[1]).as_bytes(),
+            &decode(parts[2]),
+        )
+        .unwrap();
+}
+
+#[test]
+fn rejected_token_requests_are_errors() {
+    let (endpoint, storage) = start_server(true);
+    storage.lock().unwrap().token_response = Some(("400 Bad Request", RECORDED_TOKEN_ERROR.into()));
+
+    match open_with_service_account(&endpoint) {
+        Err(QuocoError::GoogleStorageError(message)) => assert!(message.contains("invalid_grant")),
+        other => panic!("expected a GoogleStorageError, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn expiring_tokens_are_replaced() {
+    let (endpoint, storage) = start_server(true);
+    // Expires within the refresh margin, so it's never reused
+    storage.lock().unwrap().token_response =
+        Some(("200 OK", RECORDED_TOKEN_RESPONSE.replace("3599", "30")));
+
+    let mut source = open_with_service_account(&endpoint).unwrap();
+    let requests = storage.lock().unwrap().token_requests;
+    source.create_object(&mut reader(b"refreshed")).unwrap();
+    assert!(storage.lock().unwrap().token_requests > requests);
+}
+
+#[test]
+fn emulator_url_needs_no_credentials() {
+    let (endpoint, storage) = start_server(false);
+
+    let mut source = open_source_url(
+        &format!("gs://vaults/from-url?endpoint={}", endpoint),
+        TEST_KEY,
+    )
+    .unwrap();
+    source.create_object(&mut reader(b"emulated")).unwrap();
+    source.flush().unwrap();
+
+    assert!(storage
+        .lock()
+        .unwrap()
+        .objects
+        .contains_key("vaults/from-url/names"));
+}
+
+#[test]
+fn rejected_requests_are_errors() {
+    let (endpoint, _storage) = start_server(true);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let provider_calls = calls.clone();
+
+    let result = GoogleStorageObjectSource::open(
+        Some(&endpoint),
+        "vaults",
+        "",
+        GoogleCredentials::TokenProvider(Arc::new(move || {
+            provider_calls.fetch_add(1, Ordering::SeqCst);
+            Ok("expired".to_string())
+        })),
+        TEST_KEY,
+    );
+    assert!(matches!(result, Err(QuocoError::GoogleStorageError(_))));
+    assert!(calls.load(Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn listing_includes_orphans() {
+    let (endpoint, storage) = start_server(true);
+
+    let mut source = GoogleStorageObjectSource::open(
+        Some(&endpoint),
+        "vaults",
+        "listed",
+        token_provider(),
+        TEST_KEY,
+    )
+    .unwrap();
+    let id = source.create_object(&mut reader(b"listed")).unwrap();
+    source.set_object_name(&id, "listed.txt").unwrap();
+    let orphan_id = [7; 16];
+    storage.lock().unwrap().objects.insert(
+        format!("vaults/listed/{}", "07".repeat(16)),
+        b"left behind".to_vec(),
+    );
+
+    let info = source.stat(&id).unwrap().unwrap();
+    assert_eq!(info.name.as_deref(), Some("listed.txt"));
+    assert!(info.hash.is_some());
+    assert!(info.size.unwrap() > 0);
+    assert!(info.created.unwrap() < info.modified.unwrap());
+
+    let mut infos: Vec<_> = source.list().unwrap().collect();
+    infos.sort_by_key(|info| info.name.is_none());
+    assert_eq!(infos.len(), 2);
+    assert_eq!(infos[0], info);
+    assert_eq!(infos[1].id, orphan_id);
+    assert_eq!(infos[1].hash, None);
+    assert_eq!(infos[1].size, Some(11));
+    assert_eq!(source.stat(&orphan_id).unwrap(), Some(infos[1].clone()));
+    assert_eq!(source.stat(&[8; 16]).unwrap(), None);
+}
+
+#[test]
+fn ranges_download_only_what_they_need() {
+    let (endpoint, storage) = start_server(true);
+    // Incompressible, so the stored object is about as large as the data
+    let data: Vec<u8> = (0..4 * 1024 * 1024u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+
+    let mut source =
+        GoogleStorageObjectSource::open(Some(&endpoint), "vaults", "", token_provider(), TEST_KEY)
+            .unwrap();
+    let id = source.create_object(&mut reader(&data)).unwrap();
+    storage.lock().unwrap().bytes_served = 0;
+
+    let mut range = Vec::new();
+    source
+        .object_range(&id, 1000, 500)
+        .unwrap()
+        .read_to_end(&mut range)
+        .unwrap();
+    assert_eq!(range, &data[1000..1500]);
+    assert!(storage.lock().unwrap().bytes_served < data.len() / 4);
+
+    let mut tail = Vec::new();
+    source
+        .object_range(&id, data.len() as u64 - 100, 1000)
+        .unwrap()
+        .read_to_end(&mut tail)
+        .unwrap();
+    assert_eq!(tail, &data[data.len() - 100..]);
+
+    assert!(source.object_range(&[8; 16], 0, 10).is_err());
+}
+
+#[test]
+fn writers_upload_on_commit() {
+    let (endpoint, storage) = start_server(true);
+
+    let mut source =
+        GoogleStorageObjectSource::open(Some(&endpoint), "vaults", "", token_provider(), TEST_KEY)
+            .unwrap();
+    let objects = storage.lock().unwrap().objects.len();
+    let mut writer = source.begin_object().unwrap();
+    writer.write_all(b"streamed ").unwrap();
+    writer.write_all(b"upload").unwrap();
+    assert_eq!(storage.lock().unwrap().objects.len(), objects);
+    let id = writer.commit().unwrap();
+
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"streamed upload");
+}
+
+#[test]
+fn copies_stay_in_the_bucket() {
+    let (endpoint, storage) = start_server(true);
+
+    let mut source = GoogleStorageObjectSource::open(
+        Some(&endpoint),
+        "vaults",
+        "copies",
+        token_provider(),
+        TEST_KEY,
+    )
+    .unwrap();
+    let id = source.create_object(&mut reader(b"original")).unwrap();
+    storage.lock().unwrap().bytes_served = 0;
+
+    let copy = source.copy_object(&id).unwrap();
+    assert_ne!(copy, id);
+    assert_eq!(storage.lock().unwrap().bytes_served, 0);
+    assert_eq!(
+        source.object_hash(&copy).unwrap(),
+        source.object_hash(&id).unwrap()
+    );
+
+    source.delete_object(&id).unwrap();
+    let mut data = Vec::new();
+    source
+        .object(&copy)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, b"original");
+}
+
+#[test]
+fn batches_upload_metadata_once() {
+    let (endpoint, storage) = start_server(true);
+
+    let mut source = GoogleStorageObjectSource::open(
+        Some(&endpoint),
+        "vaults",
+        "batched",
+        token_provider(),
+        TEST_KEY,
+    )
+    .unwrap();
+    let mut batch = Batch::new();
+    for i in 0..10 {
+        batch.create(Cursor::new(vec![i; 100]), Some(&format!("{}.bin", i)));
+    }
+    let created = source.apply_batch(batch).unwrap();
+
+    let storage = storage.lock().unwrap();
+    let uploads = |name: &str| storage.uploads.iter().filter(|n| *n == name).count();
+    assert_eq!(uploads("vaults/batched/names"), 1);
+    assert_eq!(uploads("vaults/batched/hashes"), 1);
+    // The lock, the objects, then names, hashes and links once each
+    assert_eq!(storage.uploads.len(), 10 + 4);
+    assert_eq!(
+        source.object_id_with_name("3.bin").unwrap(),
+        Some(&created[3])
+    );
+}
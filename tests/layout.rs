@@ -0,0 +1,135 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::formats::ObjectLayout;
+use quocofs::object::{FsObjectSource, ObjectId, ObjectSource};
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read(source: &mut dyn ObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn flat_path(vault: &Path, id: &ObjectId) -> PathBuf {
+    vault.join(hex::encode(id))
+}
+
+fn sharded_path(vault: &Path, id: &ObjectId) -> PathBuf {
+    let hex = hex::encode(id);
+    vault.join(&hex[0..2]).join(&hex[2..4]).join(&hex)
+}
+
+fn root_blob_count(vault: &Path) -> usize {
+    fs::read_dir(vault)
+        .unwrap()
+        .filter(|entry| {
+            let name = entry.as_ref().unwrap().file_name();
+            name.to_str().unwrap().len() == 32
+        })
+        .count()
+}
+
+#[test]
+fn sharded_layout_is_kept_in_vault_config() {
+    let vault = tempdir().unwrap();
+    let id = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        assert_eq!(source.layout(), ObjectLayout::Flat);
+        assert_eq!(source.set_layout(ObjectLayout::Sharded).unwrap(), 0);
+
+        let id = source.create_object(&mut reader(b"sharded")).unwrap();
+        source.set_object_name(&id, "sharded.txt").unwrap();
+        source.flush().unwrap();
+        id
+    };
+    assert!(sharded_path(vault.path(), &id).is_file());
+    assert!(!flat_path(vault.path(), &id).exists());
+
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert_eq!(source.layout(), ObjectLayout::Sharded);
+    assert_eq!(read(&mut source, &id), b"sharded");
+    assert_eq!(source.list().unwrap().count(), 1);
+    assert!(source.stat(&id).unwrap().unwrap().size.is_some());
+
+    source.delete_object(&id).unwrap();
+    source.flush().unwrap();
+    assert!(!sharded_path(vault.path(), &id).exists());
+}
+
+#[test]
+fn switching_layouts_moves_existing_objects() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let ids: Vec<ObjectId> = (0..10)
+        .map(|i| {
+            source
+                .create_object(&mut reader(format!("object {}", i).as_bytes()))
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(root_blob_count(vault.path()), 10);
+
+    assert_eq!(source.set_layout(ObjectLayout::Sharded).unwrap(), 10);
+    assert_eq!(root_blob_count(vault.path()), 0);
+    for (i, id) in ids.iter().enumerate() {
+        assert!(sharded_path(vault.path(), id).is_file());
+        assert_eq!(read(&mut source, id), format!("object {}", i).as_bytes());
+    }
+    // Moving again has nothing left to do
+    assert_eq!(source.set_layout(ObjectLayout::Sharded).unwrap(), 0);
+
+    assert_eq!(source.set_layout(ObjectLayout::Flat).unwrap(), 10);
+    assert_eq!(root_blob_count(vault.path()), 10);
+    // Only the vault's own files are left once shard directories are cleaned up
+    for entry in fs::read_dir(vault.path()).unwrap() {
+        assert!(entry.unwrap().file_type().unwrap().is_file());
+    }
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(read(&mut source, id), format!("object {}", i).as_bytes());
+    }
+}
+
+#[test]
+fn objects_left_in_the_old_layout_are_still_read() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let moved = source.create_object(&mut reader(b"moved")).unwrap();
+    let left = source.create_object(&mut reader(b"left behind")).unwrap();
+    source.set_layout(ObjectLayout::Sharded).unwrap();
+
+    // As if moving had been interrupted
+    fs::rename(
+        sharded_path(vault.path(), &left),
+        flat_path(vault.path(), &left),
+    )
+    .unwrap();
+    assert_eq!(read(&mut source, &left), b"left behind");
+    assert_eq!(read(&mut source, &moved), b"moved");
+    assert!(source.object_exists(&left).unwrap());
+    assert_eq!(source.list().unwrap().count(), 2);
+
+    // Rewritten objects are written in the current layout, and the old file goes on flush
+    source
+        .modify_object(&left, &mut reader(b"rewritten"))
+        .unwrap();
+    source.flush().unwrap();
+    assert!(!flat_path(vault.path(), &left).exists());
+    assert!(fs::read_dir(vault.path()).unwrap().all(|entry| {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        hex::decode(&name).map_or(true, |bytes| bytes.len() == 1)
+    }));
+    assert_eq!(read(&mut source, &left), b"rewritten");
+
+    let copy = source.copy_object(&moved).unwrap();
+    assert!(sharded_path(vault.path(), &copy).is_file());
+    assert_eq!(read(&mut source, &copy), b"moved");
+}
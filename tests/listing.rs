@@ -0,0 +1,65 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{FsObjectSource, MemoryObjectSource, ObjectSource};
+use quocofs::util::sha256;
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::Cursor;
+use tempfile::tempdir;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+#[test]
+fn filesystem_stat_and_list() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+
+    let id = source.create_object(&mut reader(b"listed")).unwrap();
+    source.set_object_name(&id, "listed.txt").unwrap();
+    // Shares a blob with the first, which mustn't make that blob look like an orphan
+    let copy = source.create_object(&mut reader(b"listed")).unwrap();
+    let orphan_id = [7; 16];
+    fs::write(vault.path().join("07".repeat(16)), b"left behind").unwrap();
+
+    let info = source.stat(&id).unwrap().unwrap();
+    assert_eq!(info.id, id);
+    assert_eq!(info.name.as_deref(), Some("listed.txt"));
+    assert_eq!(
+        info.hash,
+        Some(sha256(&mut Cursor::new(b"listed")).unwrap())
+    );
+    assert!(info.size.unwrap() > b"listed".len() as u64);
+    assert!(info.modified.is_some());
+
+    let orphan = source.stat(&orphan_id).unwrap().unwrap();
+    assert_eq!(orphan.name, None);
+    assert_eq!(orphan.hash, None);
+    assert_eq!(orphan.size, Some(11));
+    assert_eq!(source.stat(&[8; 16]).unwrap(), None);
+
+    let mut ids: Vec<_> = source.list().unwrap().map(|info| info.id).collect();
+    ids.sort_unstable();
+    let mut expected = vec![id, copy, orphan_id];
+    expected.sort_unstable();
+    assert_eq!(ids, expected);
+
+    source.delete_object(&orphan_id).unwrap();
+    assert_eq!(source.stat(&orphan_id).unwrap(), None);
+}
+
+#[test]
+fn memory_listing_comes_from_metadata() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+    let id = source.create_object(&mut reader(b"in memory")).unwrap();
+    source.set_object_name(&id, "memory.txt").unwrap();
+
+    let infos: Vec<_> = source.list().unwrap().collect();
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].name.as_deref(), Some("memory.txt"));
+    assert!(infos[0].hash.is_some());
+    assert_eq!(infos[0].size, None);
+}
@@ -0,0 +1,121 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{
+    BoxedObjectSource, MemoryObjectSource, ObjectId, ObjectSource, RemoteSourceConfig,
+};
+use quocofs::session::{close_session, get_session, new_memory_session, Session, VaultSession};
+use quocofs::util::sha256;
+use quocofs::ReadSeek;
+use std::io;
+use std::io::{Cursor, Read};
+use std::time::UNIX_EPOCH;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read(source: &mut dyn ObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn objects_round_trip() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+
+    let id = source.create_object(&mut reader(b"in memory")).unwrap();
+    source.set_object_name(&id, "memory.txt").unwrap();
+    source.flush().unwrap();
+
+    assert!(source.object_exists(&id).unwrap());
+    assert!(source.size() > 0);
+    assert_eq!(read(&mut source, &id), b"in memory");
+    assert_eq!(source.object_id_with_name("memory.txt").unwrap(), Some(&id));
+    assert_eq!(
+        source.object_hash(&id).unwrap(),
+        Some(&sha256(&mut Cursor::new(b"in memory")).unwrap())
+    );
+
+    source.modify_object(&id, &mut reader(b"changed")).unwrap();
+    assert_eq!(read(&mut source, &id), b"changed");
+
+    source.delete_object(&id).unwrap();
+    assert!(!source.object_exists(&id).unwrap());
+    assert_eq!(source.object_name(&id).unwrap(), None);
+    assert_eq!(source.size(), 0);
+}
+
+#[test]
+fn deleting_unknown_objects_is_not_found() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+    let id = source.create_object(&mut reader(b"deleted")).unwrap();
+    source.delete_object(&id).unwrap();
+
+    assert!(matches!(
+        source.delete_object(&id),
+        Err(QuocoError::IoError(err)) if err.kind() == io::ErrorKind::NotFound
+    ));
+}
+
+#[test]
+fn renames_update_last_updated() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+    let id = source.create_object(&mut reader(b"renamed")).unwrap();
+    source.set_object_name(&id, "before.txt").unwrap();
+    let mut hashes = source.hashes().clone();
+    hashes.set_last_updated(UNIX_EPOCH);
+    source
+        .replace_reference_formats(source.names().clone(), hashes)
+        .unwrap();
+
+    source.set_object_name(&id, "before.txt").unwrap();
+    assert_eq!(*source.last_updated(), UNIX_EPOCH);
+    source.set_object_name(&id, "after.txt").unwrap();
+    assert!(*source.last_updated() > UNIX_EPOCH);
+}
+
+#[test]
+fn sessions_sync_between_memory_sources() {
+    let mut session = Session::open(
+        Box::new(MemoryObjectSource::new(TEST_KEY)),
+        vec![(
+            "backup".into(),
+            Box::new(MemoryObjectSource::new(TEST_KEY)) as BoxedObjectSource,
+        )],
+    )
+    .unwrap();
+
+    let id = session.local.create_object(&mut reader(b"synced")).unwrap();
+    session.local.set_object_name(&id, "synced.txt").unwrap();
+    session.push_remote("backup").unwrap();
+
+    let remote = session.remote("backup").unwrap();
+    assert_eq!(remote.object_id_with_name("synced.txt").unwrap(), Some(&id));
+    assert_eq!(read(remote, &id), b"synced");
+}
+
+#[test]
+fn memory_session_with_memory_remote() {
+    let id = new_memory_session(
+        TEST_KEY,
+        vec![("memory".into(), RemoteSourceConfig::Memory)],
+    )
+    .unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let object_id = session
+            .source_mut()
+            .create_object(&mut reader(b"ephemeral"))
+            .unwrap();
+        session.push_remote("memory").unwrap();
+        assert_eq!(
+            read(session.remote("memory").unwrap(), &object_id),
+            b"ephemeral"
+        );
+    }
+    assert!(close_session(&id));
+}
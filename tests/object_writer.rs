@@ -0,0 +1,98 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{
+    CachedObjectSource, FsObjectSource, MemoryObjectSource, ObjectId, ObjectSource,
+};
+use quocofs::util::sha256;
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+use tempfile::tempdir;
+
+fn read(source: &mut dyn ObjectSource, id: &ObjectId) -> Vec<u8> {
+    let mut data = Vec::new();
+    source.object(id).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn write_in_pieces(source: &mut dyn ObjectSource, pieces: &[&[u8]]) -> ObjectId {
+    let mut writer = source.begin_object().unwrap();
+    for piece in pieces {
+        writer.write_all(piece).unwrap();
+    }
+    writer.commit().unwrap()
+}
+
+fn file_count(path: &Path) -> usize {
+    fs::read_dir(path).unwrap().count()
+}
+
+#[test]
+fn filesystem_writer_round_trip() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+
+    let id = write_in_pieces(&mut source, &[b"written ", b"in ", b"pieces"]);
+    assert_eq!(read(&mut source, &id), b"written in pieces");
+    assert_eq!(
+        source.object_hash(&id).unwrap(),
+        Some(&sha256(&mut Cursor::new(b"written in pieces")).unwrap())
+    );
+
+    let mut writer = source.begin_modify_object(&id).unwrap();
+    writer.write_all(b"rewritten").unwrap();
+    assert_eq!(writer.commit().unwrap(), id);
+    assert_eq!(read(&mut source, &id), b"rewritten");
+}
+
+#[test]
+fn filesystem_writer_discards_uncommitted_data() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let files = file_count(vault.path());
+
+    {
+        let mut writer = source.begin_object().unwrap();
+        writer.write_all(b"abandoned").unwrap();
+        assert_eq!(file_count(vault.path()), files + 1);
+    }
+    assert_eq!(file_count(vault.path()), files);
+    assert_eq!(source.list().unwrap().count(), 0);
+}
+
+#[test]
+fn filesystem_writer_dedupes() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    source.set_dedupe(true);
+
+    let first = write_in_pieces(&mut source, &[b"same"]);
+    let files = file_count(vault.path());
+    let second = write_in_pieces(&mut source, &[b"sa", b"me"]);
+
+    assert_ne!(first, second);
+    assert_eq!(file_count(vault.path()), files);
+    assert_eq!(read(&mut source, &second), b"same");
+}
+
+#[test]
+fn buffered_writer_round_trip() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+
+    let id = write_in_pieces(&mut source, &[b"in ", b"memory"]);
+    assert_eq!(read(&mut source, &id), b"in memory");
+}
+
+#[test]
+fn cached_writer_replaces_cached_data() {
+    let mut source = CachedObjectSource::new(Box::new(MemoryObjectSource::new(TEST_KEY)));
+
+    let id = write_in_pieces(&mut source, &[b"cached"]);
+    assert_eq!(source.object_cached(&id).unwrap().into_inner(), b"cached");
+
+    let mut writer = source.begin_modify_object(&id).unwrap();
+    writer.write_all(b"replaced").unwrap();
+    writer.commit().unwrap();
+    assert_eq!(read(&mut source, &id), b"replaced");
+}
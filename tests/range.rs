@@ -0,0 +1,62 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::object::{
+    CachedObjectSource, FsObjectSource, MemoryObjectSource, ObjectId, ObjectSource,
+};
+use quocofs::ReadSeek;
+use std::io::{Cursor, Read};
+use tempfile::tempdir;
+
+const DATA: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn read_range(source: &mut dyn ObjectSource, id: &ObjectId, offset: u64, len: u64) -> Vec<u8> {
+    let mut data = Vec::new();
+    source
+        .object_range(id, offset, len)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    data
+}
+
+fn check_ranges(source: &mut dyn ObjectSource, id: &ObjectId) {
+    assert_eq!(read_range(source, id, 0, 3), b"The");
+    assert_eq!(read_range(source, id, 16, 3), b"fox");
+    // Ranges past the end are cut short rather than failing
+    assert_eq!(read_range(source, id, 40, 100), b"dog");
+    assert_eq!(read_range(source, id, 100, 10), b"");
+}
+
+#[test]
+fn filesystem_ranges() {
+    let vault = tempdir().unwrap();
+    let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    let id = source.create_object(&mut reader(DATA)).unwrap();
+
+    check_ranges(&mut source, &id);
+    assert!(source.object_range(&[8; 16], 0, 10).is_err());
+}
+
+#[test]
+fn default_ranges() {
+    let mut source = MemoryObjectSource::new(TEST_KEY);
+    let id = source.create_object(&mut reader(DATA)).unwrap();
+
+    check_ranges(&mut source, &id);
+}
+
+#[test]
+fn cached_ranges() {
+    let mut source = CachedObjectSource::new(Box::new(MemoryObjectSource::new(TEST_KEY)));
+    let id = source.create_object(&mut reader(DATA)).unwrap();
+
+    // Once from the inner source, then from the cache
+    check_ranges(&mut source, &id);
+    source.object_cached(&id).unwrap();
+    check_ranges(&mut source, &id);
+}
@@ -0,0 +1,196 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{
+    open_source_url, register_source_factory, registered_schemes, unregister_source_factory,
+    BoxedObjectSource, Key, MemoryObjectSource, ObjectSource, RemoteSourceConfig,
+};
+use quocofs::session::{
+    close_session, get_session, new_local_session_with_options, new_memory_session, new_session,
+    VaultOptions,
+};
+use quocofs::ReadSeek;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tempfile::tempdir;
+use url::Url;
+
+#[test]
+fn builtin_schemes_are_registered() {
+    let schemes = registered_schemes();
+    assert!(schemes.contains(&"file".to_string()));
+    assert!(schemes.contains(&"mem".to_string()));
+}
+
+#[test]
+fn file_url_opens_directory() {
+    let dir = tempdir().unwrap();
+    let url = Url::from_directory_path(dir.path()).unwrap();
+
+    let id = {
+        let mut source = open_source_url(url.as_str(), TEST_KEY).unwrap();
+        let id = source
+            .create_object(
+                &mut (Box::new(Cursor::new(b"from a url".to_vec())) as Box<dyn ReadSeek>),
+            )
+            .unwrap();
+        source.flush().unwrap();
+        id
+    };
+
+    let source = open_source_url(url.as_str(), TEST_KEY).unwrap();
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"from a url");
+}
+
+#[test]
+fn custom_factory_opens_remote() {
+    let opened = Arc::new(AtomicUsize::new(0));
+    let factory_opened = opened.clone();
+    register_source_factory("counted", move |url: &Url, key: &Key| {
+        assert_eq!(url.host_str(), Some("example"));
+        factory_opened.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(MemoryObjectSource::new(key)) as BoxedObjectSource)
+    });
+
+    let id = new_memory_session(
+        TEST_KEY,
+        vec![(
+            "counted".into(),
+            RemoteSourceConfig::Url("counted://example/vault".into()),
+        )],
+    )
+    .unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        session.push_remote("counted").unwrap();
+    }
+    assert!(close_session(&id));
+    assert_eq!(opened.load(Ordering::SeqCst), 1);
+
+    assert!(unregister_source_factory("counted"));
+    assert!(matches!(
+        open_source_url("counted://example/vault", TEST_KEY),
+        Err(QuocoError::UnsupportedScheme(_))
+    ));
+}
+
+#[test]
+fn invalid_urls_are_rejected() {
+    assert!(matches!(
+        open_source_url("not a url", TEST_KEY),
+        Err(QuocoError::InvalidUrl(_))
+    ));
+    assert!(matches!(
+        open_source_url("file://remote-host/vault", TEST_KEY),
+        Err(QuocoError::InvalidUrl(_))
+    ));
+}
+
+#[test]
+fn sessions_open_from_urls() {
+    let mirror = tempdir().unwrap();
+    let mirror_url = Url::from_directory_path(mirror.path()).unwrap();
+
+    let id = new_session(
+        "mem://",
+        TEST_KEY,
+        vec![(
+            "mirror".into(),
+            RemoteSourceConfig::Url(mirror_url.to_string()),
+        )],
+    )
+    .unwrap();
+    let object_id = {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let object_id = session
+            .source_mut()
+            .create_object(
+                &mut (Box::new(Cursor::new(b"url session".to_vec())) as Box<dyn ReadSeek>),
+            )
+            .unwrap();
+        session.push_remote("mirror").unwrap();
+        object_id
+    };
+    assert!(close_session(&id));
+
+    let id = new_session(mirror_url.as_str(), TEST_KEY, Vec::new()).unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let mut data = Vec::new();
+        session
+            .source_mut()
+            .object(&object_id)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"url session");
+    }
+    assert!(close_session(&id));
+}
+
+#[test]
+fn unknown_options_are_rejected() {
+    assert!(matches!(
+        open_source_url("mem://?persist=true", TEST_KEY),
+        Err(QuocoError::InvalidUrl(_))
+    ));
+}
+
+fn store_twice(source: &mut dyn ObjectSource) {
+    for _ in 0..2 {
+        source
+            .create_object(&mut (Box::new(Cursor::new(b"same".to_vec())) as Box<dyn ReadSeek>))
+            .unwrap();
+    }
+    source.flush().unwrap();
+}
+
+fn file_count(dir: &Path) -> usize {
+    fs::read_dir(dir).unwrap().count()
+}
+
+#[test]
+fn dedupe_option_shares_blobs() {
+    let plain = tempdir().unwrap();
+    let plain_url = Url::from_directory_path(plain.path()).unwrap();
+    store_twice(&mut *open_source_url(plain_url.as_str(), TEST_KEY).unwrap());
+
+    let deduped = tempdir().unwrap();
+    let mut deduped_url = Url::from_directory_path(deduped.path()).unwrap();
+    deduped_url.set_query(Some("dedupe=1"));
+    store_twice(&mut *open_source_url(deduped_url.as_str(), TEST_KEY).unwrap());
+    assert_eq!(file_count(deduped.path()), file_count(plain.path()) - 1);
+
+    let from_session = tempdir().unwrap();
+    let id = new_local_session_with_options(
+        from_session.path().to_str().unwrap(),
+        TEST_KEY,
+        &VaultOptions {
+            dedupe: true,
+            ..VaultOptions::default()
+        },
+    )
+    .unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        store_twice(session.source_mut());
+    }
+    assert!(close_session(&id));
+    assert_eq!(file_count(from_session.path()), file_count(deduped.path()));
+
+    deduped_url.set_query(Some("dedupe=yes"));
+    assert!(matches!(
+        open_source_url(deduped_url.as_str(), TEST_KEY),
+        Err(QuocoError::InvalidUrl(_))
+    ));
+}
@@ -0,0 +1,123 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{ObjectSource, RemoteSourceConfig};
+use quocofs::session::{close_session, get_session, new_session};
+use quocofs::ReadSeek;
+use std::io::{Cursor, Read};
+use tempfile::tempdir;
+
+#[test]
+fn filesystem_remote_push_and_pull() {
+    let first_local = tempdir().unwrap();
+    let second_local = tempdir().unwrap();
+    let mirror = tempdir().unwrap();
+    let remote = || {
+        vec![(
+            "mirror".to_string(),
+            RemoteSourceConfig::Filesystem {
+                path: mirror.path().into(),
+            },
+        )]
+    };
+
+    let first = new_session(first_local.path().to_str().unwrap(), TEST_KEY, remote()).unwrap();
+    let id = {
+        let session = get_session(&first).unwrap();
+        let mut session = session.write().unwrap();
+        let id = session
+            .source_mut()
+            .create_object(&mut (Box::new(Cursor::new(b"mirrored".to_vec())) as Box<dyn ReadSeek>))
+            .unwrap();
+        session
+            .source_mut()
+            .set_object_name(&id, "mirrored.txt")
+            .unwrap();
+        session.push_remote("mirror").unwrap();
+        id
+    };
+    // The mirror is locked for as long as a session uses it
+    assert!(new_session(second_local.path().to_str().unwrap(), TEST_KEY, remote()).is_err());
+    assert!(close_session(&first));
+
+    let second = new_session(second_local.path().to_str().unwrap(), TEST_KEY, remote()).unwrap();
+    {
+        let session = get_session(&second).unwrap();
+        let mut session = session.write().unwrap();
+        session.pull_remote("mirror").unwrap();
+        assert_eq!(
+            session
+                .source_mut()
+                .object_id_with_name("mirrored.txt")
+                .unwrap(),
+            Some(&id)
+        );
+        let mut data = Vec::new();
+        session
+            .source_mut()
+            .object(&id)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, b"mirrored");
+    }
+    assert!(close_session(&second));
+}
+
+#[test]
+fn push_all_updates_every_remote() {
+    let local = tempdir().unwrap();
+    let nas = tempdir().unwrap();
+    let drive = tempdir().unwrap();
+
+    let id = new_session(
+        local.path().to_str().unwrap(),
+        TEST_KEY,
+        vec![
+            (
+                "nas".into(),
+                RemoteSourceConfig::Filesystem {
+                    path: nas.path().into(),
+                },
+            ),
+            (
+                "drive".into(),
+                RemoteSourceConfig::Filesystem {
+                    path: drive.path().into(),
+                },
+            ),
+        ],
+    )
+    .unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        session
+            .source_mut()
+            .create_object(&mut (Box::new(Cursor::new(b"backed up".to_vec())) as Box<dyn ReadSeek>))
+            .unwrap();
+
+        let status = session.remote_status("nas").unwrap();
+        assert_eq!(status.local_only, 1);
+        assert!(status.last_pushed.is_none());
+
+        session.push_all().unwrap();
+        let statuses = session.remote_statuses().unwrap();
+        assert_eq!(
+            statuses.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            vec!["drive", "nas"]
+        );
+        for status in statuses {
+            assert!(status.in_sync());
+            assert!(status.last_pushed.is_some());
+            assert!(status.last_error.is_none());
+        }
+
+        assert!(matches!(
+            session.push_remote("cloud"),
+            Err(QuocoError::RemoteNotFound(_))
+        ));
+    }
+    assert!(close_session(&id));
+}
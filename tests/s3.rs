@@ -0,0 +1,281 @@
+mod util;
+
+use crate::util::{serve_http, HttpResponse, TEST_KEY};
+use quocofs::error::QuocoError;
+use quocofs::object::{open_source_url, ObjectSource, S3Credentials, S3ObjectSource};
+use quocofs::ReadSeek;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+type Bucket = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+const BUCKET_PATH: &str = "/vaults/";
+
+/// Stand-in for an S3-compatible server that keeps objects in memory. Only supports the
+/// requests S3ObjectSource makes, and only checks that requests are signed, not the signature.
+fn start_server() -> (String, Bucket) {
+    start_server_at("")
+}
+
+/// Like [`start_server`], for a server behind a gateway that serves it under `base_path`.
+/// Objects are still keyed by their path on the server itself.
+fn start_server_at(base_path: &'static str) -> (String, Bucket) {
+    let bucket: Bucket = Arc::new(Mutex::new(HashMap::new()));
+
+    let server_bucket = bucket.clone();
+    let endpoint = serve_http(move |mut request| {
+        request.path = match request.path.strip_prefix(base_path) {
+            Some(path) => path.replace("%26", "&"),
+            None => return HttpResponse::new("404 Not Found"),
+        };
+        if !request
+            .headers
+            .get("authorization")
+            .is_some_and(|a| a.starts_with("AWS4-HMAC-SHA256 Credential=test-key/"))
+        {
+            return HttpResponse::new("403 Forbidden");
+        }
+
+        let mut objects = server_bucket.lock().unwrap();
+        if let Some(query) = request.path.strip_prefix("/vaults/?") {
+            return list_objects(&objects, query);
+        }
+        match request.method.as_str() {
+            "PUT" => match request.headers.get("x-amz-copy-source") {
+                Some(source) => match objects.get(source).cloned() {
+                    Some(data) => {
+                        objects.insert(request.path, data);
+                        HttpResponse::new("200 OK")
+                    }
+                    None => HttpResponse::new("404 Not Found"),
+                },
+                None => {
+                    objects.insert(request.path, request.body);
+                    HttpResponse::new("200 OK")
+                }
+            },
+            "DELETE" => {
+                objects.remove(&request.path);
+                HttpResponse::new("204 No Content")
+            }
+            "GET" => match objects.get(&request.path) {
+                Some(data) => HttpResponse::with_body("200 OK", data.clone()),
+                None => HttpResponse::new("404 Not Found"),
+            },
+            "HEAD" => match objects.get(&request.path) {
+                Some(data) => HttpResponse {
+                    headers: vec![
+                        ("Content-Length", data.len().to_string()),
+                        ("Last-Modified", "Wed, 21 Oct 2015 07:28:00 GMT".into()),
+                    ],
+                    ..HttpResponse::new("200 OK")
+                },
+                None => HttpResponse::new("404 Not Found"),
+            },
+            _ => HttpResponse::new("405 Method Not Allowed"),
+        }
+    });
+
+    (format!("{}{}", endpoint, base_path), bucket)
+}
+
+/// Answers a ListObjectsV2 request with every matching object on one page.
+fn list_objects(objects: &HashMap<String, Vec<u8>>, query: &str) -> HttpResponse {
+    let prefix = query
+        .split('&')
+        .find_map(|param| param.strip_prefix("prefix="))
+        .unwrap_or("")
+        .replace("%2F", "/")
+        .replace("%26", "&");
+    let contents: String = objects
+        .iter()
+        .filter_map(|(path, data)| Some((path.strip_prefix(BUCKET_PATH)?, data)))
+        .filter(|(name, _)| name.starts_with(&prefix))
+        .map(|(name, data)| {
+            format!(
+                "<Contents><Key>{}</Key><LastModified>2015-10-21T07:28:00.000Z</LastModified>\
+                 <Size>{}</Size></Contents>",
+                // Like S3, with characters escaped as numeric references
+                name.replace('&', "&#x26;"),
+                data.len()
+            )
+        })
+        .collect();
+    HttpResponse::with_body(
+        "200 OK",
+        format!(
+            "<ListBucketResult><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+            contents
+        )
+        .into_bytes(),
+    )
+}
+
+fn credentials(access_key_id: &str) -> S3Credentials {
+    S3Credentials {
+        access_key_id: access_key_id.into(),
+        secret_access_key: "test-secret".into(),
+        session_token: None,
+    }
+}
+
+fn open(endpoint: &str, prefix: &str) -> Result<S3ObjectSource, QuocoError> {
+    S3ObjectSource::open(
+        Some(endpoint),
+        "us-east-1",
+        "vaults",
+        prefix,
+        credentials("test-key"),
+        TEST_KEY,
+    )
+}
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+#[test]
+fn objects_and_names_round_trip() {
+    let (endpoint, bucket) = start_server();
+
+    let id = {
+        let mut source = open(&endpoint, "vault").unwrap();
+        let id = source.create_object(&mut reader(b"hello s3")).unwrap();
+        source.set_object_name(&id, "greeting.txt").unwrap();
+        source.flush().unwrap();
+        id
+    };
+
+    {
+        let objects = bucket.lock().unwrap();
+        assert!(objects.keys().all(|key| key.starts_with("/vaults/vault/")));
+        assert!(objects.contains_key("/vaults/vault/names"));
+        assert!(!objects.contains_key("/vaults/vault/quoco.lock"));
+    }
+
+    let mut source = open(&endpoint, "vault/").unwrap();
+    assert_eq!(
+        source.object_id_with_name("greeting.txt").unwrap(),
+        Some(&id)
+    );
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello s3");
+
+    source.delete_object(&id).unwrap();
+    assert!(!source.object_exists(&id).unwrap());
+}
+
+#[test]
+fn locked_prefix_is_rejected() {
+    let (endpoint, _bucket) = start_server();
+
+    let _source = open(&endpoint, "vault").unwrap();
+    assert!(matches!(
+        open(&endpoint, "vault"),
+        Err(QuocoError::SessionPathLocked(_))
+    ));
+    // Vaults under other prefixes are locked separately
+    open(&endpoint, "other").unwrap();
+}
+
+#[test]
+fn rejected_requests_are_errors() {
+    let (endpoint, _bucket) = start_server();
+
+    assert!(matches!(
+        S3ObjectSource::open(
+            Some(&endpoint),
+            "us-east-1",
+            "vaults",
+            "",
+            credentials("wrong-key"),
+            TEST_KEY,
+        ),
+        Err(QuocoError::S3Error(_))
+    ));
+}
+
+#[test]
+fn opens_from_url() {
+    let (endpoint, bucket) = start_server();
+
+    let url = format!(
+        "s3://test-key:test-secret@vaults/from/url?region=us-east-1&endpoint={}",
+        endpoint
+    );
+    let mut source = open_source_url(&url, TEST_KEY).unwrap();
+    source.create_object(&mut reader(b"hello url")).unwrap();
+    source.flush().unwrap();
+
+    assert!(bucket
+        .lock()
+        .unwrap()
+        .contains_key("/vaults/from/url/names"));
+    assert!(matches!(
+        open_source_url(&format!("{}&colour=blue", url), TEST_KEY),
+        Err(QuocoError::InvalidUrl(_))
+    ));
+}
+
+#[test]
+fn copies_and_listings_use_bucket_requests() {
+    let (endpoint, bucket) = start_server();
+
+    let mut source = open(&endpoint, "listed").unwrap();
+    let id = source.create_object(&mut reader(b"listed")).unwrap();
+    source.set_object_name(&id, "listed.txt").unwrap();
+    let copy = source.copy_object(&id).unwrap();
+    bucket.lock().unwrap().insert(
+        format!("/vaults/listed/{}", "07".repeat(16)),
+        b"left behind".to_vec(),
+    );
+
+    let info = source.stat(&copy).unwrap().unwrap();
+    assert_eq!(info.hash, source.object_hash(&id).unwrap().copied());
+    assert!(info.size.unwrap() > 0);
+    assert!(info.modified.is_some());
+
+    let mut infos: Vec<_> = source.list().unwrap().collect();
+    infos.sort_by_key(|info| (info.name.is_none(), info.hash.is_none()));
+    assert_eq!(infos.len(), 3);
+    assert_eq!(infos[0].name.as_deref(), Some("listed.txt"));
+    assert_eq!(infos[2].id, [7; 16]);
+    assert_eq!(infos[2].size, Some(11));
+
+    source.delete_object(&id).unwrap();
+    let mut data = Vec::new();
+    source
+        .object(&copy)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, b"listed");
+}
+
+#[test]
+fn endpoint_paths_and_escaped_keys_are_kept() {
+    let (endpoint, bucket) = start_server_at("/gateway/s3");
+
+    let mut source = open(&endpoint, "this&that").unwrap();
+    let id = source
+        .create_object(&mut reader(b"behind a gateway"))
+        .unwrap();
+    source.flush().unwrap();
+    assert!(bucket
+        .lock()
+        .unwrap()
+        .contains_key("/vaults/this&that/names"));
+
+    let infos: Vec<_> = source.list().unwrap().collect();
+    assert_eq!(infos.len(), 1);
+    assert_eq!(infos[0].id, id);
+    let mut data = Vec::new();
+    source
+        .object_range(&id, 3, 0)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert!(data.is_empty());
+}
@@ -0,0 +1,288 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{FsObjectSource, ObjectSource, RemoteSourceConfig};
+use quocofs::session::{
+    close_session, get_session, new_local_session, new_memory_session, new_remote_session, Session,
+};
+use quocofs::util::bytes_to_hex_str;
+use quocofs::ReadSeek;
+use std::io::{Cursor, Read};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn reader(data: &[u8]) -> Box<dyn ReadSeek> {
+    Box::new(Cursor::new(data.to_vec()))
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn sessions_are_send_and_sync() {
+    assert_send_sync::<Session>();
+}
+
+#[test]
+fn unknown_sessions_are_errors() {
+    assert!(matches!(
+        get_session(&[8; 16]),
+        Err(QuocoError::SessionNotFound(_))
+    ));
+
+    let id = new_memory_session(TEST_KEY, vec![]).unwrap();
+    assert!(close_session(&id));
+    assert!(matches!(
+        get_session(&id),
+        Err(QuocoError::SessionNotFound(_))
+    ));
+}
+
+#[test]
+fn sessions_are_used_from_many_threads() {
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            thread::spawn(move || {
+                let id = new_memory_session(
+                    TEST_KEY,
+                    vec![("memory".into(), RemoteSourceConfig::Memory)],
+                )
+                .unwrap();
+                let session = get_session(&id).unwrap();
+                for j in 0..20 {
+                    let mut session = session.write().unwrap();
+                    let object_id = session
+                        .source_mut()
+                        .create_object(&mut reader(format!("{} {}", i, j).as_bytes()))
+                        .unwrap();
+                    session
+                        .source_mut()
+                        .set_object_name(&object_id, &format!("{}.txt", j))
+                        .unwrap();
+                }
+                session.write().unwrap().push_all().unwrap();
+                let status = session.read().unwrap().remote_status("memory").unwrap();
+                assert!(status.in_sync());
+                assert!(close_session(&id));
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn sessions_allow_concurrent_readers() {
+    let id = new_memory_session(TEST_KEY, vec![]).unwrap();
+    {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let object_id = session
+            .source_mut()
+            .create_object(&mut reader(b"shared"))
+            .unwrap();
+        session
+            .source_mut()
+            .set_object_name(&object_id, "shared.txt")
+            .unwrap();
+    }
+
+    let session = get_session(&id).unwrap();
+    let reading = session.read().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let other_reader = thread::spawn(move || {
+        // Doesn't have to wait for the read lock held above
+        let session = get_session(&id).unwrap();
+        let session = session.read().unwrap();
+        sender
+            .send(
+                session
+                    .source()
+                    .object_id_with_name("shared.txt")
+                    .unwrap()
+                    .copied(),
+            )
+            .unwrap();
+    });
+    let found = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(
+        found.as_ref(),
+        reading.source().object_id_with_name("shared.txt").unwrap()
+    );
+    drop(reading);
+    other_reader.join().unwrap();
+    assert!(close_session(&id));
+}
+
+#[test]
+fn object_data_is_read_from_many_threads_at_once() {
+    let vault = tempdir().unwrap();
+    let object_id = {
+        let mut source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+        let object_id = source.create_object(&mut reader(b"read twice")).unwrap();
+        source.flush().unwrap();
+        object_id
+    };
+    let id = new_local_session(vault.path().to_str().unwrap(), TEST_KEY).unwrap();
+
+    let read = move || {
+        let session = get_session(&id).unwrap();
+        let session = session.read().unwrap();
+        let mut data = Vec::new();
+        session
+            .source()
+            .object(&object_id)
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        data
+    };
+
+    let session = get_session(&id).unwrap();
+    let reading = session.read().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let other_reader = thread::spawn(move || sender.send(read()).unwrap());
+    // Only arrives while the read lock above is held if reads don't need the write lock
+    let other_data = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    let mut data = Vec::new();
+    reading
+        .source()
+        .object_range(&object_id, 5, 5)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    drop(reading);
+    other_reader.join().unwrap();
+
+    assert_eq!(other_data, b"read twice");
+    assert_eq!(data, b"twice");
+    assert_eq!(read(), b"read twice");
+    assert!(close_session(&id));
+}
+
+#[test]
+fn local_only_sessions_have_no_remotes() {
+    let vault = tempdir().unwrap();
+    let id = new_local_session(vault.path().to_str().unwrap(), TEST_KEY).unwrap();
+    let object_id = {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let object_id = session
+            .source_mut()
+            .create_object(&mut reader(b"local"))
+            .unwrap();
+        assert!(session.remote_names().is_empty());
+        assert!(session.remote_statuses().unwrap().is_empty());
+        session.push_all().unwrap();
+        assert!(matches!(
+            session.push_remote("origin"),
+            Err(QuocoError::RemoteNotFound(_))
+        ));
+        session.flush().unwrap();
+        object_id
+    };
+    assert!(close_session(&id));
+
+    let source = FsObjectSource::open(vault.path(), TEST_KEY).unwrap();
+    assert!(source.object_exists(&object_id).unwrap());
+}
+
+#[test]
+fn remote_only_sessions_use_the_remote_directly() {
+    let remote = tempdir().unwrap();
+    let id = new_remote_session(
+        RemoteSourceConfig::Filesystem {
+            path: remote.path().into(),
+        },
+        TEST_KEY,
+    )
+    .unwrap();
+    let object_id = {
+        let session = get_session(&id).unwrap();
+        let mut session = session.write().unwrap();
+        let object_id = session
+            .source_mut()
+            .create_object(&mut reader(b"remote"))
+            .unwrap();
+        session
+            .source_mut()
+            .set_object_name(&object_id, "remote.txt")
+            .unwrap();
+        session.pull_all().unwrap();
+        session.flush().unwrap();
+        object_id
+    };
+    assert!(close_session(&id));
+
+    let source = FsObjectSource::open(remote.path(), TEST_KEY).unwrap();
+    assert_eq!(
+        source.object_id_with_name("remote.txt").unwrap(),
+        Some(&object_id)
+    );
+    let mut data = Vec::new();
+    source
+        .object(&object_id)
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    assert_eq!(data, b"remote");
+}
+
+#[test]
+fn metadata_json_for_missing_objects_is_rejected() {
+    let id = new_memory_session(TEST_KEY, vec![]).unwrap();
+    let session = get_session(&id).unwrap();
+    let mut session = session.write().unwrap();
+    let object_id = session
+        .source_mut()
+        .create_object(&mut reader(b"stored"))
+        .unwrap();
+    let mut json = Vec::new();
+    session.export_metadata_json(&mut json).unwrap();
+
+    let missing = format!(
+        r#"{{"version": 1, "last_updated": 0, "names": {{"{}": "missing.txt"}}, "hashes": {{}}}}"#,
+        "07".repeat(16)
+    );
+    assert!(matches!(
+        session.import_metadata_json(&mut missing.as_bytes()),
+        Err(QuocoError::JsonError(_))
+    ));
+    assert!(session.source().object_hash(&object_id).unwrap().is_some());
+
+    session.import_metadata_json(&mut json.as_slice()).unwrap();
+    drop(session);
+    assert!(close_session(&id));
+}
+
+#[test]
+fn imported_metadata_drops_left_out_objects() {
+    let vault = tempdir().unwrap();
+    let id = new_local_session(vault.path().to_str().unwrap(), TEST_KEY).unwrap();
+    let session = get_session(&id).unwrap();
+    let mut session = session.write().unwrap();
+    let kept = session
+        .source_mut()
+        .create_object(&mut reader(b"kept"))
+        .unwrap();
+    let mut json = Vec::new();
+    session.export_metadata_json(&mut json).unwrap();
+    let dropped = session
+        .source_mut()
+        .create_object(&mut reader(b"dropped"))
+        .unwrap();
+    session.flush().unwrap();
+    let blob_path = vault.path().join(bytes_to_hex_str(&dropped));
+    assert!(blob_path.exists());
+
+    session.import_metadata_json(&mut json.as_slice()).unwrap();
+    assert!(!blob_path.exists());
+    assert!(!session.source().object_exists(&dropped).unwrap());
+    assert!(session.source().object_exists(&kept).unwrap());
+    drop(session);
+    assert!(close_session(&id));
+}
@@ -0,0 +1,121 @@
+mod util;
+
+use crate::util::TEST_KEY;
+use quocofs::error::QuocoError;
+use quocofs::object::{ObjectSource, SftpObjectSource, SshCredentials};
+use quocofs::ReadSeek;
+use std::env;
+use std::io::{Cursor, Read};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+
+/// Server details for tests that need a real SSH server, read from `QUOCOFS_TEST_SFTP_*`
+/// environment variables. Those tests are skipped when `QUOCOFS_TEST_SFTP_HOST` isn't set.
+struct TestServer {
+    host: String,
+    port: u16,
+    known_hosts: PathBuf,
+    credentials: SshCredentials,
+    path: PathBuf,
+}
+
+fn test_server() -> Option<TestServer> {
+    let host = env::var("QUOCOFS_TEST_SFTP_HOST").ok()?;
+    let var = |name: &str| env::var(format!("QUOCOFS_TEST_SFTP_{}", name)).ok();
+
+    Some(TestServer {
+        host,
+        port: var("PORT").map_or(22, |port| port.parse().unwrap()),
+        known_hosts: var("KNOWN_HOSTS")
+            .expect("QUOCOFS_TEST_SFTP_KNOWN_HOSTS must be set")
+            .into(),
+        credentials: SshCredentials {
+            username: var("USER").expect("QUOCOFS_TEST_SFTP_USER must be set"),
+            private_key: var("PRIVATE_KEY").map(PathBuf::from),
+            passphrase: var("PASSPHRASE"),
+        },
+        path: var("PATH")
+            .expect("QUOCOFS_TEST_SFTP_PATH must be set")
+            .into(),
+    })
+}
+
+fn open(server: &TestServer, known_hosts: &Path) -> Result<SftpObjectSource, QuocoError> {
+    SftpObjectSource::open(
+        &server.host,
+        server.port,
+        known_hosts,
+        &server.credentials,
+        &server.path,
+        TEST_KEY,
+    )
+}
+
+#[test]
+fn objects_and_names_round_trip() {
+    let server = match test_server() {
+        Some(server) => server,
+        None => return,
+    };
+
+    let id = {
+        let mut source = open(&server, &server.known_hosts).unwrap();
+        let id = source
+            .create_object(
+                &mut (Box::new(Cursor::new(b"hello sftp".to_vec())) as Box<dyn ReadSeek>),
+            )
+            .unwrap();
+        source.set_object_name(&id, "greeting.txt").unwrap();
+        source.flush().unwrap();
+        id
+    };
+
+    let mut source = open(&server, &server.known_hosts).unwrap();
+    assert_eq!(
+        source.object_id_with_name("greeting.txt").unwrap(),
+        Some(&id)
+    );
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello sftp");
+
+    source.delete_object(&id).unwrap();
+    source.flush().unwrap();
+    assert!(!source.object_exists(&id).unwrap());
+}
+
+#[test]
+fn unknown_host_key_is_rejected() {
+    let server = match test_server() {
+        Some(server) => server,
+        None => return,
+    };
+
+    let empty_known_hosts = tempfile::NamedTempFile::new().unwrap();
+    assert!(matches!(
+        open(&server, empty_known_hosts.path()),
+        Err(QuocoError::HostKeyRejected(_))
+    ));
+}
+
+#[test]
+fn unreachable_host_is_an_error() {
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    assert!(SftpObjectSource::open(
+        "127.0.0.1",
+        port,
+        Path::new("known_hosts"),
+        &SshCredentials {
+            username: "quoco".into(),
+            private_key: None,
+            passphrase: None,
+        },
+        Path::new("vault"),
+        TEST_KEY,
+    )
+    .is_err());
+}
@@ -1,9 +1,13 @@
 use quocofs::object::Key;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
 use std::path::PathBuf;
-use std::{fs, io};
+use std::sync::Arc;
+use std::{fs, io, thread};
 
+#[allow(dead_code)]
 pub fn tests_data_dir() -> PathBuf {
     PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data")
 }
@@ -89,3 +93,114 @@ where
         assert_eq!(output_data.into_inner(), reference_data)
     }
 }
+
+#[allow(dead_code)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[allow(dead_code)]
+pub struct HttpResponse {
+    pub status: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl HttpResponse {
+    pub fn new(status: &'static str) -> Self {
+        HttpResponse {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_body(status: &'static str, body: Vec<u8>) -> Self {
+        HttpResponse {
+            body,
+            ..HttpResponse::new(status)
+        }
+    }
+}
+
+/// Starts a minimal HTTP/1.1 server on localhost that answers every request with `handler`, for
+/// standing in for remote storage services. Returns the server's base URL.
+#[allow(dead_code)]
+pub fn serve_http<F>(handler: F) -> String
+where
+    F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let handler = handler.clone();
+            thread::spawn(move || handle_http_connection(stream.unwrap(), &*handler));
+        }
+    });
+
+    url
+}
+
+fn handle_http_connection<F: Fn(HttpRequest) -> HttpResponse>(stream: TcpStream, handler: &F) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap().to_string();
+        let path = parts.next().unwrap().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_at(line.find(':').unwrap());
+            headers.insert(name.to_lowercase(), value[1..].trim().to_string());
+        }
+
+        let mut body = vec![
+            0u8;
+            headers
+                .get("content-length")
+                .map_or(0, |length| length.parse().unwrap())
+        ];
+        reader.read_exact(&mut body).unwrap();
+
+        let response = handler(HttpRequest {
+            method,
+            path,
+            headers,
+            body,
+        });
+
+        write!(stream, "HTTP/1.1 {}\r\n", response.status).unwrap();
+        for (name, value) in response.headers.iter() {
+            write!(stream, "{}: {}\r\n", name, value).unwrap();
+        }
+        // Handlers set their own length for HEAD responses, which have no body
+        if !response
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        {
+            write!(stream, "Content-Length: {}\r\n", response.body.len()).unwrap();
+        }
+        write!(stream, "\r\n").unwrap();
+        stream.write_all(&response.body).unwrap();
+    }
+}
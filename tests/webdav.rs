@@ -0,0 +1,158 @@
+mod util;
+
+use crate::util::{serve_http, HttpResponse, TEST_KEY};
+use quocofs::error::QuocoError;
+use quocofs::object::{ObjectSource, WebDavCredentials, WebDavObjectSource};
+use quocofs::ReadSeek;
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+const LOCK_TOKEN: &str = "opaquelocktoken:e71d4fae-5dec-22d6-fea5-00a0c91e6be4";
+
+#[derive(Default)]
+struct Collection {
+    files: HashMap<String, Vec<u8>>,
+    /// Path of the locked file, if any
+    locked: Option<String>,
+}
+
+/// Stand-in for a WebDAV server that keeps files in memory. Servers without `supports_lock`
+/// answer LOCK like servers without class 2 compliance.
+fn start_server(supports_lock: bool) -> (String, Arc<Mutex<Collection>>) {
+    let collection = Arc::new(Mutex::new(Collection::default()));
+
+    let server_collection = collection.clone();
+    let url = serve_http(move |request| {
+        if request.headers.get("authorization").map(String::as_str)
+            != Some("Basic cXVvY286aHVudGVyMg==")
+        {
+            return HttpResponse::new("401 Unauthorized");
+        }
+
+        let mut collection = server_collection.lock().unwrap();
+        match request.method.as_str() {
+            "PUT" => {
+                if collection.locked.as_ref() == Some(&request.path) {
+                    return HttpResponse::new("423 Locked");
+                }
+                collection.files.insert(request.path, request.body);
+                HttpResponse::new("201 Created")
+            }
+            "GET" => match collection.files.get(&request.path) {
+                Some(data) => HttpResponse::with_body("200 OK", data.clone()),
+                None => HttpResponse::new("404 Not Found"),
+            },
+            "PROPFIND" => match collection.files.get(&request.path) {
+                Some(_) => HttpResponse::new("207 Multi-Status"),
+                None => HttpResponse::new("404 Not Found"),
+            },
+            "DELETE" => {
+                if collection.locked.as_ref() == Some(&request.path) {
+                    return HttpResponse::new("423 Locked");
+                }
+                match collection.files.remove(&request.path) {
+                    Some(_) => HttpResponse::new("204 No Content"),
+                    None => HttpResponse::new("404 Not Found"),
+                }
+            }
+            "LOCK" if supports_lock => {
+                if collection.locked.is_some() {
+                    return HttpResponse::new("423 Locked");
+                }
+                collection.files.entry(request.path.clone()).or_default();
+                collection.locked = Some(request.path);
+                let mut response = HttpResponse::new("200 OK");
+                response
+                    .headers
+                    .push(("Lock-Token", format!("<{}>", LOCK_TOKEN)));
+                response
+            }
+            "UNLOCK" if supports_lock => {
+                if request.headers.get("lock-token").map(String::as_str)
+                    != Some(&format!("<{}>", LOCK_TOKEN))
+                {
+                    return HttpResponse::new("409 Conflict");
+                }
+                collection.locked = None;
+                HttpResponse::new("204 No Content")
+            }
+            _ => HttpResponse::new("405 Method Not Allowed"),
+        }
+    });
+
+    (format!("{}/dav/vault", url), collection)
+}
+
+fn open(url: &str) -> Result<WebDavObjectSource, QuocoError> {
+    WebDavObjectSource::open(
+        url,
+        WebDavCredentials {
+            username: "quoco".into(),
+            password: "hunter2".into(),
+        },
+        TEST_KEY,
+    )
+}
+
+fn round_trip(supports_lock: bool) {
+    let (url, collection) = start_server(supports_lock);
+
+    let id = {
+        let mut source = open(&url).unwrap();
+        assert!(collection
+            .lock()
+            .unwrap()
+            .files
+            .contains_key("/dav/vault/quoco.lock"));
+        assert_eq!(collection.lock().unwrap().locked.is_some(), supports_lock);
+
+        let id = source
+            .create_object(
+                &mut (Box::new(Cursor::new(b"hello webdav".to_vec())) as Box<dyn ReadSeek>),
+            )
+            .unwrap();
+        source.set_object_name(&id, "greeting.txt").unwrap();
+        source.flush().unwrap();
+        id
+    };
+
+    {
+        let collection = collection.lock().unwrap();
+        assert!(collection.locked.is_none());
+        assert!(!collection.files.contains_key("/dav/vault/quoco.lock"));
+        assert!(collection.files.contains_key("/dav/vault/names"));
+    }
+
+    let mut source = open(&url).unwrap();
+    assert_eq!(
+        source.object_id_with_name("greeting.txt").unwrap(),
+        Some(&id)
+    );
+    let mut data = Vec::new();
+    source.object(&id).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"hello webdav");
+
+    source.delete_object(&id).unwrap();
+    assert!(!source.object_exists(&id).unwrap());
+}
+
+#[test]
+fn round_trip_with_lock_support() {
+    round_trip(true);
+}
+
+#[test]
+fn round_trip_without_lock_support() {
+    round_trip(false);
+}
+
+#[test]
+fn locked_vault_is_rejected() {
+    for supports_lock in [true, false].iter() {
+        let (url, _collection) = start_server(*supports_lock);
+
+        let _source = open(&url).unwrap();
+        assert!(matches!(open(&url), Err(QuocoError::SessionPathLocked(_))));
+    }
+}